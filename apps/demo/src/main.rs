@@ -3,6 +3,7 @@ fn main() {
         Demo,
         engine::LaunchSettings {
             window_title: "Spectral Engine - Demo".to_string(),
+            ..Default::default()
         },
     );
 }
@@ -11,20 +12,150 @@ fn main() {
 pub struct Demo;
 
 impl engine::State for Demo {
+    fn on_start(
+        &mut self,
+        engine_context: &mut engine::EngineContext,
+        _renderer: &mut engine::Renderer<'_>,
+    ) {
+        engine_context.world = create_world();
+    }
+
     fn update(
         &mut self,
         engine_context: &mut engine::EngineContext,
         ui_context: &engine::egui::Context,
     ) {
         let mut messages = Vec::new();
+
+        // Dropping a world or glTF file onto the window loads it into the world
+        engine_context
+            .events
+            .engine_events()
+            .iter()
+            .for_each(|event| {
+                if let engine::EngineEvent::FileDropped { path } = event {
+                    messages.push(engine::EngineMessage::LoadScene { path: path.clone() });
+                }
+            });
+
         engine::egui::Window::new("Demo").show(ui_context, |ui| {
             ui.heading("Spectral Engine Demo App");
             if ui.button("Click me!").clicked() {
                 messages.push(engine::EngineMessage::Empty);
             }
+            if ui.button("Toggle fullscreen").clicked() {
+                messages.push(engine::EngineMessage::ToggleFullscreen);
+            }
+            if ui.button("Screenshot").clicked() {
+                messages.push(engine::EngineMessage::Screenshot {
+                    path: "screenshot.png".into(),
+                    include_ui: false,
+                });
+            }
+            if ui.button("Quit").clicked() {
+                messages.push(engine::EngineMessage::Quit);
+            }
         });
         messages.drain(..).for_each(|message| {
             engine_context.pending_messages.push(message);
         });
     }
 }
+
+fn create_world() -> engine::world::World {
+    let mut world = engine::world::World::default();
+
+    let shapes = [
+        ("triangle", engine::world::PrimitiveShape::Triangle),
+        (
+            "box",
+            engine::world::PrimitiveShape::Box(Default::default()),
+        ),
+        (
+            "capsule",
+            engine::world::PrimitiveShape::Capsule(Default::default()),
+        ),
+        (
+            "cylinder",
+            engine::world::PrimitiveShape::Cylinder(Default::default()),
+        ),
+        (
+            "sphere",
+            engine::world::PrimitiveShape::Sphere(Default::default()),
+        ),
+    ];
+
+    let mut scene = engine::world::Scene::default();
+    let viewport = scene.add_node(engine::world::Node::Viewport(
+        engine::world::Viewport::Main {
+            dimension: engine::world::ViewportDimension::default(),
+        },
+    ));
+    let camera = scene.add_node(engine::world::Node::Node3D {
+        transform: engine::world::Transform3D {
+            translation: engine::nalgebra_glm::vec3(0.0, 1.5, 8.0),
+            ..Default::default()
+        },
+        node: engine::world::Node3D::Camera3D {
+            camera: engine::world::Camera3D::default(),
+        },
+    });
+    scene.add_edge(viewport, camera);
+
+    let shape_count = shapes.len();
+    for (index, (name, shape)) in shapes.into_iter().enumerate() {
+        world.meshes.insert(
+            name.to_string(),
+            engine::world::Mesh::PrimitiveMesh(engine::world::PrimitiveMesh {
+                shape,
+                material: None,
+            }),
+        );
+        let offset = index as f32 - (shape_count as f32 - 1.0) * 0.5;
+        let node = scene.add_node(engine::world::Node::Node3D {
+            transform: engine::world::Transform3D {
+                translation: engine::nalgebra_glm::vec3(offset * 2.5, 0.0, 0.0),
+                ..Default::default()
+            },
+            node: engine::world::Node3D::Empty,
+        });
+        let mesh = scene.add_node(engine::world::Node::VisualInstance3D(
+            engine::world::VisualInstance3D::Geometry(engine::world::Geometry::MeshInstance3D(
+                engine::world::MeshInstance3D {
+                    mesh_reference: Some(name.to_string()),
+                },
+            )),
+        ));
+        scene.add_edge(node, mesh);
+    }
+
+    let floor = scene.add_node(engine::world::Node::Node3D {
+        transform: engine::world::Transform3D {
+            translation: engine::nalgebra_glm::vec3(0.0, -1.0, 0.0),
+            ..Default::default()
+        },
+        node: engine::world::Node3D::Empty,
+    });
+    world.meshes.insert(
+        "floor".to_string(),
+        engine::world::Mesh::PrimitiveMesh(engine::world::PrimitiveMesh {
+            shape: engine::world::PrimitiveShape::Plane(engine::world::PlaneShape {
+                size: engine::nalgebra_glm::vec2(16.0, 8.0),
+                subdivisions: 0,
+            }),
+            material: None,
+        }),
+    );
+    let floor_mesh = scene.add_node(engine::world::Node::VisualInstance3D(
+        engine::world::VisualInstance3D::Geometry(engine::world::Geometry::MeshInstance3D(
+            engine::world::MeshInstance3D {
+                mesh_reference: Some("floor".to_string()),
+            },
+        )),
+    ));
+    scene.add_edge(floor, floor_mesh);
+
+    world.scenes.push(scene);
+
+    world
+}
@@ -3,6 +3,7 @@ fn main() {
         Editor,
         engine::LaunchSettings {
             window_title: "Spectral Engine".to_string(),
+            ..Default::default()
         },
     );
 }
@@ -3,6 +3,7 @@ fn main() {
         App,
         engine::LaunchSettings {
             window_title: "Spectral Engine - Template".to_string(),
+            ..Default::default()
         },
     );
 }
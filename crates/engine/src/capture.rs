@@ -0,0 +1,114 @@
+// Tracks screenshot and frame sequence requests between frames and writes the results to disk
+#[derive(Default)]
+pub struct FrameCapturer {
+    screenshots: Vec<Screenshot>,
+    sequence: Option<Sequence>,
+}
+
+struct Screenshot {
+    path: std::path::PathBuf,
+    include_ui: bool,
+}
+
+struct Sequence {
+    directory: std::path::PathBuf,
+    include_ui: bool,
+    remaining: Option<u32>,
+    next_frame: u32,
+}
+
+impl FrameCapturer {
+    pub fn screenshot(&mut self, path: std::path::PathBuf, include_ui: bool) {
+        self.screenshots.push(Screenshot { path, include_ui });
+    }
+
+    pub fn start_sequence(
+        &mut self,
+        directory: std::path::PathBuf,
+        include_ui: bool,
+        frame_count: Option<u32>,
+    ) {
+        if let Err(error) = std::fs::create_dir_all(&directory) {
+            log::error!(
+                "Failed to create capture directory {}: {error}",
+                directory.display()
+            );
+            return;
+        }
+        log::info!("Capturing frames to {}", directory.display());
+        self.sequence = Some(Sequence {
+            directory,
+            include_ui,
+            remaining: frame_count,
+            next_frame: 0,
+        });
+    }
+
+    pub fn stop_sequence(&mut self) {
+        if let Some(sequence) = self.sequence.take() {
+            log::info!(
+                "Captured {} frames to {}",
+                sequence.next_frame,
+                sequence.directory.display()
+            );
+        }
+    }
+
+    pub fn request(&self) -> crate::renderer::CaptureRequest {
+        let include_ui = self
+            .screenshots
+            .iter()
+            .map(|screenshot| screenshot.include_ui)
+            .chain(self.sequence.iter().map(|sequence| sequence.include_ui));
+        let mut request = crate::renderer::CaptureRequest::default();
+        include_ui.for_each(|include_ui| match include_ui {
+            true => request.overlay = true,
+            false => request.scene = true,
+        });
+        request
+    }
+
+    pub fn finish_frame(&mut self, captured: crate::renderer::CapturedFrame) {
+        let image = |include_ui: bool| match include_ui {
+            true => captured.overlay.as_ref(),
+            false => captured.scene.as_ref(),
+        };
+
+        for screenshot in self.screenshots.drain(..) {
+            if let Some(image) = image(screenshot.include_ui) {
+                save(image, &screenshot.path);
+            }
+        }
+
+        let Some(sequence) = self.sequence.as_mut() else {
+            return;
+        };
+        if let Some(image) = image(sequence.include_ui) {
+            let path = sequence
+                .directory
+                .join(format!("frame_{:06}.png", sequence.next_frame));
+            save(image, &path);
+            sequence.next_frame += 1;
+            if let Some(remaining) = sequence.remaining.as_mut() {
+                *remaining = remaining.saturating_sub(1);
+                if *remaining == 0 {
+                    self.stop_sequence();
+                }
+            }
+        }
+    }
+}
+
+fn save(
+    image: &Result<crate::world::Image, crate::renderer::CaptureError>,
+    path: &std::path::Path,
+) {
+    let result = match image {
+        Ok(image) => image.save_png(path).map_err(|error| error.to_string()),
+        Err(error) => Err(error.to_string()),
+    };
+    match result {
+        Ok(()) => log::info!("Saved capture to {}", path.display()),
+        Err(error) => log::error!("Failed to capture {}: {error}", path.display()),
+    }
+}
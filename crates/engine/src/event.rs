@@ -0,0 +1,205 @@
+/// Something that happened inside the engine that the application may want to react to
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineEvent {
+    // The window's inner size changed, in physical pixels
+    Resized {
+        width: u32,
+        height: u32,
+    },
+    FocusChanged {
+        focused: bool,
+    },
+    FileDropped {
+        path: std::path::PathBuf,
+    },
+    // Scenes appended to the world by `EngineMessage::LoadScene`
+    SceneLoaded {
+        path: std::path::PathBuf,
+        scenes: std::ops::Range<usize>,
+    },
+    AssetFailed {
+        path: std::path::PathBuf,
+        error: String,
+    },
+    // The gpu device was lost. On native the renderer has been recreated and passed to
+    // `State::on_renderer_recreated`, so textures registered with egui and anything else
+    // created on the old device can be made again. On the web nothing is rendered after it.
+    DeviceLost {
+        reason: String,
+    },
+}
+
+/// Engine -> application event queue, including events of any type defined by the application.
+///
+/// Events are double buffered: anything sent during a frame becomes readable during the
+/// next `State::update` and is dropped after it, so every event is seen exactly once.
+/// Subscribers are called for each event at the start of the frame it becomes readable in.
+#[derive(Default)]
+pub struct Events {
+    engine: Vec<EngineEvent>,
+    pending_engine: Vec<EngineEvent>,
+    user: std::collections::HashMap<std::any::TypeId, Box<dyn EventList>>,
+    pending_user: std::collections::HashMap<std::any::TypeId, Box<dyn EventList>>,
+    subscribers: Vec<Subscriber>,
+    user_subscribers: std::collections::HashMap<std::any::TypeId, Vec<UserSubscriber>>,
+}
+
+type Subscriber = Box<dyn FnMut(&EngineEvent)>;
+type UserSubscriber = Box<dyn FnMut(&dyn std::any::Any)>;
+
+impl Events {
+    /// Engine events readable this frame
+    pub fn engine_events(&self) -> &[EngineEvent] {
+        &self.engine
+    }
+
+    /// Events of an application defined type readable this frame
+    pub fn read<T: 'static>(&self) -> &[T] {
+        self.user
+            .get(&std::any::TypeId::of::<T>())
+            .and_then(|events| events.as_any().downcast_ref::<Vec<T>>())
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Queues an application defined event, readable during the next frame
+    pub fn send<T: 'static>(&mut self, event: T) {
+        self.pending_user
+            .entry(std::any::TypeId::of::<T>())
+            .or_insert_with(|| Box::<Vec<T>>::default())
+            .as_any_mut()
+            .downcast_mut::<Vec<T>>()
+            .expect("Event lists are keyed by their element type")
+            .push(event);
+    }
+
+    pub fn subscribe(&mut self, callback: impl FnMut(&EngineEvent) + 'static) {
+        self.subscribers.push(Box::new(callback));
+    }
+
+    pub fn subscribe_to<T: 'static>(&mut self, mut callback: impl FnMut(&T) + 'static) {
+        self.user_subscribers
+            .entry(std::any::TypeId::of::<T>())
+            .or_default()
+            .push(Box::new(move |event| {
+                if let Some(event) = event.downcast_ref::<T>() {
+                    callback(event);
+                }
+            }));
+    }
+
+    pub(crate) fn push(&mut self, event: EngineEvent) {
+        self.pending_engine.push(event);
+    }
+
+    // Makes everything queued since the last call readable and notifies subscribers
+    pub(crate) fn update(&mut self) {
+        self.engine = std::mem::take(&mut self.pending_engine);
+        self.user = std::mem::take(&mut self.pending_user);
+
+        for event in self.engine.iter() {
+            self.subscribers
+                .iter_mut()
+                .for_each(|subscriber| subscriber(event));
+        }
+        for (type_id, events) in self.user.iter() {
+            if let Some(subscribers) = self.user_subscribers.get_mut(type_id) {
+                events.for_each(&mut |event| {
+                    subscribers
+                        .iter_mut()
+                        .for_each(|subscriber| subscriber(event));
+                });
+            }
+        }
+    }
+}
+
+// A type erased `Vec<T>` of application defined events
+trait EventList {
+    fn as_any(&self) -> &dyn std::any::Any;
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any;
+    fn for_each(&self, callback: &mut dyn FnMut(&dyn std::any::Any));
+}
+
+impl<T: 'static> EventList for Vec<T> {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+
+    fn for_each(&self, callback: &mut dyn FnMut(&dyn std::any::Any)) {
+        self.iter().for_each(|event| callback(event));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Scored(u32);
+
+    #[test]
+    fn events_are_readable_for_the_frame_after_they_are_sent() {
+        let mut events = Events::default();
+        events.update();
+        // Sent during the first frame
+        events.push(EngineEvent::FocusChanged { focused: true });
+        events.send(Scored(3));
+        assert!(events.engine_events().is_empty());
+        assert!(events.read::<Scored>().is_empty());
+
+        events.update();
+        assert_eq!(
+            events.engine_events(),
+            [EngineEvent::FocusChanged { focused: true }]
+        );
+        assert_eq!(events.read::<Scored>(), [Scored(3)]);
+
+        events.update();
+        assert!(events.engine_events().is_empty());
+        assert!(events.read::<Scored>().is_empty());
+    }
+
+    #[test]
+    fn events_sent_while_reading_wait_for_the_next_frame() {
+        let mut events = Events::default();
+        events.send(Scored(1));
+        events.update();
+        events.send(Scored(2));
+        assert_eq!(events.read::<Scored>(), [Scored(1)]);
+        events.update();
+        assert_eq!(events.read::<Scored>(), [Scored(2)]);
+        // Other types are kept apart
+        assert!(events.read::<u32>().is_empty());
+    }
+
+    #[test]
+    fn subscribers_see_each_event_once() {
+        let seen = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let mut events = Events::default();
+        let engine_seen = seen.clone();
+        events.subscribe(move |event| {
+            if let EngineEvent::Resized { width, .. } = event {
+                engine_seen.borrow_mut().push(*width);
+            }
+        });
+        let user_seen = seen.clone();
+        events.subscribe_to(move |Scored(score): &Scored| user_seen.borrow_mut().push(*score));
+
+        events.push(EngineEvent::Resized {
+            width: 640,
+            height: 480,
+        });
+        events.send(Scored(7));
+        events.send(Scored(8));
+        assert!(seen.borrow().is_empty());
+        events.update();
+        assert_eq!(*seen.borrow(), [640, 7, 8]);
+        events.update();
+        assert_eq!(*seen.borrow(), [640, 7, 8]);
+    }
+}
@@ -0,0 +1,863 @@
+/// Reference images checked in alongside the engine crate
+pub const REFERENCE_DIRECTORY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/golden");
+
+/// Failed comparisons write their actual and diff images here
+pub const OUTPUT_DIRECTORY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../../target/golden");
+
+#[derive(Debug)]
+pub enum GoldenError {
+    Io(std::io::Error),
+    Image(image::ImageError),
+    Readback(crate::CaptureError),
+    MissingReference {
+        path: std::path::PathBuf,
+    },
+    SizeMismatch {
+        expected: (u32, u32),
+        actual: (u32, u32),
+    },
+    Mismatch {
+        mismatched_pixels: usize,
+        total_pixels: usize,
+        max_difference: u8,
+        diff_path: std::path::PathBuf,
+    },
+}
+
+impl std::fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "Failed to access golden image: {error}"),
+            Self::Image(error) => write!(f, "Failed to encode or decode golden image: {error}"),
+            Self::Readback(error) => write!(f, "Failed to read back rendered frame: {error}"),
+            Self::MissingReference { path } => write!(
+                f,
+                "Reference image {} does not exist, run with GOLDEN_BLESS=1 to create it",
+                path.display()
+            ),
+            Self::SizeMismatch { expected, actual } => write!(
+                f,
+                "Rendered image is {}x{} but the reference is {}x{}",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+            Self::Mismatch {
+                mismatched_pixels,
+                total_pixels,
+                max_difference,
+                diff_path,
+            } => write!(
+                f,
+                "{mismatched_pixels} of {total_pixels} pixels differ (max difference {max_difference}), see {}",
+                diff_path.display()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GoldenError {}
+
+impl From<std::io::Error> for GoldenError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<image::ImageError> for GoldenError {
+    fn from(error: image::ImageError) -> Self {
+        Self::Image(error)
+    }
+}
+
+impl From<crate::CaptureError> for GoldenError {
+    fn from(error: crate::CaptureError) -> Self {
+        Self::Readback(error)
+    }
+}
+
+/// How far a rendered image may drift from its reference before a check fails.
+///
+/// Rasterizers disagree slightly on edge coverage and rounding, so a handful of pixels
+/// are allowed to differ without failing the check.
+#[derive(Debug, Copy, Clone)]
+pub struct Tolerance {
+    /// Largest perceptual difference (0-255) between two pixels that still counts as a match
+    pub max_pixel_difference: u8,
+    /// Fraction of pixels allowed to exceed `max_pixel_difference`
+    pub max_mismatched_fraction: f32,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            max_pixel_difference: 8,
+            max_mismatched_fraction: 0.005,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub mismatched_pixels: usize,
+    pub total_pixels: usize,
+    pub max_difference: u8,
+    /// Mismatched pixels in red over a dimmed copy of the reference
+    pub diff: crate::world::Image,
+}
+
+impl Comparison {
+    pub fn passes(&self, tolerance: &Tolerance) -> bool {
+        self.mismatched_pixels as f32
+            <= self.total_pixels as f32 * tolerance.max_mismatched_fraction
+    }
+}
+
+/// Compares two RGBA8 images pixel by pixel, ignoring alpha
+pub fn compare(
+    actual: &crate::world::Image,
+    expected: &crate::world::Image,
+    tolerance: &Tolerance,
+) -> Result<Comparison, GoldenError> {
+    if (actual.width, actual.height) != (expected.width, expected.height) {
+        return Err(GoldenError::SizeMismatch {
+            expected: (expected.width, expected.height),
+            actual: (actual.width, actual.height),
+        });
+    }
+
+    let mut mismatched_pixels = 0;
+    let mut max_difference = 0;
+    let mut diff_pixels = Vec::with_capacity(expected.pixels.len());
+    actual
+        .pixels
+        .chunks_exact(4)
+        .zip(expected.pixels.chunks_exact(4))
+        .for_each(|(actual, expected)| {
+            let difference = pixel_difference(actual, expected);
+            max_difference = max_difference.max(difference);
+            if difference > tolerance.max_pixel_difference {
+                mismatched_pixels += 1;
+                diff_pixels.extend_from_slice(&[255, 0, 0, 255]);
+            } else {
+                let luma = (0.299 * expected[0] as f32
+                    + 0.587 * expected[1] as f32
+                    + 0.114 * expected[2] as f32)
+                    / 3.0;
+                diff_pixels.extend_from_slice(&[luma as u8, luma as u8, luma as u8, 255]);
+            }
+        });
+
+    Ok(Comparison {
+        mismatched_pixels,
+        total_pixels: (expected.width * expected.height) as usize,
+        max_difference,
+        diff: crate::world::Image {
+            width: expected.width,
+            height: expected.height,
+            pixels: diff_pixels,
+            color_space: None,
+        },
+    })
+}
+
+/// Weighted euclidean ("redmean") color distance scaled to 0-255.
+///
+/// Cheaper than converting to a perceptual color space but tracks human sensitivity to
+/// green and to red/blue shifts far better than a plain per-channel difference.
+pub fn pixel_difference(a: &[u8], b: &[u8]) -> u8 {
+    let red_mean = (a[0] as f32 + b[0] as f32) * 0.5;
+    let dr = a[0] as f32 - b[0] as f32;
+    let dg = a[1] as f32 - b[1] as f32;
+    let db = a[2] as f32 - b[2] as f32;
+    let distance = ((2.0 + red_mean / 256.0) * dr * dr
+        + 4.0 * dg * dg
+        + (2.0 + (255.0 - red_mean) / 256.0) * db * db)
+        .sqrt();
+    // The largest possible distance is 3 * 255
+    (distance / 3.0).round().min(255.0) as u8
+}
+
+/// Renders world fixtures offscreen and checks them against reference images on disk
+pub struct GoldenHarness {
+    pub width: u32,
+    pub height: u32,
+    pub tolerance: Tolerance,
+    pub reference_directory: std::path::PathBuf,
+    pub output_directory: std::path::PathBuf,
+    /// Overwrite references with the rendered output instead of comparing
+    pub bless: bool,
+}
+
+impl Default for GoldenHarness {
+    fn default() -> Self {
+        Self {
+            width: 128,
+            height: 128,
+            tolerance: Tolerance::default(),
+            reference_directory: REFERENCE_DIRECTORY.into(),
+            output_directory: OUTPUT_DIRECTORY.into(),
+            bless: false,
+        }
+    }
+}
+
+impl GoldenHarness {
+    /// Whether this machine has the software adapter `render` needs
+    pub fn adapter_available(&self) -> bool {
+        pollster::block_on(crate::Renderer::headless_adapter_available(true))
+    }
+
+    /// Renders the world on a software adapter so output doesn't depend on the host gpu
+    pub fn render(
+        &self,
+        world: &mut crate::world::World,
+    ) -> Result<crate::world::Image, GoldenError> {
+        if let Err(error) = world.update_transforms() {
+            log::error!("{error}");
+        }
+        // A fresh renderer per fixture, since gpu meshes are cached by mesh id
+        let mut renderer =
+            pollster::block_on(crate::Renderer::new_headless(self.width, self.height, true));
+        renderer.render_world(world);
+        Ok(renderer
+            .read_pixels()
+            .expect("Headless renderers always have an offscreen target")?)
+    }
+
+    pub fn check(&self, name: &str, world: &mut crate::world::World) -> Result<(), GoldenError> {
+        let actual = self.render(world)?;
+        let reference_path = self.reference_directory.join(format!("{name}.png"));
+
+        if self.bless {
+            std::fs::create_dir_all(&self.reference_directory)?;
+            actual.save_png(&reference_path)?;
+            return Ok(());
+        }
+
+        if !reference_path.exists() {
+            return Err(GoldenError::MissingReference {
+                path: reference_path,
+            });
+        }
+        let expected = crate::world::Image::load_png(&reference_path)?;
+
+        std::fs::create_dir_all(&self.output_directory)?;
+        let actual_path = self.output_directory.join(format!("{name}.actual.png"));
+        let diff_path = self.output_directory.join(format!("{name}.diff.png"));
+
+        let comparison = match compare(&actual, &expected, &self.tolerance) {
+            Ok(comparison) => comparison,
+            Err(error) => {
+                actual.save_png(&actual_path)?;
+                return Err(error);
+            }
+        };
+        if comparison.passes(&self.tolerance) {
+            return Ok(());
+        }
+
+        actual.save_png(&actual_path)?;
+        comparison.diff.save_png(&diff_path)?;
+        Err(GoldenError::Mismatch {
+            mismatched_pixels: comparison.mismatched_pixels,
+            total_pixels: comparison.total_pixels,
+            max_difference: comparison.max_difference,
+            diff_path,
+        })
+    }
+}
+
+/// Fixtures covering every primitive shape, both projections and depth testing
+pub fn reference_scenes() -> Vec<(String, crate::world::World)> {
+    let shapes = [
+        ("triangle", crate::world::PrimitiveShape::Triangle),
+        ("box", crate::world::PrimitiveShape::Box(Default::default())),
+        (
+            "capsule",
+            crate::world::PrimitiveShape::Capsule(Default::default()),
+        ),
+        (
+            "cylinder",
+            crate::world::PrimitiveShape::Cylinder(Default::default()),
+        ),
+        (
+            "plane",
+            crate::world::PrimitiveShape::Plane(Default::default()),
+        ),
+        ("point", crate::world::PrimitiveShape::Point),
+        (
+            "sphere",
+            crate::world::PrimitiveShape::Sphere(Default::default()),
+        ),
+    ];
+
+    let projections = [
+        (
+            "perspective",
+            crate::world::Projection::Perspective(crate::world::PerspectiveCamera {
+                y_fov_rad: 60_f32.to_radians(),
+                ..Default::default()
+            }),
+        ),
+        (
+            "orthographic",
+            crate::world::Projection::Orthographic(crate::world::OrthographicCamera {
+                x_mag: 1.5,
+                y_mag: 1.5,
+                z_far: 100.0,
+                z_near: 0.01,
+            }),
+        ),
+    ];
+
+    let mut scenes = Vec::new();
+    for (projection_name, projection) in projections.iter() {
+        for (shape_name, shape) in shapes.iter() {
+            let mut world = fixture_world(projection.clone());
+            add_shape(
+                &mut world,
+                shape_name,
+                shape.clone(),
+                crate::world::Transform3D::default(),
+                None,
+            );
+            scenes.push((format!("{shape_name}_{projection_name}"), world));
+        }
+    }
+
+    // The far box is added last so it is drawn over the near one unless depth testing works
+    let mut world = fixture_world(projections[0].1.clone());
+    add_shape(
+        &mut world,
+        "near",
+        crate::world::PrimitiveShape::Box(Default::default()),
+        crate::world::Transform3D {
+            translation: nalgebra_glm::vec3(-0.4, 0.0, 0.5),
+            ..Default::default()
+        },
+        None,
+    );
+    add_shape(
+        &mut world,
+        "far",
+        crate::world::PrimitiveShape::Box(crate::world::BoxShape {
+            size: nalgebra_glm::vec3(2.0, 2.0, 2.0),
+            ..Default::default()
+        }),
+        crate::world::Transform3D {
+            translation: nalgebra_glm::vec3(0.6, 0.0, -1.5),
+            ..Default::default()
+        },
+        None,
+    );
+    scenes.push(("depth_test".to_string(), world));
+
+    scenes.extend(material_scenes(projections[0].1.clone()));
+    scenes.extend(light_scenes(projections[0].1.clone()));
+    scenes.extend(shadow_scenes(projections[0].1.clone()));
+    scenes
+}
+
+fn material_scenes(projection: crate::world::Projection) -> Vec<(String, crate::world::World)> {
+    let sphere = || crate::world::PrimitiveShape::Sphere(Default::default());
+    let at = |x: f32, y: f32, z: f32| crate::world::Transform3D {
+        translation: nalgebra_glm::vec3(x, y, z),
+        scale: nalgebra_glm::vec3(0.6, 0.6, 0.6),
+        ..Default::default()
+    };
+    let mut scenes = Vec::new();
+
+    let mut world = fixture_world(projection.clone());
+    world
+        .textures
+        .insert("checker".to_string(), checker_texture(false));
+    add_material(
+        &mut world,
+        "flat",
+        crate::world::Material {
+            base_color_factor: [1.0, 0.4, 0.1, 1.0],
+            unlit: true,
+            ..Default::default()
+        },
+    );
+    add_material(
+        &mut world,
+        "textured",
+        crate::world::Material {
+            base_color_texture: Some(texture_reference("checker")),
+            unlit: true,
+            ..Default::default()
+        },
+    );
+    add_shape(
+        &mut world,
+        "flat",
+        sphere(),
+        at(-0.6, 0.0, 0.0),
+        Some("flat"),
+    );
+    add_shape(
+        &mut world,
+        "textured",
+        crate::world::PrimitiveShape::Box(Default::default()),
+        at(0.6, 0.0, 0.0),
+        Some("textured"),
+    );
+    scenes.push(("material_unlit".to_string(), world));
+
+    let mut world = fixture_world(projection.clone());
+    let pbr = [
+        ("rough_dielectric", [0.8, 0.1, 0.1], 0.0, 0.9, [0.0; 3]),
+        ("smooth_metal", [1.0, 0.8, 0.3], 1.0, 0.3, [0.0; 3]),
+        ("emissive", [0.1, 0.1, 0.1], 0.0, 0.5, [0.1, 0.4, 1.0]),
+    ];
+    for (index, (name, color, metallic, roughness, emissive)) in pbr.into_iter().enumerate() {
+        add_material(
+            &mut world,
+            name,
+            crate::world::Material {
+                base_color_factor: [color[0], color[1], color[2], 1.0],
+                metallic_factor: metallic,
+                roughness_factor: roughness,
+                emissive_factor: emissive,
+                ..Default::default()
+            },
+        );
+        let x = (index as f32 - 1.0) * 1.1;
+        add_shape(&mut world, name, sphere(), at(x, 0.0, 0.0), Some(name));
+    }
+    scenes.push(("material_pbr".to_string(), world));
+
+    // The cutout box and translucent sphere both have to show the opaque box behind them
+    let mut world = fixture_world(projection.clone());
+    world
+        .textures
+        .insert("holes".to_string(), checker_texture(true));
+    add_material(
+        &mut world,
+        "opaque",
+        crate::world::Material {
+            base_color_factor: [0.2, 0.6, 0.2, 1.0],
+            metallic_factor: 0.0,
+            ..Default::default()
+        },
+    );
+    add_material(
+        &mut world,
+        "mask",
+        crate::world::Material {
+            base_color_texture: Some(texture_reference("holes")),
+            metallic_factor: 0.0,
+            alpha_mode: crate::world::AlphaMode::Mask { cutoff: 0.5 },
+            ..Default::default()
+        },
+    );
+    add_material(
+        &mut world,
+        "blend",
+        crate::world::Material {
+            base_color_factor: [0.2, 0.3, 1.0, 0.4],
+            metallic_factor: 0.0,
+            alpha_mode: crate::world::AlphaMode::Blend,
+            ..Default::default()
+        },
+    );
+    add_shape(
+        &mut world,
+        "background",
+        crate::world::PrimitiveShape::Box(crate::world::BoxShape {
+            size: nalgebra_glm::vec3(3.0, 1.0, 0.2),
+            ..Default::default()
+        }),
+        crate::world::Transform3D {
+            translation: nalgebra_glm::vec3(0.0, 0.0, -1.0),
+            ..Default::default()
+        },
+        Some("opaque"),
+    );
+    add_shape(
+        &mut world,
+        "cutout",
+        crate::world::PrimitiveShape::Box(Default::default()),
+        at(-0.6, 0.0, 0.3),
+        Some("mask"),
+    );
+    add_shape(
+        &mut world,
+        "translucent",
+        sphere(),
+        at(0.6, 0.0, 0.3),
+        Some("blend"),
+    );
+    scenes.push(("material_alpha".to_string(), world));
+
+    // Both planes are flipped to face away from the camera, only the double-sided one shows
+    let mut world = fixture_world(projection);
+    for (name, double_sided, x) in [("single_sided", false, -0.6), ("double_sided", true, 0.6)] {
+        add_material(
+            &mut world,
+            name,
+            crate::world::Material {
+                base_color_factor: [0.9, 0.9, 0.9, 1.0],
+                metallic_factor: 0.0,
+                double_sided,
+                ..Default::default()
+            },
+        );
+        add_shape(
+            &mut world,
+            name,
+            crate::world::PrimitiveShape::Plane(crate::world::PlaneShape {
+                size: nalgebra_glm::vec2(1.0, 1.0),
+                ..Default::default()
+            }),
+            crate::world::Transform3D {
+                translation: nalgebra_glm::vec3(x, 0.0, 0.0),
+                rotation: nalgebra_glm::quat_angle_axis(
+                    std::f32::consts::PI,
+                    &nalgebra_glm::vec3(0.0, 0.0, 1.0),
+                ),
+                ..Default::default()
+            },
+            Some(name),
+        );
+    }
+    scenes.push(("material_double_sided".to_string(), world));
+
+    // A white sun shining down from above and in front of the camera
+    let sun_direction = -nalgebra_glm::normalize(&nalgebra_glm::vec3(0.4, 0.8, 0.45));
+    for (_, world) in scenes.iter_mut() {
+        add_light(
+            world,
+            crate::world::Transform3D {
+                rotation: nalgebra_glm::quat_rotation(&-nalgebra_glm::Vec3::z(), &sun_direction),
+                ..Default::default()
+            },
+            crate::world::Node3D::DirectionalLight3D {
+                light: crate::world::DirectionalLight3D {
+                    intensity: 3.0,
+                    ..Default::default()
+                },
+            },
+        );
+    }
+    scenes
+}
+
+// Omni and spot lights over a floor, without a sun so only their light shows
+fn light_scenes(projection: crate::world::Projection) -> Vec<(String, crate::world::World)> {
+    let floor = |world: &mut crate::world::World| {
+        add_material(
+            world,
+            "floor",
+            crate::world::Material {
+                base_color_factor: [0.8, 0.8, 0.8, 1.0],
+                metallic_factor: 0.0,
+                roughness_factor: 0.8,
+                ..Default::default()
+            },
+        );
+        add_shape(
+            world,
+            "floor",
+            crate::world::PrimitiveShape::Plane(crate::world::PlaneShape {
+                size: nalgebra_glm::vec2(6.0, 6.0),
+                ..Default::default()
+            }),
+            crate::world::Transform3D {
+                translation: nalgebra_glm::vec3(0.0, -0.5, 0.0),
+                ..Default::default()
+            },
+            Some("floor"),
+        );
+    };
+    let at = |x: f32, y: f32, z: f32| crate::world::Transform3D {
+        translation: nalgebra_glm::vec3(x, y, z),
+        ..Default::default()
+    };
+    let mut scenes = Vec::new();
+
+    let mut world = fixture_world(projection.clone());
+    floor(&mut world);
+    add_shape(
+        &mut world,
+        "sphere",
+        crate::world::PrimitiveShape::Sphere(Default::default()),
+        crate::world::Transform3D {
+            scale: nalgebra_glm::vec3(0.5, 0.5, 0.5),
+            ..at(0.0, -0.25, 0.0)
+        },
+        Some("floor"),
+    );
+    add_light(
+        &mut world,
+        at(-0.9, 0.0, 0.3),
+        crate::world::Node3D::OmniLight3D {
+            light: crate::world::OmniLight3D {
+                color: [1.0, 0.3, 0.2],
+                intensity: 1.5,
+                range: 1.5,
+            },
+        },
+    );
+    add_light(
+        &mut world,
+        crate::world::Transform3D {
+            rotation: nalgebra_glm::quat_angle_axis(
+                -std::f32::consts::FRAC_PI_2,
+                &nalgebra_glm::vec3(1.0, 0.0, 0.0),
+            ),
+            ..at(0.9, 1.0, 0.0)
+        },
+        crate::world::Node3D::SpotLight3D {
+            light: crate::world::SpotLight3D {
+                color: [0.3, 0.8, 1.0],
+                intensity: 6.0,
+                range: 3.0,
+                inner_cone_angle_rad: 15_f32.to_radians(),
+                outer_cone_angle_rad: 25_f32.to_radians(),
+                shadow: None,
+            },
+        },
+    );
+    scenes.push(("lights_omni_spot".to_string(), world));
+
+    // Enough small lights that each cluster only sees a few, gaps or seams along tile edges
+    // mean lights were binned into the wrong clusters
+    let mut world = fixture_world(projection);
+    floor(&mut world);
+    for row in 0..8 {
+        for column in 0..8 {
+            let hue = (row * 8 + column) as f32 / 64.0 * std::f32::consts::TAU;
+            add_light(
+                &mut world,
+                at(column as f32 * 0.5 - 1.75, -0.4, row as f32 * 0.5 - 2.5),
+                crate::world::Node3D::OmniLight3D {
+                    light: crate::world::OmniLight3D {
+                        color: [
+                            0.5 + 0.5 * hue.cos(),
+                            0.5 + 0.5 * (hue + 2.1).cos(),
+                            0.5 + 0.5 * (hue + 4.2).cos(),
+                        ],
+                        intensity: 0.3,
+                        range: 0.5,
+                    },
+                },
+            );
+        }
+    }
+    scenes.push(("lights_clustered".to_string(), world));
+
+    scenes
+}
+
+// Casters over a large floor. Acne shows as stripes on lit surfaces, too much bias as
+// shadows detached from their casters and badly fit cascades as blocky or missing shadows.
+fn shadow_scenes(projection: crate::world::Projection) -> Vec<(String, crate::world::World)> {
+    let casters = |world: &mut crate::world::World| {
+        add_material(
+            world,
+            "matte",
+            crate::world::Material {
+                base_color_factor: [0.8, 0.8, 0.8, 1.0],
+                metallic_factor: 0.0,
+                roughness_factor: 0.9,
+                ..Default::default()
+            },
+        );
+        add_shape(
+            world,
+            "floor",
+            crate::world::PrimitiveShape::Plane(crate::world::PlaneShape {
+                size: nalgebra_glm::vec2(40.0, 40.0),
+                ..Default::default()
+            }),
+            crate::world::Transform3D {
+                translation: nalgebra_glm::vec3(0.0, -0.5, 0.0),
+                ..Default::default()
+            },
+            Some("matte"),
+        );
+        add_shape(
+            world,
+            "box",
+            crate::world::PrimitiveShape::Box(Default::default()),
+            crate::world::Transform3D {
+                translation: nalgebra_glm::vec3(-0.7, -0.2, 0.2),
+                rotation: nalgebra_glm::quat_angle_axis(0.5, &nalgebra_glm::Vec3::y()),
+                scale: nalgebra_glm::vec3(0.6, 0.6, 0.6),
+            },
+            Some("matte"),
+        );
+        add_shape(
+            world,
+            "sphere",
+            crate::world::PrimitiveShape::Sphere(Default::default()),
+            crate::world::Transform3D {
+                translation: nalgebra_glm::vec3(0.6, -0.1, -0.2),
+                scale: nalgebra_glm::vec3(0.8, 0.8, 0.8),
+                ..Default::default()
+            },
+            Some("matte"),
+        );
+        // Far enough away to land in a later cascade
+        add_shape(
+            world,
+            "pillar",
+            crate::world::PrimitiveShape::Cylinder(Default::default()),
+            crate::world::Transform3D {
+                translation: nalgebra_glm::vec3(1.5, 0.5, -6.0),
+                ..Default::default()
+            },
+            Some("matte"),
+        );
+    };
+    let mut scenes = Vec::new();
+
+    let mut world = fixture_world(projection.clone());
+    casters(&mut world);
+    let sun_direction = nalgebra_glm::normalize(&nalgebra_glm::vec3(0.6, -0.7, -0.4));
+    add_light(
+        &mut world,
+        crate::world::Transform3D {
+            rotation: nalgebra_glm::quat_rotation(&-nalgebra_glm::Vec3::z(), &sun_direction),
+            ..Default::default()
+        },
+        crate::world::Node3D::DirectionalLight3D {
+            light: crate::world::DirectionalLight3D {
+                intensity: 3.0,
+                shadow: Some(Default::default()),
+                ..Default::default()
+            },
+        },
+    );
+    scenes.push(("shadows_directional".to_string(), world));
+
+    let mut world = fixture_world(projection);
+    casters(&mut world);
+    add_light(
+        &mut world,
+        crate::world::Transform3D {
+            translation: nalgebra_glm::vec3(-1.2, 2.0, 1.2),
+            rotation: nalgebra_glm::quat_rotation(
+                &-nalgebra_glm::Vec3::z(),
+                &nalgebra_glm::normalize(&nalgebra_glm::vec3(0.5, -1.0, -0.5)),
+            ),
+            ..Default::default()
+        },
+        crate::world::Node3D::SpotLight3D {
+            light: crate::world::SpotLight3D {
+                color: [1.0, 0.9, 0.7],
+                intensity: 12.0,
+                range: 8.0,
+                inner_cone_angle_rad: 25_f32.to_radians(),
+                outer_cone_angle_rad: 40_f32.to_radians(),
+                shadow: Some(Default::default()),
+            },
+        },
+    );
+    scenes.push(("shadows_spot".to_string(), world));
+
+    scenes
+}
+
+fn add_material(world: &mut crate::world::World, name: &str, material: crate::world::Material) {
+    world.materials.insert(name.to_string(), material);
+}
+
+fn texture_reference(texture: &str) -> crate::world::TextureReference {
+    crate::world::TextureReference {
+        texture: texture.to_string(),
+        uv_set: 0,
+    }
+}
+
+// 4x4 checkerboard, with transparent instead of dark squares for `cutout`
+fn checker_texture(cutout: bool) -> crate::world::Texture {
+    let mut pixels = Vec::new();
+    for y in 0..4 {
+        for x in 0..4 {
+            pixels.extend_from_slice(match ((x + y) % 2 == 0, cutout) {
+                (true, _) => &[230, 230, 230, 255],
+                (false, false) => &[40, 40, 120, 255],
+                (false, true) => &[0, 0, 0, 0],
+            });
+        }
+    }
+    crate::world::Texture {
+        image: crate::world::Image {
+            width: 4,
+            height: 4,
+            pixels,
+            color_space: None,
+        },
+        sampler: crate::world::Sampler {
+            mag_filter: crate::world::Filter::Nearest,
+            min_filter: crate::world::Filter::Nearest,
+            ..Default::default()
+        },
+    }
+}
+
+// A main viewport with a camera looking down at the origin from slightly above
+fn fixture_world(projection: crate::world::Projection) -> crate::world::World {
+    let mut scene = crate::world::Scene::default();
+    let viewport = scene.add_node(crate::world::Node::Viewport(crate::world::Viewport::Main {
+        dimension: crate::world::ViewportDimension::default(),
+    }));
+    let pitch = -(1.5_f32 / 3.0).atan();
+    let camera = scene.add_node(crate::world::Node::Node3D {
+        transform: crate::world::Transform3D {
+            translation: nalgebra_glm::vec3(0.0, 1.5, 3.0),
+            rotation: nalgebra_glm::quat_angle_axis(pitch, &nalgebra_glm::vec3(1.0, 0.0, 0.0)),
+            ..Default::default()
+        },
+        node: crate::world::Node3D::Camera3D {
+            camera: crate::world::Camera3D { projection },
+        },
+    });
+    scene.add_edge(viewport, camera);
+
+    let mut world = crate::world::World::default();
+    world.scenes.push(scene);
+    world
+}
+
+fn add_light(
+    world: &mut crate::world::World,
+    transform: crate::world::Transform3D,
+    light: crate::world::Node3D,
+) {
+    world.scenes[0].add_node(crate::world::Node::Node3D {
+        transform,
+        node: light,
+    });
+}
+
+fn add_shape(
+    world: &mut crate::world::World,
+    name: &str,
+    shape: crate::world::PrimitiveShape,
+    transform: crate::world::Transform3D,
+    material: Option<&str>,
+) {
+    world.meshes.insert(
+        name.to_string(),
+        crate::world::Mesh::PrimitiveMesh(crate::world::PrimitiveMesh {
+            shape,
+            material: material.map(str::to_string),
+        }),
+    );
+    let scene = &mut world.scenes[0];
+    let node = scene.add_node(crate::world::Node::Node3D {
+        transform,
+        node: crate::world::Node3D::Empty,
+    });
+    let mesh = scene.add_node(crate::world::Node::VisualInstance3D(
+        crate::world::VisualInstance3D::Geometry(crate::world::Geometry::MeshInstance3D(
+            crate::world::MeshInstance3D {
+                mesh_reference: Some(name.to_string()),
+            },
+        )),
+    ));
+    scene.add_edge(node, mesh);
+}
@@ -0,0 +1,644 @@
+#[derive(Debug)]
+pub enum ImportError {
+    Gltf(gltf::Error),
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Gltf(error) => write!(f, "Failed to import glTF file: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Gltf(error) => Some(error),
+        }
+    }
+}
+
+impl From<gltf::Error> for ImportError {
+    fn from(error: gltf::Error) -> Self {
+        Self::Gltf(error)
+    }
+}
+
+/// Imports a `.gltf` or `.glb` file, resolving external buffers and images relative to it.
+///
+/// Every glTF scene becomes a `Scene` in the returned world, in document order.
+pub fn import_gltf(path: impl AsRef<std::path::Path>) -> Result<crate::world::World, ImportError> {
+    let (document, buffers, images) = gltf::import(path)?;
+    Ok(GltfImporter::new(&document, &buffers, &images).import())
+}
+
+/// Imports a glTF document from memory. Only embedded or data URI resources can be resolved.
+pub fn import_gltf_slice(bytes: &[u8]) -> Result<crate::world::World, ImportError> {
+    let (document, buffers, images) = gltf::import_slice(bytes)?;
+    Ok(GltfImporter::new(&document, &buffers, &images).import())
+}
+
+struct GltfImporter<'a> {
+    document: &'a gltf::Document,
+    buffers: &'a [gltf::buffer::Data],
+    images: &'a [gltf::image::Data],
+    world: crate::world::World,
+    mesh_ids: Vec<crate::world::MeshId>,
+    material_ids: Vec<crate::world::MaterialId>,
+    texture_ids: Vec<crate::world::TextureId>,
+}
+
+impl<'a> GltfImporter<'a> {
+    fn new(
+        document: &'a gltf::Document,
+        buffers: &'a [gltf::buffer::Data],
+        images: &'a [gltf::image::Data],
+    ) -> Self {
+        Self {
+            document,
+            buffers,
+            images,
+            world: crate::world::World::default(),
+            mesh_ids: Vec::new(),
+            material_ids: Vec::new(),
+            texture_ids: Vec::new(),
+        }
+    }
+
+    fn import(mut self) -> crate::world::World {
+        self.import_textures();
+        self.import_materials();
+        self.import_meshes();
+        for scene in self.document.scenes() {
+            let mut graph = crate::world::Scene::default();
+            for node in scene.nodes() {
+                self.import_node(&mut graph, &node);
+            }
+            self.world.scenes.push(graph);
+        }
+        self.world
+    }
+
+    fn import_textures(&mut self) {
+        for texture in self.document.textures() {
+            let id = unique_id(
+                &self.world.textures,
+                texture.name().or(texture.source().name()),
+                "texture",
+                texture.index(),
+            );
+            let image = self
+                .images
+                .get(texture.source().index())
+                .map(convert_image)
+                .unwrap_or_default();
+            self.world.textures.insert(
+                id.clone(),
+                crate::world::Texture {
+                    image,
+                    sampler: convert_sampler(&texture.sampler()),
+                },
+            );
+            self.texture_ids.push(id);
+        }
+    }
+
+    fn import_materials(&mut self) {
+        for material in self.document.materials() {
+            let Some(index) = material.index() else {
+                continue;
+            };
+            let id = unique_id(&self.world.materials, material.name(), "material", index);
+            let pbr = material.pbr_metallic_roughness();
+            let converted = crate::world::Material {
+                base_color_factor: pbr.base_color_factor(),
+                base_color_texture: pbr
+                    .base_color_texture()
+                    .map(|info| self.texture_reference(info.texture(), info.tex_coord())),
+                metallic_factor: pbr.metallic_factor(),
+                roughness_factor: pbr.roughness_factor(),
+                metallic_roughness_texture: pbr
+                    .metallic_roughness_texture()
+                    .map(|info| self.texture_reference(info.texture(), info.tex_coord())),
+                normal_texture: material
+                    .normal_texture()
+                    .map(|normal| self.texture_reference(normal.texture(), normal.tex_coord())),
+                normal_scale: material
+                    .normal_texture()
+                    .map(|normal| normal.scale())
+                    .unwrap_or(1.0),
+                occlusion_texture: material.occlusion_texture().map(|occlusion| {
+                    self.texture_reference(occlusion.texture(), occlusion.tex_coord())
+                }),
+                occlusion_strength: material
+                    .occlusion_texture()
+                    .map(|occlusion| occlusion.strength())
+                    .unwrap_or(1.0),
+                emissive_factor: material.emissive_factor(),
+                emissive_texture: material
+                    .emissive_texture()
+                    .map(|info| self.texture_reference(info.texture(), info.tex_coord())),
+                alpha_mode: match material.alpha_mode() {
+                    gltf::material::AlphaMode::Opaque => crate::world::AlphaMode::Opaque,
+                    gltf::material::AlphaMode::Mask => crate::world::AlphaMode::Mask {
+                        cutoff: material.alpha_cutoff().unwrap_or(0.5),
+                    },
+                    gltf::material::AlphaMode::Blend => crate::world::AlphaMode::Blend,
+                },
+                double_sided: material.double_sided(),
+                unlit: material.unlit(),
+            };
+            self.world.materials.insert(id.clone(), converted);
+            self.material_ids.push(id);
+        }
+    }
+
+    fn texture_reference(
+        &self,
+        texture: gltf::Texture,
+        uv_set: u32,
+    ) -> crate::world::TextureReference {
+        crate::world::TextureReference {
+            texture: self.texture_ids[texture.index()].clone(),
+            uv_set,
+        }
+    }
+
+    fn import_meshes(&mut self) {
+        for mesh in self.document.meshes() {
+            let id = unique_id(&self.world.meshes, mesh.name(), "mesh", mesh.index());
+            let surfaces = mesh
+                .primitives()
+                .map(|primitive| self.import_primitive(&primitive))
+                .collect();
+            self.world.meshes.insert(
+                id.clone(),
+                crate::world::Mesh::ArrayMesh(crate::world::ArrayMesh { surfaces }),
+            );
+            self.mesh_ids.push(id);
+        }
+    }
+
+    fn import_primitive(&self, primitive: &gltf::Primitive) -> crate::world::Surface {
+        let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+
+        let attributes = crate::world::VertexAttributes {
+            positions: reader
+                .read_positions()
+                .map(|positions| positions.collect())
+                .unwrap_or_default(),
+            normals: reader.read_normals().map(|normals| normals.collect()),
+            tangents: reader.read_tangents().map(|tangents| tangents.collect()),
+            uv0: reader
+                .read_tex_coords(0)
+                .map(|uvs| uvs.into_f32().collect()),
+            uv1: reader
+                .read_tex_coords(1)
+                .map(|uvs| uvs.into_f32().collect()),
+            colors: reader
+                .read_colors(0)
+                .map(|colors| colors.into_rgba_f32().collect()),
+            joints: reader
+                .read_joints(0)
+                .map(|joints| joints.into_u16().collect()),
+            weights: reader
+                .read_weights(0)
+                .map(|weights| weights.into_f32().collect()),
+        };
+
+        let indices = reader.read_indices().map(|indices| match indices {
+            gltf::mesh::util::ReadIndices::U8(indices) => {
+                crate::world::Indices::U16(indices.map(u16::from).collect())
+            }
+            gltf::mesh::util::ReadIndices::U16(indices) => {
+                crate::world::Indices::U16(indices.collect())
+            }
+            gltf::mesh::util::ReadIndices::U32(indices) => {
+                crate::world::Indices::U32(indices.collect())
+            }
+        });
+
+        let (topology, indices) =
+            convert_topology(primitive.mode(), indices, attributes.vertex_count());
+
+        crate::world::Surface {
+            attributes,
+            indices,
+            topology,
+            material: primitive
+                .material()
+                .index()
+                .map(|index| self.material_ids[index].clone()),
+        }
+    }
+
+    fn import_node(
+        &self,
+        graph: &mut crate::world::Scene,
+        node: &gltf::Node,
+    ) -> petgraph::graph::NodeIndex {
+        let (translation, rotation, scale) = node.transform().decomposed();
+        let node3d = match node.camera() {
+            Some(camera) => crate::world::Node3D::Camera3D {
+                camera: convert_camera(&camera),
+            },
+            None => crate::world::Node3D::Empty,
+        };
+        let node_index = graph.add_node(crate::world::Node::Node3D {
+            transform: crate::world::Transform3D::from((translation, rotation, scale)),
+            node: node3d,
+        });
+
+        if let Some(mesh) = node.mesh() {
+            let mesh_instance = graph.add_node(crate::world::Node::VisualInstance3D(
+                crate::world::VisualInstance3D::Geometry(crate::world::Geometry::MeshInstance3D(
+                    crate::world::MeshInstance3D {
+                        mesh_reference: Some(self.mesh_ids[mesh.index()].clone()),
+                    },
+                )),
+            ));
+            graph.add_edge(node_index, mesh_instance);
+        }
+
+        for child in node.children() {
+            let child_index = self.import_node(graph, &child);
+            graph.add_edge(node_index, child_index);
+        }
+
+        node_index
+    }
+}
+
+// Prefers the glTF name, falling back to the element index when unnamed or already taken
+fn unique_id<T>(
+    registry: &std::collections::HashMap<String, T>,
+    name: Option<&str>,
+    prefix: &str,
+    index: usize,
+) -> String {
+    match name {
+        Some(name) if !name.is_empty() && !registry.contains_key(name) => name.to_string(),
+        Some(name) if !name.is_empty() => format!("{name}_{index}"),
+        _ => format!("{prefix}_{index}"),
+    }
+}
+
+fn convert_camera(camera: &gltf::Camera) -> crate::world::Camera3D {
+    let projection = match camera.projection() {
+        gltf::camera::Projection::Perspective(perspective) => {
+            crate::world::Projection::Perspective(crate::world::PerspectiveCamera {
+                aspect_ratio: perspective.aspect_ratio(),
+                y_fov_rad: perspective.yfov(),
+                z_far: perspective.zfar(),
+                z_near: perspective.znear(),
+            })
+        }
+        gltf::camera::Projection::Orthographic(orthographic) => {
+            crate::world::Projection::Orthographic(crate::world::OrthographicCamera {
+                x_mag: orthographic.xmag(),
+                y_mag: orthographic.ymag(),
+                z_far: orthographic.zfar(),
+                z_near: orthographic.znear(),
+            })
+        }
+    };
+    crate::world::Camera3D { projection }
+}
+
+fn convert_sampler(sampler: &gltf::texture::Sampler) -> crate::world::Sampler {
+    let convert_wrap = |wrap: gltf::texture::WrappingMode| match wrap {
+        gltf::texture::WrappingMode::ClampToEdge => crate::world::WrapMode::ClampToEdge,
+        gltf::texture::WrappingMode::MirroredRepeat => crate::world::WrapMode::MirroredRepeat,
+        gltf::texture::WrappingMode::Repeat => crate::world::WrapMode::Repeat,
+    };
+    let (min_filter, mipmap_filter) = match sampler.min_filter() {
+        Some(gltf::texture::MinFilter::Nearest)
+        | Some(gltf::texture::MinFilter::NearestMipmapNearest) => {
+            (crate::world::Filter::Nearest, crate::world::Filter::Nearest)
+        }
+        Some(gltf::texture::MinFilter::NearestMipmapLinear) => {
+            (crate::world::Filter::Nearest, crate::world::Filter::Linear)
+        }
+        Some(gltf::texture::MinFilter::LinearMipmapNearest) => {
+            (crate::world::Filter::Linear, crate::world::Filter::Nearest)
+        }
+        _ => (crate::world::Filter::Linear, crate::world::Filter::Linear),
+    };
+    crate::world::Sampler {
+        mag_filter: match sampler.mag_filter() {
+            Some(gltf::texture::MagFilter::Nearest) => crate::world::Filter::Nearest,
+            _ => crate::world::Filter::Linear,
+        },
+        min_filter,
+        mipmap_filter,
+        wrap_u: convert_wrap(sampler.wrap_s()),
+        wrap_v: convert_wrap(sampler.wrap_t()),
+    }
+}
+
+// Expands every glTF pixel format to RGBA8, keeping the most significant bits of wider channels
+fn convert_image(image: &gltf::image::Data) -> crate::world::Image {
+    let channels = |bytes_per_channel: usize, channel_count: usize| {
+        image
+            .pixels
+            .chunks_exact(bytes_per_channel * channel_count)
+            .flat_map(|pixel| {
+                let mut rgba = [0, 0, 0, 255];
+                for (channel, value) in pixel.chunks_exact(bytes_per_channel).enumerate() {
+                    rgba[channel] = match bytes_per_channel {
+                        1 => value[0],
+                        2 => value[1],
+                        _ => {
+                            let float =
+                                f32::from_le_bytes([value[0], value[1], value[2], value[3]]);
+                            (float.clamp(0.0, 1.0) * 255.0).round() as u8
+                        }
+                    };
+                }
+                // Single channel images are treated as grayscale
+                if channel_count == 1 {
+                    rgba[1] = rgba[0];
+                    rgba[2] = rgba[0];
+                }
+                rgba
+            })
+            .collect::<Vec<_>>()
+    };
+    let pixels = match image.format {
+        gltf::image::Format::R8 => channels(1, 1),
+        gltf::image::Format::R8G8 => channels(1, 2),
+        gltf::image::Format::R8G8B8 => channels(1, 3),
+        gltf::image::Format::R8G8B8A8 => image.pixels.clone(),
+        gltf::image::Format::R16 => channels(2, 1),
+        gltf::image::Format::R16G16 => channels(2, 2),
+        gltf::image::Format::R16G16B16 => channels(2, 3),
+        gltf::image::Format::R16G16B16A16 => channels(2, 4),
+        gltf::image::Format::R32G32B32FLOAT => channels(4, 3),
+        gltf::image::Format::R32G32B32A32FLOAT => channels(4, 4),
+    };
+    crate::world::Image {
+        width: image.width,
+        height: image.height,
+        pixels,
+        color_space: None,
+    }
+}
+
+// Line loops and triangle fans have no gpu equivalent, so they are expanded into lists
+fn convert_topology(
+    mode: gltf::mesh::Mode,
+    indices: Option<crate::world::Indices>,
+    vertex_count: usize,
+) -> (
+    crate::world::PrimitiveTopology,
+    Option<crate::world::Indices>,
+) {
+    let expand = |indices: Option<crate::world::Indices>, expand: fn(&[u32]) -> Vec<u32>| {
+        let indices = match indices {
+            Some(indices) => indices.iter().collect::<Vec<_>>(),
+            None => (0..vertex_count as u32).collect(),
+        };
+        Some(crate::world::Indices::U32(expand(&indices)))
+    };
+    match mode {
+        gltf::mesh::Mode::Points => (crate::world::PrimitiveTopology::PointList, indices),
+        gltf::mesh::Mode::Lines => (crate::world::PrimitiveTopology::LineList, indices),
+        gltf::mesh::Mode::LineStrip => (crate::world::PrimitiveTopology::LineStrip, indices),
+        gltf::mesh::Mode::Triangles => (crate::world::PrimitiveTopology::TriangleList, indices),
+        gltf::mesh::Mode::TriangleStrip => {
+            (crate::world::PrimitiveTopology::TriangleStrip, indices)
+        }
+        gltf::mesh::Mode::LineLoop => (
+            crate::world::PrimitiveTopology::LineList,
+            expand(indices, |indices| {
+                (0..indices.len())
+                    .flat_map(|index| [indices[index], indices[(index + 1) % indices.len()]])
+                    .collect()
+            }),
+        ),
+        gltf::mesh::Mode::TriangleFan => (
+            crate::world::PrimitiveTopology::TriangleList,
+            expand(indices, |indices| {
+                (1..indices.len().saturating_sub(1))
+                    .flat_map(|index| [indices[0], indices[index], indices[index + 1]])
+                    .collect()
+            }),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> std::path::PathBuf {
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join(name)
+    }
+
+    fn single_node(
+        scene: &crate::world::Scene,
+        predicate: impl Fn(&crate::world::Node) -> bool,
+    ) -> petgraph::graph::NodeIndex {
+        let mut matches = scene
+            .node_indices()
+            .filter(|index| predicate(&scene[*index]));
+        let node = matches.next().expect("no matching node");
+        assert!(matches.next().is_none(), "more than one matching node");
+        node
+    }
+
+    fn parent(
+        scene: &crate::world::Scene,
+        node: petgraph::graph::NodeIndex,
+    ) -> Option<petgraph::graph::NodeIndex> {
+        scene
+            .neighbors_directed(node, petgraph::Direction::Incoming)
+            .next()
+    }
+
+    fn translation(world: &crate::world::World, node: petgraph::graph::NodeIndex) -> [f32; 3] {
+        let matrix = world.global_transform(0, node).unwrap();
+        [matrix[(0, 3)], matrix[(1, 3)], matrix[(2, 3)]]
+    }
+
+    fn assert_near(found: [f32; 3], expected: [f32; 3]) {
+        for (found, expected) in found.iter().zip(expected) {
+            assert!(
+                (found - expected).abs() < 1e-5,
+                "expected {expected:?}, found {found:?}"
+            );
+        }
+    }
+
+    fn assert_triangle_scene(mut world: crate::world::World) {
+        use crate::world::{Geometry, Node, Node3D, Projection, VisualInstance3D};
+
+        assert_eq!(world.scenes.len(), 1);
+        let scene = &world.scenes[0];
+        // Four glTF nodes plus the mesh instance under the node holding the mesh
+        assert_eq!(scene.node_count(), 5);
+        assert_eq!(scene.edge_count(), 4);
+
+        let mesh_instance = single_node(scene, |node| {
+            matches!(
+                node,
+                Node::VisualInstance3D(VisualInstance3D::Geometry(Geometry::MeshInstance3D(instance)))
+                    if instance.mesh_reference.as_deref() == Some("triangle")
+            )
+        });
+        let perspective = single_node(scene, |node| {
+            matches!(node, Node::Node3D { node: Node3D::Camera3D { camera }, .. }
+                if matches!(camera.projection, Projection::Perspective(_)))
+        });
+        let orthographic = single_node(scene, |node| {
+            matches!(node, Node::Node3D { node: Node3D::Camera3D { camera }, .. }
+                if matches!(camera.projection, Projection::Orthographic(_)))
+        });
+        let child = parent(scene, mesh_instance).unwrap();
+        let root = parent(scene, child).unwrap();
+        assert_eq!(parent(scene, root), None);
+        assert_eq!(parent(scene, perspective), Some(root));
+        assert_eq!(parent(scene, orthographic), Some(root));
+
+        let Node::Node3D { transform, .. } = &scene[root] else {
+            panic!("root is not a Node3D");
+        };
+        assert_near(transform.translation.into(), [1.0, 2.0, 3.0]);
+        assert_near(transform.scale.into(), [2.0, 2.0, 2.0]);
+        assert!((transform.rotation.coords.y - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-5);
+
+        let Node::Node3D {
+            node: Node3D::Camera3D { camera },
+            ..
+        } = &scene[perspective]
+        else {
+            unreachable!();
+        };
+        let Projection::Perspective(camera) = &camera.projection else {
+            unreachable!();
+        };
+        assert_eq!(camera.aspect_ratio, Some(1.5));
+        assert_eq!(camera.y_fov_rad, 0.8);
+        assert_eq!(camera.z_far, Some(100.0));
+        assert_eq!(camera.z_near, 0.1);
+
+        let Node::Node3D {
+            node: Node3D::Camera3D { camera },
+            ..
+        } = &scene[orthographic]
+        else {
+            unreachable!();
+        };
+        let Projection::Orthographic(camera) = &camera.projection else {
+            unreachable!();
+        };
+        assert_eq!(
+            (camera.x_mag, camera.y_mag, camera.z_far, camera.z_near),
+            (2.0, 1.5, 50.0, 0.01)
+        );
+
+        // The root turns a quarter around Y and doubles in size
+        world.update_transforms().unwrap();
+        assert_near(translation(&world, child), [1.0, 4.0, 3.0]);
+        assert_near(translation(&world, mesh_instance), [1.0, 4.0, 3.0]);
+        assert_near(translation(&world, perspective), [11.0, 2.0, 3.0]);
+        assert_near(translation(&world, orthographic), [1.0, 2.0, 5.0]);
+
+        assert_eq!(world.meshes.len(), 1);
+        let surfaces = world.meshes["triangle"].surfaces();
+        assert_eq!(surfaces.len(), 1);
+        let surface = &surfaces[0];
+        assert_eq!(
+            surface.attributes.positions,
+            [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+        );
+        assert_eq!(surface.attributes.normals, Some(vec![[0.0, 0.0, 1.0]; 3]));
+        assert_eq!(
+            surface.attributes.uv0,
+            Some(vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]])
+        );
+        assert_eq!(
+            surface.indices.as_ref().unwrap().iter().collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+        assert_eq!(
+            surface.topology,
+            crate::world::PrimitiveTopology::TriangleList
+        );
+        assert_eq!(surface.material.as_deref(), Some("checker"));
+
+        assert_eq!(world.materials.len(), 1);
+        let material = &world.materials["checker"];
+        assert_eq!(material.base_color_factor, [1.0, 0.5, 0.5, 1.0]);
+        assert_eq!(material.metallic_factor, 0.0);
+        assert_eq!(material.roughness_factor, 0.75);
+        assert_eq!(
+            material.base_color_texture,
+            Some(crate::world::TextureReference {
+                texture: "checker".to_string(),
+                uv_set: 0,
+            })
+        );
+
+        assert_eq!(world.textures.len(), 1);
+        let texture = &world.textures["checker"];
+        assert_eq!((texture.image.width, texture.image.height), (2, 2));
+        assert_eq!(
+            texture.image.pixels,
+            [
+                255, 255, 255, 255, 0, 0, 0, 255, //
+                0, 0, 0, 255, 255, 255, 255, 255,
+            ]
+        );
+        assert_eq!(
+            texture.sampler,
+            crate::world::Sampler {
+                mag_filter: crate::world::Filter::Nearest,
+                min_filter: crate::world::Filter::Nearest,
+                mipmap_filter: crate::world::Filter::Nearest,
+                wrap_u: crate::world::WrapMode::ClampToEdge,
+                wrap_v: crate::world::WrapMode::MirroredRepeat,
+            }
+        );
+    }
+
+    #[test]
+    fn imports_gltf_with_external_resources() {
+        assert_triangle_scene(import_gltf(fixture("triangle.gltf")).unwrap());
+    }
+
+    #[test]
+    fn imports_glb() {
+        assert_triangle_scene(import_gltf(fixture("triangle.glb")).unwrap());
+    }
+
+    #[test]
+    fn imports_glb_from_memory() {
+        let bytes = std::fs::read(fixture("triangle.glb")).unwrap();
+        assert_triangle_scene(import_gltf_slice(&bytes).unwrap());
+    }
+
+    #[test]
+    fn fans_and_loops_are_expanded_into_lists() {
+        let (topology, indices) = convert_topology(gltf::mesh::Mode::TriangleFan, None, 4);
+        assert_eq!(topology, crate::world::PrimitiveTopology::TriangleList);
+        assert_eq!(
+            indices.unwrap().iter().collect::<Vec<_>>(),
+            [0, 1, 2, 0, 2, 3]
+        );
+
+        let (topology, indices) = convert_topology(
+            gltf::mesh::Mode::LineLoop,
+            Some(crate::world::Indices::U16(vec![2, 0, 1])),
+            3,
+        );
+        assert_eq!(topology, crate::world::PrimitiveTopology::LineList);
+        assert_eq!(
+            indices.unwrap().iter().collect::<Vec<_>>(),
+            [2, 0, 0, 1, 1, 2]
+        );
+    }
+}
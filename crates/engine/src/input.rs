@@ -0,0 +1,546 @@
+pub use winit::event::MouseButton;
+pub use winit::keyboard::KeyCode;
+
+// Pixel scroll deltas (touchpads, browsers) are converted to lines at this rate, matching egui
+const PIXELS_PER_SCROLL_LINE: f32 = 50.0;
+
+/// Keyboard, mouse and gamepad state for the current frame, plus named actions and axes.
+///
+/// Input the gui consumed never reaches this resource, except releases, so keys held while
+/// focus moves into a text field don't get stuck down.
+#[derive(Default, Debug, Clone)]
+pub struct Input {
+    pub action_map: ActionMap,
+    keys: ButtonState<KeyCode>,
+    mouse_buttons: ButtonState<MouseButton>,
+    gamepad_buttons: ButtonState<GamepadButton>,
+    gamepad_axes: std::collections::HashMap<GamepadAxis, f32>,
+    mouse_position: Option<nalgebra_glm::Vec2>,
+    mouse_delta: nalgebra_glm::Vec2,
+    wheel_delta: nalgebra_glm::Vec2,
+}
+
+impl Input {
+    pub fn key_pressed(&self, key: KeyCode) -> bool {
+        self.keys.pressed(key)
+    }
+
+    pub fn key_just_pressed(&self, key: KeyCode) -> bool {
+        self.keys.just_pressed(key)
+    }
+
+    pub fn key_just_released(&self, key: KeyCode) -> bool {
+        self.keys.just_released(key)
+    }
+
+    pub fn mouse_button_pressed(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.pressed(button)
+    }
+
+    pub fn mouse_button_just_pressed(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.just_pressed(button)
+    }
+
+    pub fn mouse_button_just_released(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.just_released(button)
+    }
+
+    pub fn gamepad_button_pressed(&self, button: GamepadButton) -> bool {
+        self.gamepad_buttons.pressed(button)
+    }
+
+    pub fn gamepad_button_just_pressed(&self, button: GamepadButton) -> bool {
+        self.gamepad_buttons.just_pressed(button)
+    }
+
+    pub fn gamepad_button_just_released(&self, button: GamepadButton) -> bool {
+        self.gamepad_buttons.just_released(button)
+    }
+
+    pub fn gamepad_axis(&self, axis: GamepadAxis) -> f32 {
+        self.gamepad_axes.get(&axis).copied().unwrap_or_default()
+    }
+
+    /// Cursor position in physical pixels from the top left, `None` while outside the window
+    pub fn mouse_position(&self) -> Option<nalgebra_glm::Vec2> {
+        self.mouse_position
+    }
+
+    /// Cursor movement since the previous frame in physical pixels
+    pub fn mouse_delta(&self) -> nalgebra_glm::Vec2 {
+        self.mouse_delta
+    }
+
+    /// Scroll since the previous frame in lines, positive y scrolls up
+    pub fn wheel_delta(&self) -> nalgebra_glm::Vec2 {
+        self.wheel_delta
+    }
+
+    pub fn pressed(&self, binding: &Binding) -> bool {
+        match binding {
+            Binding::Key(key) => self.key_pressed(*key),
+            Binding::Mouse(button) => self.mouse_button_pressed(*button),
+            Binding::Gamepad(button) => self.gamepad_button_pressed(*button),
+        }
+    }
+
+    pub fn just_pressed(&self, binding: &Binding) -> bool {
+        match binding {
+            Binding::Key(key) => self.key_just_pressed(*key),
+            Binding::Mouse(button) => self.mouse_button_just_pressed(*button),
+            Binding::Gamepad(button) => self.gamepad_button_just_pressed(*button),
+        }
+    }
+
+    pub fn just_released(&self, binding: &Binding) -> bool {
+        match binding {
+            Binding::Key(key) => self.key_just_released(*key),
+            Binding::Mouse(button) => self.mouse_button_just_released(*button),
+            Binding::Gamepad(button) => self.gamepad_button_just_released(*button),
+        }
+    }
+
+    /// True while any binding of the action is held
+    pub fn action_pressed(&self, action: &str) -> bool {
+        self.action_bindings(action)
+            .iter()
+            .any(|binding| self.pressed(binding))
+    }
+
+    /// True on the frame the first binding of the action goes down
+    pub fn action_just_pressed(&self, action: &str) -> bool {
+        let bindings = self.action_bindings(action);
+        bindings.iter().any(|binding| self.just_pressed(binding))
+            && !bindings
+                .iter()
+                .any(|binding| self.pressed(binding) && !self.just_pressed(binding))
+    }
+
+    /// True on the frame the last held binding of the action goes up
+    pub fn action_just_released(&self, action: &str) -> bool {
+        let bindings = self.action_bindings(action);
+        bindings.iter().any(|binding| self.just_released(binding))
+            && !bindings.iter().any(|binding| self.pressed(binding))
+    }
+
+    /// Sum of every binding of the axis, clamped to [-1, 1]
+    pub fn axis(&self, axis: &str) -> f32 {
+        let Some(bindings) = self.action_map.axes.get(axis) else {
+            return 0.0;
+        };
+        bindings
+            .iter()
+            .map(|binding| match binding {
+                AxisBinding::Buttons { negative, positive } => {
+                    self.pressed(positive) as i32 as f32 - self.pressed(negative) as i32 as f32
+                }
+                AxisBinding::Gamepad { axis, dead_zone } => {
+                    let value = self.gamepad_axis(*axis);
+                    if value.abs() < *dead_zone {
+                        0.0
+                    } else {
+                        value
+                    }
+                }
+            })
+            .sum::<f32>()
+            .clamp(-1.0, 1.0)
+    }
+
+    /// Feeds gamepad button state from whichever gamepad backend the application uses
+    pub fn set_gamepad_button(&mut self, button: GamepadButton, pressed: bool) {
+        self.gamepad_buttons.set(button, pressed);
+    }
+
+    pub fn set_gamepad_axis(&mut self, axis: GamepadAxis, value: f32) {
+        self.gamepad_axes.insert(axis, value.clamp(-1.0, 1.0));
+    }
+
+    /// Updates the state from a window event. Consumed events only release buttons.
+    pub fn handle_window_event(&mut self, event: &winit::event::WindowEvent, consumed: bool) {
+        match event {
+            winit::event::WindowEvent::KeyboardInput {
+                event:
+                    winit::event::KeyEvent {
+                        physical_key: winit::keyboard::PhysicalKey::Code(key_code),
+                        state,
+                        repeat,
+                        ..
+                    },
+                ..
+            } => match state {
+                winit::event::ElementState::Pressed if !consumed && !repeat => {
+                    self.keys.set(*key_code, true)
+                }
+                winit::event::ElementState::Released => self.keys.set(*key_code, false),
+                _ => {}
+            },
+
+            winit::event::WindowEvent::MouseInput { state, button, .. } => match state {
+                winit::event::ElementState::Pressed if !consumed => {
+                    self.mouse_buttons.set(*button, true)
+                }
+                winit::event::ElementState::Released => self.mouse_buttons.set(*button, false),
+                _ => {}
+            },
+
+            // The position is tracked even over the gui so deltas don't jump when leaving it
+            winit::event::WindowEvent::CursorMoved { position, .. } => {
+                let position = nalgebra_glm::vec2(position.x as f32, position.y as f32);
+                if let Some(previous) = self.mouse_position.filter(|_| !consumed) {
+                    self.mouse_delta += position - previous;
+                }
+                self.mouse_position = Some(position);
+            }
+
+            winit::event::WindowEvent::CursorLeft { .. } => self.mouse_position = None,
+
+            winit::event::WindowEvent::MouseWheel { delta, .. } if !consumed => match delta {
+                winit::event::MouseScrollDelta::LineDelta(x, y) => {
+                    self.wheel_delta += nalgebra_glm::vec2(*x, *y)
+                }
+                winit::event::MouseScrollDelta::PixelDelta(position) => {
+                    self.wheel_delta += nalgebra_glm::vec2(position.x as f32, position.y as f32)
+                        / PIXELS_PER_SCROLL_LINE
+                }
+            },
+
+            // Releases that happen while unfocused are never delivered
+            winit::event::WindowEvent::Focused(false) => {
+                self.keys.release_all();
+                self.mouse_buttons.release_all();
+            }
+
+            _ => {}
+        }
+    }
+
+    /// Clears per-frame state, called by the engine after every frame
+    pub fn end_frame(&mut self) {
+        self.keys.end_frame();
+        self.mouse_buttons.end_frame();
+        self.gamepad_buttons.end_frame();
+        self.mouse_delta = nalgebra_glm::Vec2::zeros();
+        self.wheel_delta = nalgebra_glm::Vec2::zeros();
+    }
+
+    fn action_bindings(&self, action: &str) -> &[Binding] {
+        self.action_map
+            .actions
+            .get(action)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ButtonState<T> {
+    pressed: std::collections::HashSet<T>,
+    just_pressed: std::collections::HashSet<T>,
+    just_released: std::collections::HashSet<T>,
+}
+
+impl<T> Default for ButtonState<T> {
+    fn default() -> Self {
+        Self {
+            pressed: Default::default(),
+            just_pressed: Default::default(),
+            just_released: Default::default(),
+        }
+    }
+}
+
+impl<T: Copy + Eq + std::hash::Hash> ButtonState<T> {
+    fn pressed(&self, button: T) -> bool {
+        self.pressed.contains(&button)
+    }
+
+    fn just_pressed(&self, button: T) -> bool {
+        self.just_pressed.contains(&button)
+    }
+
+    fn just_released(&self, button: T) -> bool {
+        self.just_released.contains(&button)
+    }
+
+    fn set(&mut self, button: T, pressed: bool) {
+        if pressed {
+            if self.pressed.insert(button) {
+                self.just_pressed.insert(button);
+            }
+        } else if self.pressed.remove(&button) {
+            self.just_released.insert(button);
+        }
+    }
+
+    fn release_all(&mut self) {
+        self.just_released.extend(self.pressed.drain());
+    }
+
+    fn end_frame(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+    }
+}
+
+/// Named actions and axes mapped to physical inputs, e.g. "jump" -> Space or gamepad South.
+///
+/// The default map binds `ActionMap::QUIT` to Escape, which the engine treats as a close
+/// request. Remove it with `unbind_action` to use Escape for something else.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ActionMap {
+    #[serde(default)]
+    pub actions: std::collections::BTreeMap<String, Vec<Binding>>,
+    #[serde(default)]
+    pub axes: std::collections::BTreeMap<String, Vec<AxisBinding>>,
+}
+
+impl Default for ActionMap {
+    fn default() -> Self {
+        let mut action_map = Self {
+            actions: std::collections::BTreeMap::new(),
+            axes: std::collections::BTreeMap::new(),
+        };
+        action_map.bind_action(Self::QUIT, Binding::Key(KeyCode::Escape));
+        action_map
+    }
+}
+
+impl ActionMap {
+    /// Action that asks the application to close, see `State::on_close_requested`
+    pub const QUIT: &'static str = "quit";
+
+    pub fn load(
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<Self, crate::persistence::PersistenceError> {
+        Self::from_ron_str(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn save(
+        &self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), crate::persistence::PersistenceError> {
+        let config = ron::ser::PrettyConfig::default().indentor("    ".to_string());
+        std::fs::write(path, ron::ser::to_string_pretty(self, config)?)?;
+        Ok(())
+    }
+
+    pub fn from_ron_str(source: &str) -> Result<Self, crate::persistence::PersistenceError> {
+        Ok(ron::from_str(source)?)
+    }
+
+    pub fn bind_action(&mut self, action: impl Into<String>, binding: Binding) {
+        self.actions.entry(action.into()).or_default().push(binding);
+    }
+
+    /// Removes every binding of an action, returning them if there were any
+    pub fn unbind_action(&mut self, action: &str) -> Option<Vec<Binding>> {
+        self.actions.remove(action)
+    }
+
+    pub fn bind_axis(&mut self, axis: impl Into<String>, binding: AxisBinding) {
+        self.axes.entry(axis.into()).or_default().push(binding);
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButton),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum AxisBinding {
+    // Two buttons driving -1 and +1, e.g. A and D
+    Buttons {
+        negative: Binding,
+        positive: Binding,
+    },
+    // Values with a magnitude below `dead_zone` read as zero
+    Gamepad {
+        axis: GamepadAxis,
+        dead_zone: f32,
+    },
+}
+
+// Buttons are named by position so layouts from different vendors share bindings
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum GamepadButton {
+    South,
+    East,
+    West,
+    North,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jump_map() -> ActionMap {
+        let mut action_map = ActionMap::default();
+        action_map.bind_action("jump", Binding::Key(KeyCode::Space));
+        action_map.bind_action("jump", Binding::Gamepad(GamepadButton::South));
+        action_map.bind_axis(
+            "move",
+            AxisBinding::Buttons {
+                negative: Binding::Key(KeyCode::KeyA),
+                positive: Binding::Key(KeyCode::KeyD),
+            },
+        );
+        action_map.bind_axis(
+            "move",
+            AxisBinding::Gamepad {
+                axis: GamepadAxis::LeftStickX,
+                dead_zone: 0.2,
+            },
+        );
+        action_map
+    }
+
+    fn input() -> Input {
+        Input {
+            action_map: jump_map(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn presses_and_releases_last_one_frame() {
+        let mut input = input();
+        input.keys.set(KeyCode::Space, true);
+        assert!(input.key_pressed(KeyCode::Space));
+        assert!(input.key_just_pressed(KeyCode::Space));
+
+        input.end_frame();
+        // Held keys repeat without pressing again
+        input.keys.set(KeyCode::Space, true);
+        assert!(input.key_pressed(KeyCode::Space));
+        assert!(!input.key_just_pressed(KeyCode::Space));
+
+        input.keys.set(KeyCode::Space, false);
+        assert!(!input.key_pressed(KeyCode::Space));
+        assert!(input.key_just_released(KeyCode::Space));
+
+        input.end_frame();
+        assert!(!input.key_just_released(KeyCode::Space));
+    }
+
+    #[test]
+    fn taps_within_a_frame_are_both_pressed_and_released() {
+        let mut input = input();
+        input.set_gamepad_button(GamepadButton::East, true);
+        input.set_gamepad_button(GamepadButton::East, false);
+        assert!(!input.gamepad_button_pressed(GamepadButton::East));
+        assert!(input.gamepad_button_just_pressed(GamepadButton::East));
+        assert!(input.gamepad_button_just_released(GamepadButton::East));
+        // Releasing what isn't held does nothing
+        input.end_frame();
+        input.set_gamepad_button(GamepadButton::East, false);
+        assert!(!input.gamepad_button_just_released(GamepadButton::East));
+    }
+
+    #[test]
+    fn losing_focus_releases_everything() {
+        let mut input = input();
+        input.keys.set(KeyCode::KeyW, true);
+        input.mouse_buttons.set(MouseButton::Left, true);
+        input.end_frame();
+        input.handle_window_event(&winit::event::WindowEvent::Focused(false), false);
+        assert!(!input.key_pressed(KeyCode::KeyW));
+        assert!(input.key_just_released(KeyCode::KeyW));
+        assert!(!input.mouse_button_pressed(MouseButton::Left));
+        assert!(input.mouse_button_just_released(MouseButton::Left));
+    }
+
+    #[test]
+    fn actions_trigger_on_their_first_press_and_last_release() {
+        let mut input = input();
+        input.keys.set(KeyCode::Space, true);
+        assert!(input.action_pressed("jump"));
+        assert!(input.action_just_pressed("jump"));
+        input.end_frame();
+
+        // A second binding going down while the first is held doesn't press again
+        input.set_gamepad_button(GamepadButton::South, true);
+        assert!(!input.action_just_pressed("jump"));
+        input.keys.set(KeyCode::Space, false);
+        assert!(input.action_pressed("jump"));
+        assert!(!input.action_just_released("jump"));
+        input.end_frame();
+
+        input.set_gamepad_button(GamepadButton::South, false);
+        assert!(!input.action_pressed("jump"));
+        assert!(input.action_just_released("jump"));
+        assert!(!input.action_pressed("crouch"));
+    }
+
+    #[test]
+    fn quit_is_bound_to_escape_until_unbound() {
+        let mut input = input();
+        input.keys.set(KeyCode::Escape, true);
+        assert!(input.action_just_pressed(ActionMap::QUIT));
+        assert_eq!(
+            input.action_map.unbind_action(ActionMap::QUIT),
+            Some(vec![Binding::Key(KeyCode::Escape)])
+        );
+        assert!(!input.action_pressed(ActionMap::QUIT));
+    }
+
+    #[test]
+    fn axes_sum_their_bindings() {
+        let mut input = input();
+        assert_eq!(input.axis("move"), 0.0);
+        input.keys.set(KeyCode::KeyD, true);
+        assert_eq!(input.axis("move"), 1.0);
+        input.keys.set(KeyCode::KeyA, true);
+        assert_eq!(input.axis("move"), 0.0);
+
+        input.keys.set(KeyCode::KeyD, false);
+        input.set_gamepad_axis(GamepadAxis::LeftStickX, 0.1);
+        assert_eq!(input.axis("move"), -1.0);
+        input.keys.set(KeyCode::KeyA, false);
+        // Inside the dead zone
+        assert_eq!(input.axis("move"), 0.0);
+        input.set_gamepad_axis(GamepadAxis::LeftStickX, 0.5);
+        assert_eq!(input.axis("move"), 0.5);
+        // Clamped once past the buttons' range
+        input.keys.set(KeyCode::KeyD, true);
+        assert_eq!(input.axis("move"), 1.0);
+        input.set_gamepad_axis(GamepadAxis::LeftStickX, -3.0);
+        assert_eq!(input.gamepad_axis(GamepadAxis::LeftStickX), -1.0);
+        assert_eq!(input.axis("missing"), 0.0);
+    }
+
+    #[test]
+    fn action_maps_round_trip_through_ron() {
+        let action_map = jump_map();
+        let config = ron::ser::PrettyConfig::default();
+        let source = ron::ser::to_string_pretty(&action_map, config).unwrap();
+        let loaded = ActionMap::from_ron_str(&source).unwrap();
+        assert_eq!(loaded.actions, action_map.actions);
+        assert_eq!(loaded.axes, action_map.axes);
+    }
+}
@@ -1,12 +1,35 @@
+mod capture;
+mod lighting;
 mod renderer;
+mod shadow;
 
 mod platform;
 
+pub mod event;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod golden;
+pub mod import;
+pub mod input;
 pub mod message;
+pub mod persistence;
+pub mod primitive;
+pub mod settings;
+pub mod texture;
+pub mod time;
+pub mod transform;
 pub mod world;
 
+pub use event::*;
+pub use input::Input;
 pub use message::*;
 pub use platform::*;
+pub use renderer::{CaptureError, CaptureRequest, CapturedFrame, Renderer};
+pub use settings::{LaunchSettings, RendererSettings, WindowMode};
+pub use time::Time;
 
 pub use egui;
+pub use egui_wgpu;
 pub use log;
+pub use nalgebra_glm;
+pub use petgraph;
+pub use winit;
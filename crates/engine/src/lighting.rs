@@ -0,0 +1,485 @@
+//! Collects the world's light nodes and bins omni and spot lights into a clustered light
+//! list, so each fragment only shades the lights that can reach it.
+//!
+//! The view frustum is split into a grid of screen tiles and exponentially spaced depth
+//! slices. Each cluster gets a range into a shared list of light indices, built on the cpu
+//! every frame and consumed by the material shader.
+
+/// Directional lights past this many are ignored
+pub const MAX_DIRECTIONAL_LIGHTS: usize = 4;
+pub const CLUSTER_TILES_X: u32 = 16;
+pub const CLUSTER_TILES_Y: u32 = 9;
+pub const CLUSTER_SLICES: u32 = 24;
+pub const CLUSTER_COUNT: usize = (CLUSTER_TILES_X * CLUSTER_TILES_Y * CLUSTER_SLICES) as usize;
+// Slicing from the near plane of a close camera would spend most slices on the first meter
+const MIN_SLICE_DEPTH: f32 = 0.1;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DirectionalLight {
+    /// World space direction the light travels in
+    pub direction: nalgebra_glm::Vec3,
+    /// Color scaled by intensity
+    pub radiance: nalgebra_glm::Vec3,
+    pub shadow: Option<crate::world::Shadow>,
+    pub cascades: crate::world::ShadowCascades,
+}
+
+/// An omni or spot light, omni lights have a cone covering every direction
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PointLight {
+    pub position: nalgebra_glm::Vec3,
+    /// World space direction the cone points in
+    pub direction: nalgebra_glm::Vec3,
+    /// Color scaled by intensity
+    pub radiance: nalgebra_glm::Vec3,
+    pub range: f32,
+    // The cone attenuation is clamp(cos(angle) * scale + offset, 0, 1) squared
+    pub cone_scale: f32,
+    pub cone_offset: f32,
+    /// Half angle of the outer cone, PI for omni lights
+    pub cone_angle: f32,
+    /// Only spot lights cast shadows
+    pub shadow: Option<crate::world::Shadow>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Lights {
+    pub directional: Vec<DirectionalLight>,
+    pub point: Vec<PointLight>,
+}
+
+/// Gathers every light node in the world with its global transform applied
+pub fn collect_lights(world: &crate::world::World) -> Lights {
+    let mut lights = Lights::default();
+    for (scene_index, scene) in world.scenes.iter().enumerate() {
+        for node_index in scene.node_indices() {
+            let crate::world::Node::Node3D { node, .. } = &scene[node_index] else {
+                continue;
+            };
+            let Some(transform) = world.global_transform(scene_index, node_index) else {
+                continue;
+            };
+            let position = transform.column(3).xyz();
+            let direction = (transform * nalgebra_glm::vec4(0.0, 0.0, -1.0, 0.0))
+                .xyz()
+                .try_normalize(f32::EPSILON)
+                .unwrap_or_else(|| -nalgebra_glm::Vec3::z());
+            let radiance =
+                |color: &[f32; 3], intensity: f32| nalgebra_glm::make_vec3(color) * intensity;
+            match node {
+                crate::world::Node3D::DirectionalLight3D { light } => {
+                    lights.directional.push(DirectionalLight {
+                        direction,
+                        radiance: radiance(&light.color, light.intensity),
+                        shadow: light.shadow,
+                        cascades: light.cascades,
+                    })
+                }
+                crate::world::Node3D::OmniLight3D { light } => lights.point.push(PointLight {
+                    position,
+                    direction,
+                    radiance: radiance(&light.color, light.intensity),
+                    range: light.range,
+                    cone_scale: 0.0,
+                    cone_offset: 1.0,
+                    cone_angle: std::f32::consts::PI,
+                    shadow: None,
+                }),
+                crate::world::Node3D::SpotLight3D { light } => {
+                    let cos_outer = light.outer_cone_angle_rad.cos();
+                    let cos_inner = light.inner_cone_angle_rad.cos();
+                    let cone_scale = 1.0 / (cos_inner - cos_outer).max(1e-3);
+                    lights.point.push(PointLight {
+                        position,
+                        direction,
+                        radiance: radiance(&light.color, light.intensity),
+                        range: light.range,
+                        cone_scale,
+                        cone_offset: -cos_outer * cone_scale,
+                        cone_angle: light.outer_cone_angle_rad,
+                        shadow: light.shadow,
+                    })
+                }
+                _ => {}
+            }
+        }
+    }
+    // Lights that can't reach anything would only take up space in the clusters
+    lights.point.retain(|light| light.range > 0.0);
+    lights
+}
+
+/// Light index ranges per cluster, see `build_clusters`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clusters {
+    /// View depth where the second slice starts, everything closer is in the first slice
+    pub near: f32,
+    /// Multiplies the log of view depth over `near` to give the slice index
+    pub slice_scale: f32,
+    /// Offset into `indices` and light count for each cluster, ordered by slice, then row,
+    /// then column
+    pub ranges: Vec<[u32; 2]>,
+    pub indices: Vec<u32>,
+}
+
+impl Clusters {
+    /// Index into `ranges` of the cluster with the given tile and slice
+    pub fn cluster_index(tile_x: u32, tile_y: u32, slice: u32) -> usize {
+        ((slice * CLUSTER_TILES_Y + tile_y) * CLUSTER_TILES_X + tile_x) as usize
+    }
+
+    /// The slice containing a view depth, the inverse of `slice_depth`
+    pub fn slice(&self, depth: f32) -> u32 {
+        let slice = ((depth.max(self.near) / self.near).ln() * self.slice_scale).floor();
+        (slice as u32).min(CLUSTER_SLICES - 1)
+    }
+
+    /// View depth where a slice starts
+    pub fn slice_depth(&self, slice: u32) -> f32 {
+        self.near * (slice as f32 / self.slice_scale).exp()
+    }
+}
+
+/// Assigns each light to every cluster its bounding sphere touches.
+///
+/// Slices are spaced exponentially from the projection's near plane to the furthest point
+/// any light reaches, tiles evenly across the screen. Tile rows count down from the top of
+/// the screen like fragment coordinates.
+pub fn build_clusters(
+    lights: &[PointLight],
+    view: &nalgebra_glm::Mat4,
+    projection: &nalgebra_glm::Mat4,
+) -> Clusters {
+    let corners = TileCorners::new(projection);
+    let near = corners.near_depth.max(MIN_SLICE_DEPTH);
+    let view_lights = lights
+        .iter()
+        .map(|light| {
+            let position = &light.position;
+            let center = view * nalgebra_glm::vec4(position.x, position.y, position.z, 1.0);
+            (center.xyz(), light.range)
+        })
+        .collect::<Vec<_>>();
+    let far = view_lights
+        .iter()
+        .map(|(center, range)| -center.z + range)
+        .fold(near * 2.0, f32::max);
+    let mut clusters = Clusters {
+        near,
+        slice_scale: CLUSTER_SLICES as f32 / (far / near).ln(),
+        ranges: vec![[0, 0]; CLUSTER_COUNT],
+        indices: Vec::new(),
+    };
+    if view_lights.is_empty() {
+        return clusters;
+    }
+
+    // The first slice also covers everything between the near plane and `near`
+    let depths = (0..=CLUSTER_SLICES)
+        .map(|slice| match slice {
+            0 => corners.near_depth,
+            _ => clusters.slice_depth(slice),
+        })
+        .collect::<Vec<_>>();
+    let bounds = depths
+        .windows(2)
+        .flat_map(|slice| corners.bounds(slice[0], slice[1]))
+        .collect::<Vec<_>>();
+
+    let mut assignments = Vec::new();
+    for (light_index, (center, range)) in view_lights.iter().enumerate() {
+        let (min_depth, max_depth) = (-center.z - range, -center.z + range);
+        if max_depth < corners.near_depth {
+            continue;
+        }
+        let (columns, rows) = screen_tiles(projection, center, *range);
+        let center: [f32; 3] = (*center).into();
+        for slice in clusters.slice(min_depth)..=clusters.slice(max_depth) {
+            for tile_y in rows.clone() {
+                for tile_x in columns.clone() {
+                    let cluster = Clusters::cluster_index(tile_x, tile_y, slice);
+                    if sphere_intersects_box(center, *range, &bounds[cluster]) {
+                        assignments.push((cluster, light_index as u32));
+                    }
+                }
+            }
+        }
+    }
+
+    // Counting sort by cluster, lights stay in order within each cluster
+    for (cluster, _) in &assignments {
+        clusters.ranges[*cluster][1] += 1;
+    }
+    let mut offset = 0;
+    for range in clusters.ranges.iter_mut() {
+        range[0] = offset;
+        offset += range[1];
+    }
+    let mut next = clusters
+        .ranges
+        .iter()
+        .map(|range| range[0])
+        .collect::<Vec<_>>();
+    clusters.indices = vec![0; assignments.len()];
+    for (cluster, light_index) in assignments {
+        clusters.indices[next[cluster] as usize] = light_index;
+        next[cluster] += 1;
+    }
+    clusters
+}
+
+// Columns and rows of the tiles covered by a view space sphere's bounding box on screen, or
+// every tile if part of the box is behind the camera
+fn screen_tiles(
+    projection: &nalgebra_glm::Mat4,
+    center: &nalgebra_glm::Vec3,
+    radius: f32,
+) -> (std::ops::RangeInclusive<u32>, std::ops::RangeInclusive<u32>) {
+    let every_tile = (0..=CLUSTER_TILES_X - 1, 0..=CLUSTER_TILES_Y - 1);
+    let (mut min, mut max) = ([f32::MAX; 2], [f32::MIN; 2]);
+    for corner in 0..8 {
+        let offset = [0, 1, 2].map(|axis| match corner & (1 << axis) {
+            0 => -radius,
+            _ => radius,
+        });
+        let clip = projection
+            * nalgebra_glm::vec4(
+                center.x + offset[0],
+                center.y + offset[1],
+                center.z + offset[2],
+                1.0,
+            );
+        if clip.w <= f32::EPSILON {
+            return every_tile;
+        }
+        for axis in 0..2 {
+            min[axis] = min[axis].min(clip[axis] / clip.w);
+            max[axis] = max[axis].max(clip[axis] / clip.w);
+        }
+    }
+    // Rows count down from the top of the screen while y in clip space points up
+    let tile = |ndc: f32, tiles: u32| (ndc.clamp(0.0, 1.0) * tiles as f32).min(tiles as f32 - 1.0);
+    let columns = tile((min[0] + 1.0) / 2.0, CLUSTER_TILES_X) as u32
+        ..=tile((max[0] + 1.0) / 2.0, CLUSTER_TILES_X) as u32;
+    let rows = tile((1.0 - max[1]) / 2.0, CLUSTER_TILES_Y) as u32
+        ..=tile((1.0 - min[1]) / 2.0, CLUSTER_TILES_Y) as u32;
+    (columns, rows)
+}
+
+// Plain arrays rather than vectors, this runs for every light and cluster each frame
+fn sphere_intersects_box(center: [f32; 3], radius: f32, [min, max]: &[[f32; 3]; 2]) -> bool {
+    let mut distance_squared = 0.0;
+    for axis in 0..3 {
+        let offset = center[axis] - center[axis].clamp(min[axis], max[axis]);
+        distance_squared += offset * offset;
+    }
+    distance_squared <= radius * radius
+}
+
+// View space rays through the corners of the screen tiles
+struct TileCorners {
+    // Two points along each ray, indexed by row then column
+    rays: Vec<([f32; 3], [f32; 3])>,
+    // View depth of the projection's near plane
+    near_depth: f32,
+}
+
+impl TileCorners {
+    fn new(projection: &nalgebra_glm::Mat4) -> Self {
+        let inverse = projection
+            .try_inverse()
+            .unwrap_or_else(nalgebra_glm::Mat4::identity);
+        let unproject = |x: f32, y: f32, z: f32| {
+            let point = inverse * nalgebra_glm::vec4(x, y, z, 1.0);
+            (point.xyz() / point.w).into()
+        };
+        let mut rays = Vec::new();
+        for row in 0..=CLUSTER_TILES_Y {
+            for column in 0..=CLUSTER_TILES_X {
+                let x = column as f32 / CLUSTER_TILES_X as f32 * 2.0 - 1.0;
+                let y = 1.0 - row as f32 / CLUSTER_TILES_Y as f32 * 2.0;
+                rays.push((unproject(x, y, 0.0), unproject(x, y, 0.5)));
+            }
+        }
+        let near: [f32; 3] = unproject(0.0, 0.0, 0.0);
+        Self {
+            rays,
+            near_depth: -near[2],
+        }
+    }
+
+    fn index(row: u32, column: u32) -> usize {
+        (row * (CLUSTER_TILES_X + 1) + column) as usize
+    }
+
+    // Where each ray crosses a view depth
+    fn points_at_depth(&self, depth: f32) -> Vec<[f32; 3]> {
+        self.rays
+            .iter()
+            .map(|(a, b)| {
+                let t = (-depth - a[2]) / (b[2] - a[2]);
+                [0, 1, 2].map(|axis| a[axis] + (b[axis] - a[axis]) * t)
+            })
+            .collect()
+    }
+
+    // View space bounding box of each tile between two depths, in cluster order
+    fn bounds(&self, start: f32, end: f32) -> impl Iterator<Item = [[f32; 3]; 2]> {
+        let (front, back) = (self.points_at_depth(start), self.points_at_depth(end));
+        (0..CLUSTER_TILES_Y).flat_map(move |tile_y| {
+            let (front, back) = (front.clone(), back.clone());
+            (0..CLUSTER_TILES_X).map(move |tile_x| {
+                let mut bounds = [[f32::MAX; 3], [f32::MIN; 3]];
+                for (row, column) in [
+                    (tile_y, tile_x),
+                    (tile_y, tile_x + 1),
+                    (tile_y + 1, tile_x),
+                    (tile_y + 1, tile_x + 1),
+                ] {
+                    let index = Self::index(row, column);
+                    for point in [front[index], back[index]] {
+                        for axis in 0..3 {
+                            bounds[0][axis] = bounds[0][axis].min(point[axis]);
+                            bounds[1][axis] = bounds[1][axis].max(point[axis]);
+                        }
+                    }
+                }
+                bounds
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn omni(x: f32, y: f32, z: f32, range: f32) -> PointLight {
+        PointLight {
+            position: nalgebra_glm::vec3(x, y, z),
+            direction: -nalgebra_glm::Vec3::z(),
+            radiance: nalgebra_glm::vec3(1.0, 1.0, 1.0),
+            range,
+            cone_scale: 0.0,
+            cone_offset: 1.0,
+            cone_angle: std::f32::consts::PI,
+            shadow: None,
+        }
+    }
+
+    // A camera at the origin looking down -Z
+    fn cluster_lights(lights: &[PointLight]) -> Clusters {
+        let projection =
+            nalgebra_glm::perspective_rh_zo(16.0 / 9.0, 60_f32.to_radians(), 0.1, 100.0);
+        build_clusters(lights, &nalgebra_glm::Mat4::identity(), &projection)
+    }
+
+    // (tile_x, tile_y, slice) of every cluster holding the light
+    fn light_clusters(clusters: &Clusters, light: u32) -> Vec<(u32, u32, u32)> {
+        let mut found = Vec::new();
+        for slice in 0..CLUSTER_SLICES {
+            for tile_y in 0..CLUSTER_TILES_Y {
+                for tile_x in 0..CLUSTER_TILES_X {
+                    let [offset, count] =
+                        clusters.ranges[Clusters::cluster_index(tile_x, tile_y, slice)];
+                    let indices = &clusters.indices[offset as usize..(offset + count) as usize];
+                    if indices.contains(&light) {
+                        found.push((tile_x, tile_y, slice));
+                    }
+                }
+            }
+        }
+        found
+    }
+
+    #[test]
+    fn lights_land_in_the_clusters_around_them() {
+        let clusters = cluster_lights(&[omni(0.0, 0.0, -10.0, 1.0)]);
+        let found = light_clusters(&clusters, 0);
+        // The center of the screen, 10 units away
+        assert!(found.contains(&(8, 4, clusters.slice(10.0))));
+        for (tile_x, tile_y, slice) in found {
+            assert!((6..=9).contains(&tile_x), "column {tile_x}");
+            assert!((3..=5).contains(&tile_y), "row {tile_y}");
+            assert!(
+                (clusters.slice(9.0)..=clusters.slice(11.0)).contains(&slice),
+                "slice {slice}"
+            );
+        }
+    }
+
+    #[test]
+    fn rows_count_from_the_top_of_the_screen() {
+        let clusters = cluster_lights(&[omni(-4.0, 3.0, -10.0, 0.5)]);
+        let found = light_clusters(&clusters, 0);
+        assert!(!found.is_empty());
+        for (tile_x, tile_y, _) in found {
+            assert!(tile_x < CLUSTER_TILES_X / 2, "column {tile_x}");
+            assert!(tile_y < CLUSTER_TILES_Y / 2, "row {tile_y}");
+        }
+    }
+
+    #[test]
+    fn lights_behind_the_camera_still_reach_the_near_clusters() {
+        let clusters = cluster_lights(&[omni(0.0, 0.0, 2.0, 3.0)]);
+        let found = light_clusters(&clusters, 0);
+        // Its sphere wraps around the camera, so the near slice is lit edge to edge
+        for tile in [(0, 0), (8, 4), (CLUSTER_TILES_X - 1, CLUSTER_TILES_Y - 1)] {
+            assert!(found.contains(&(tile.0, tile.1, 0)), "{tile:?}");
+        }
+    }
+
+    #[test]
+    fn lights_out_of_view_are_dropped() {
+        let clusters = cluster_lights(&[
+            // Entirely behind the camera
+            omni(0.0, 0.0, 5.0, 1.0),
+            // Far off to the side
+            omni(100.0, 0.0, -10.0, 1.0),
+        ]);
+        assert!(clusters.indices.is_empty());
+        assert!(clusters.ranges.iter().all(|&[_, count]| count == 0));
+    }
+
+    #[test]
+    fn lights_are_sorted_by_cluster_in_order() {
+        let lights = [
+            omni(0.0, 0.0, -10.0, 1.0),
+            omni(3.0, 0.0, -20.0, 2.0),
+            omni(0.0, 0.0, -10.0, 1.0),
+        ];
+        let clusters = cluster_lights(&lights);
+        let mut offset = 0;
+        for &[start, count] in &clusters.ranges {
+            assert_eq!(start, offset);
+            offset += count;
+            let indices = &clusters.indices[start as usize..(start + count) as usize];
+            assert!(indices.windows(2).all(|pair| pair[0] < pair[1]));
+        }
+        assert_eq!(offset as usize, clusters.indices.len());
+        // Identical lights share every cluster
+        assert_eq!(light_clusters(&clusters, 0), light_clusters(&clusters, 2));
+    }
+
+    #[test]
+    fn slices_end_at_the_furthest_light() {
+        let clusters = cluster_lights(&[omni(0.0, 0.0, -40.0, 10.0)]);
+        assert_eq!(clusters.near, 0.1);
+        assert!((clusters.slice_depth(CLUSTER_SLICES) - 50.0).abs() < 1e-3);
+        assert_eq!(clusters.slice(0.0), 0);
+        assert_eq!(clusters.slice(1000.0), CLUSTER_SLICES - 1);
+    }
+
+    #[test]
+    fn slice_and_slice_depth_are_inverses() {
+        let clusters = cluster_lights(&[omni(0.0, 0.0, -40.0, 10.0)]);
+        for slice in 0..CLUSTER_SLICES {
+            let (start, end) = (clusters.slice_depth(slice), clusters.slice_depth(slice + 1));
+            assert!(start < end);
+            // Away from the boundaries, where rounding could go either way
+            assert_eq!(clusters.slice(start * 1.001), slice);
+            assert_eq!(clusters.slice((start + end) / 2.0), slice);
+            assert_eq!(clusters.slice(end * 0.999), slice);
+        }
+    }
+}
@@ -1,7 +1,56 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+// Commands queued by the application and applied by the engine after `State::update`
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub enum EngineMessage {
     #[default]
     Empty,
+    // Writes the next rendered frame to a PNG file
+    Screenshot {
+        path: std::path::PathBuf,
+        include_ui: bool,
+    },
+    // Writes consecutive frames as numbered PNG files until stopped or `frame_count` is reached
+    StartCapture {
+        directory: std::path::PathBuf,
+        include_ui: bool,
+        frame_count: Option<u32>,
+    },
+    StopCapture,
+    // Adds a node to a scene, the id is chosen by the sender so it can refer to the node later
+    SpawnNode {
+        scene_index: usize,
+        id: crate::world::NodeId,
+        node: crate::world::Node,
+        parent: Option<crate::world::NodeId>,
+    },
+    // Removes a node and its whole subtree
+    DespawnNode {
+        id: crate::world::NodeId,
+    },
+    // Moves a node under a new parent, or to the scene root for `None`
+    ReparentNode {
+        id: crate::world::NodeId,
+        parent: Option<crate::world::NodeId>,
+    },
+    SetTransform {
+        id: crate::world::NodeId,
+        transform: crate::world::Transform3D,
+    },
+    // Appends the scenes of a world file (RON or binary) or a glTF file to the world
+    LoadScene {
+        path: std::path::PathBuf,
+    },
+    UnloadScene {
+        scene_index: usize,
+    },
+    // Renders through a specific camera, `None` restores the default camera selection
+    SetActiveCamera {
+        id: Option<crate::world::NodeId>,
+    },
+    SetWindowTitle {
+        title: String,
+    },
+    ToggleFullscreen,
+    Quit,
 }
@@ -0,0 +1,982 @@
+/// Version of the on-disk world schema. Bump this whenever a serialized type changes shape,
+/// and teach `World::from_ron_str` to read files from the version before.
+pub const WORLD_FORMAT_VERSION: u32 = 5;
+
+/// Magic bytes at the start of every binary world file
+pub const BINARY_MAGIC: [u8; 4] = *b"SPWB";
+
+// Chunks start on this boundary so memory-mapped blobs can be cast in place
+const CHUNK_ALIGNMENT: usize = 16;
+const HEADER_SIZE: usize = 16;
+const CHUNK_ENTRY_SIZE: usize = 24;
+
+#[derive(Debug)]
+pub enum PersistenceError {
+    Io(std::io::Error),
+    Serialize(ron::Error),
+    Deserialize(ron::error::SpannedError),
+    Binary(bincode::Error),
+    UnsupportedVersion { found: u32, supported: u32 },
+    InvalidMagic,
+    Truncated,
+    InvalidChunk { index: u32 },
+}
+
+impl std::fmt::Display for PersistenceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "Failed to access world file: {error}"),
+            Self::Serialize(error) => write!(f, "Failed to serialize world: {error}"),
+            Self::Deserialize(error) => write!(f, "Failed to parse world file: {error}"),
+            Self::Binary(error) => write!(f, "Failed to encode binary world manifest: {error}"),
+            Self::InvalidMagic => write!(f, "File is not a binary world file"),
+            Self::Truncated => write!(f, "Binary world file is truncated"),
+            Self::InvalidChunk { index } => {
+                write!(
+                    f,
+                    "Binary world file references missing or invalid chunk {index}"
+                )
+            }
+            Self::UnsupportedVersion { found, supported } => write!(
+                f,
+                "World file version {found} is not supported, expected version {supported}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PersistenceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::Serialize(error) => Some(error),
+            Self::Deserialize(error) => Some(error),
+            Self::Binary(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for PersistenceError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<ron::Error> for PersistenceError {
+    fn from(error: ron::Error) -> Self {
+        Self::Serialize(error)
+    }
+}
+
+impl From<bincode::Error> for PersistenceError {
+    fn from(error: bincode::Error) -> Self {
+        Self::Binary(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for PersistenceError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        Self::Deserialize(error)
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename = "WorldFile")]
+struct WorldFileRef<'a> {
+    version: u32,
+    world: &'a crate::world::World,
+}
+
+#[derive(serde::Deserialize)]
+struct WorldFile {
+    #[serde(rename = "version")]
+    _version: u32,
+    world: crate::world::World,
+}
+
+// Version 1 stored each scene as a bare graph, before nodes had stable ids
+#[derive(serde::Deserialize)]
+#[serde(rename = "WorldFile")]
+struct WorldFileV1 {
+    #[serde(rename = "version")]
+    _version: u32,
+    world: WorldV1,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename = "World")]
+struct WorldV1 {
+    scenes: Vec<crate::world::SceneGraph>,
+    meshes: crate::world::MeshRegistry,
+    materials: crate::world::MaterialRegistry,
+    textures: crate::world::TextureRegistry,
+}
+
+impl From<WorldV1> for crate::world::World {
+    fn from(world: WorldV1) -> Self {
+        Self {
+            scenes: world
+                .scenes
+                .into_iter()
+                .map(crate::world::Scene::from)
+                .collect(),
+            meshes: world.meshes,
+            materials: world.materials,
+            textures: world.textures,
+            ..Default::default()
+        }
+    }
+}
+
+// Only the header is parsed first, so files from other versions are read with the schema
+// of their version, and newer files report a clear error instead of a schema mismatch
+#[derive(serde::Deserialize)]
+#[serde(rename = "WorldFile")]
+struct WorldFileHeader {
+    version: u32,
+    #[serde(rename = "world")]
+    _world: serde::de::IgnoredAny,
+}
+
+impl crate::world::World {
+    /// Writes the world to a human-readable RON file
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<(), PersistenceError> {
+        std::fs::write(path, self.to_ron_string()?)?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, PersistenceError> {
+        Self::from_ron_str(&std::fs::read_to_string(path)?)
+    }
+
+    /// Loads a world saved in either format, telling them apart by the binary magic bytes
+    pub fn load_any(path: impl AsRef<std::path::Path>) -> Result<Self, PersistenceError> {
+        let bytes = std::fs::read(path)?;
+        if bytes.starts_with(&BINARY_MAGIC) {
+            return Self::from_binary(&bytes);
+        }
+        let source = std::str::from_utf8(&bytes).map_err(|error| {
+            PersistenceError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+        })?;
+        Self::from_ron_str(source)
+    }
+
+    pub fn to_ron_string(&self) -> Result<String, PersistenceError> {
+        let file = WorldFileRef {
+            version: WORLD_FORMAT_VERSION,
+            world: self,
+        };
+        let config = ron::ser::PrettyConfig::default()
+            .struct_names(true)
+            .indentor("    ".to_string());
+        Ok(ron::ser::to_string_pretty(&file, config)?)
+    }
+
+    /// Parses a RON world file, upgrading files saved by older versions
+    pub fn from_ron_str(source: &str) -> Result<Self, PersistenceError> {
+        let options = ron::Options::default();
+        let header = options.from_str::<WorldFileHeader>(source)?;
+        // Fields added since version 2 default when missing, so the current schema reads those:
+        // 3 gave primitive meshes a material, 4 gave directional and spot lights shadows and
+        // 5 gave images the color space their file was saved in
+        match header.version {
+            1 => Ok(options.from_str::<WorldFileV1>(source)?.world.into()),
+            2..=WORLD_FORMAT_VERSION => Ok(options.from_str::<WorldFile>(source)?.world),
+            found => Err(PersistenceError::UnsupportedVersion {
+                found,
+                supported: WORLD_FORMAT_VERSION,
+            }),
+        }
+    }
+}
+
+/// Converts a RON world file into the binary format
+pub fn convert_text_to_binary(
+    source: impl AsRef<std::path::Path>,
+    destination: impl AsRef<std::path::Path>,
+) -> Result<(), PersistenceError> {
+    crate::world::World::load(source)?.save_binary(destination)
+}
+
+// Binary layout, all integers little-endian:
+//
+// header:      magic [u8; 4], version u32, chunk count u32, reserved u32
+// chunk table: per chunk: kind u32, reserved u32, offset u64, length u64
+// chunks:      each aligned to CHUNK_ALIGNMENT, chunk 0 is always the manifest
+//
+// The manifest is the world with bulk data stripped out. Vertex streams, indices and image
+// pixels live in their own chunks and are copied out directly rather than deserialized.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u32)]
+pub enum ChunkKind {
+    Manifest = 1,
+    VertexStream = 2,
+    Indices = 3,
+    ImagePixels = 4,
+}
+
+impl ChunkKind {
+    fn from_u32(value: u32) -> Option<Self> {
+        match value {
+            1 => Some(Self::Manifest),
+            2 => Some(Self::VertexStream),
+            3 => Some(Self::Indices),
+            4 => Some(Self::ImagePixels),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ChunkEntry {
+    pub kind: ChunkKind,
+    pub offset: u64,
+    pub length: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Manifest {
+    pub world: crate::world::World,
+    pub surfaces: Vec<SurfaceChunks>,
+    pub images: Vec<ImageChunk>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SurfaceChunks {
+    pub mesh: crate::world::MeshId,
+    pub surface: u32,
+    pub streams: Vec<(crate::world::VertexAttribute, u32)>,
+    pub indices: Option<IndexChunk>,
+}
+
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
+pub struct IndexChunk {
+    pub chunk: u32,
+    pub wide: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ImageChunk {
+    pub texture: crate::world::TextureId,
+    pub chunk: u32,
+}
+
+/// A parsed view over a binary world that borrows its bulk data from the source bytes,
+/// so chunks can be read lazily from a memory-mapped or streamed buffer
+pub struct BinaryWorld<'a> {
+    bytes: &'a [u8],
+    chunks: Vec<ChunkEntry>,
+    manifest: Manifest,
+}
+
+impl<'a> BinaryWorld<'a> {
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, PersistenceError> {
+        let header = bytes
+            .get(..HEADER_SIZE)
+            .ok_or(PersistenceError::Truncated)?;
+        if header[..4] != BINARY_MAGIC {
+            return Err(PersistenceError::InvalidMagic);
+        }
+        let version = read_u32(header, 4);
+        // Binary worlds are converted from RON ones, which is where older versions are upgraded
+        if version != WORLD_FORMAT_VERSION {
+            return Err(PersistenceError::UnsupportedVersion {
+                found: version,
+                supported: WORLD_FORMAT_VERSION,
+            });
+        }
+        let chunk_count = read_u32(header, 8) as usize;
+
+        // A corrupt count may not even be addressable, which can only mean a short file
+        let table_end = chunk_count
+            .checked_mul(CHUNK_ENTRY_SIZE)
+            .and_then(|table_size| table_size.checked_add(HEADER_SIZE))
+            .ok_or(PersistenceError::Truncated)?;
+        let table = bytes
+            .get(HEADER_SIZE..table_end)
+            .ok_or(PersistenceError::Truncated)?;
+        let chunks = table
+            .chunks_exact(CHUNK_ENTRY_SIZE)
+            .enumerate()
+            .map(|(index, entry)| {
+                let kind = ChunkKind::from_u32(read_u32(entry, 0)).ok_or(
+                    PersistenceError::InvalidChunk {
+                        index: index as u32,
+                    },
+                )?;
+                let chunk = ChunkEntry {
+                    kind,
+                    offset: read_u64(entry, 8),
+                    length: read_u64(entry, 16),
+                };
+                if chunk.offset.saturating_add(chunk.length) > bytes.len() as u64 {
+                    return Err(PersistenceError::Truncated);
+                }
+                Ok(chunk)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut binary_world = Self {
+            bytes,
+            chunks,
+            manifest: Manifest {
+                world: crate::world::World::default(),
+                surfaces: Vec::new(),
+                images: Vec::new(),
+            },
+        };
+        let manifest = binary_world.chunk(0, ChunkKind::Manifest)?;
+        binary_world.manifest = bincode::deserialize(manifest)?;
+        Ok(binary_world)
+    }
+
+    /// The world without vertex, index or pixel data
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    pub fn chunks(&self) -> &[ChunkEntry] {
+        &self.chunks
+    }
+
+    pub fn chunk(&self, index: u32, kind: ChunkKind) -> Result<&'a [u8], PersistenceError> {
+        let entry = self
+            .chunks
+            .get(index as usize)
+            .filter(|entry| entry.kind == kind)
+            .ok_or(PersistenceError::InvalidChunk { index })?;
+        Ok(&self.bytes[entry.offset as usize..(entry.offset + entry.length) as usize])
+    }
+
+    /// Copies every chunk back into a fully populated world
+    pub fn into_world(self) -> Result<crate::world::World, PersistenceError> {
+        let mut world = self.manifest.world.clone();
+
+        for surface_chunks in self.manifest.surfaces.iter() {
+            let Some(crate::world::Mesh::ArrayMesh(array_mesh)) =
+                world.meshes.get_mut(&surface_chunks.mesh)
+            else {
+                continue;
+            };
+            let Some(surface) = array_mesh.surfaces.get_mut(surface_chunks.surface as usize) else {
+                continue;
+            };
+            for (attribute, chunk) in surface_chunks.streams.iter() {
+                let bytes = self.chunk(*chunk, ChunkKind::VertexStream)?;
+                surface.attributes.set_stream_bytes(*attribute, bytes);
+            }
+            if let Some(index_chunk) = surface_chunks.indices {
+                let bytes = self.chunk(index_chunk.chunk, ChunkKind::Indices)?;
+                surface.indices = Some(if index_chunk.wide {
+                    crate::world::Indices::U32(bytemuck::pod_collect_to_vec(bytes))
+                } else {
+                    crate::world::Indices::U16(bytemuck::pod_collect_to_vec(bytes))
+                });
+            }
+        }
+
+        for image_chunk in self.manifest.images.iter() {
+            if let Some(texture) = world.textures.get_mut(&image_chunk.texture) {
+                texture.image.pixels = self
+                    .chunk(image_chunk.chunk, ChunkKind::ImagePixels)?
+                    .to_vec();
+            }
+        }
+
+        Ok(world)
+    }
+}
+
+struct BinaryWriter<'a> {
+    chunks: Vec<(ChunkKind, std::borrow::Cow<'a, [u8]>)>,
+}
+
+impl<'a> BinaryWriter<'a> {
+    fn push(&mut self, kind: ChunkKind, bytes: impl Into<std::borrow::Cow<'a, [u8]>>) -> u32 {
+        self.chunks.push((kind, bytes.into()));
+        self.chunks.len() as u32 - 1
+    }
+
+    fn finish(self) -> Vec<u8> {
+        let table_end = HEADER_SIZE + self.chunks.len() * CHUNK_ENTRY_SIZE;
+        let mut offset = table_end.next_multiple_of(CHUNK_ALIGNMENT);
+        let mut entries = Vec::with_capacity(self.chunks.len());
+        for (kind, bytes) in self.chunks.iter() {
+            entries.push((*kind, offset, bytes.len()));
+            offset = (offset + bytes.len()).next_multiple_of(CHUNK_ALIGNMENT);
+        }
+
+        let mut output = Vec::with_capacity(offset);
+        output.extend_from_slice(&BINARY_MAGIC);
+        output.extend_from_slice(&WORLD_FORMAT_VERSION.to_le_bytes());
+        output.extend_from_slice(&(self.chunks.len() as u32).to_le_bytes());
+        output.extend_from_slice(&0_u32.to_le_bytes());
+        for (kind, offset, length) in entries.iter() {
+            output.extend_from_slice(&(*kind as u32).to_le_bytes());
+            output.extend_from_slice(&0_u32.to_le_bytes());
+            output.extend_from_slice(&(*offset as u64).to_le_bytes());
+            output.extend_from_slice(&(*length as u64).to_le_bytes());
+        }
+        for ((_, bytes), (_, offset, _)) in self.chunks.iter().zip(entries.iter()) {
+            output.resize(*offset, 0);
+            output.extend_from_slice(bytes);
+        }
+        output
+    }
+}
+
+impl crate::world::World {
+    pub fn save_binary(&self, path: impl AsRef<std::path::Path>) -> Result<(), PersistenceError> {
+        std::fs::write(path, self.to_binary()?)?;
+        Ok(())
+    }
+
+    pub fn load_binary(path: impl AsRef<std::path::Path>) -> Result<Self, PersistenceError> {
+        Self::from_binary(&std::fs::read(path)?)
+    }
+
+    pub fn from_binary(bytes: &[u8]) -> Result<Self, PersistenceError> {
+        BinaryWorld::parse(bytes)?.into_world()
+    }
+
+    pub fn to_binary(&self) -> Result<Vec<u8>, PersistenceError> {
+        let mut writer = BinaryWriter { chunks: Vec::new() };
+        // Reserve chunk 0 for the manifest, which is encoded last
+        writer.push(ChunkKind::Manifest, Vec::new());
+
+        let mut manifest = Manifest {
+            world: Self {
+                scenes: self.scenes.clone(),
+                materials: self.materials.clone(),
+                ..Default::default()
+            },
+            surfaces: Vec::new(),
+            images: Vec::new(),
+        };
+
+        for (mesh_id, mesh) in self.meshes.iter() {
+            let crate::world::Mesh::ArrayMesh(array_mesh) = mesh else {
+                manifest.world.meshes.insert(mesh_id.clone(), mesh.clone());
+                continue;
+            };
+            let mut stripped_surfaces = Vec::with_capacity(array_mesh.surfaces.len());
+            for (surface_index, surface) in array_mesh.surfaces.iter().enumerate() {
+                let streams = surface
+                    .attributes
+                    .layout()
+                    .into_iter()
+                    .filter_map(|attribute| {
+                        let bytes = surface.attributes.stream_bytes(attribute)?;
+                        Some((attribute, writer.push(ChunkKind::VertexStream, bytes)))
+                    })
+                    .collect();
+                let indices = surface.indices.as_ref().map(|indices| match indices {
+                    crate::world::Indices::U16(indices) => IndexChunk {
+                        chunk: writer
+                            .push(ChunkKind::Indices, bytemuck::cast_slice::<u16, u8>(indices)),
+                        wide: false,
+                    },
+                    crate::world::Indices::U32(indices) => IndexChunk {
+                        chunk: writer
+                            .push(ChunkKind::Indices, bytemuck::cast_slice::<u32, u8>(indices)),
+                        wide: true,
+                    },
+                });
+                manifest.surfaces.push(SurfaceChunks {
+                    mesh: mesh_id.clone(),
+                    surface: surface_index as u32,
+                    streams,
+                    indices,
+                });
+                stripped_surfaces.push(crate::world::Surface {
+                    attributes: crate::world::VertexAttributes::default(),
+                    indices: None,
+                    topology: surface.topology,
+                    material: surface.material.clone(),
+                });
+            }
+            manifest.world.meshes.insert(
+                mesh_id.clone(),
+                crate::world::Mesh::ArrayMesh(crate::world::ArrayMesh {
+                    surfaces: stripped_surfaces,
+                }),
+            );
+        }
+
+        for (texture_id, texture) in self.textures.iter() {
+            manifest.images.push(ImageChunk {
+                texture: texture_id.clone(),
+                chunk: writer.push(ChunkKind::ImagePixels, texture.image.pixels.as_slice()),
+            });
+            manifest.world.textures.insert(
+                texture_id.clone(),
+                crate::world::Texture {
+                    image: crate::world::Image {
+                        width: texture.image.width,
+                        height: texture.image.height,
+                        pixels: Vec::new(),
+                        color_space: texture.image.color_space,
+                    },
+                    sampler: texture.sampler,
+                },
+            );
+        }
+
+        writer.chunks[0].1 = bincode::serialize(&manifest)?.into();
+        Ok(writer.finish())
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+/// Serializes a registry with its keys sorted so saved files diff cleanly
+pub(crate) fn serialize_sorted<S, V>(
+    map: &std::collections::HashMap<String, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+    V: serde::Serialize,
+{
+    serde::Serialize::serialize(
+        &map.iter().collect::<std::collections::BTreeMap<_, _>>(),
+        serializer,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        Camera3D, DirectionalLight3D, Geometry, Node, Node3D, OmniLight3D, SpotLight3D,
+        Transform3D, Viewport, VisualInstance3D, World,
+    };
+
+    fn node_3d(node: Node3D, x: f32) -> Node {
+        Node::Node3D {
+            transform: Transform3D {
+                translation: nalgebra_glm::vec3(x, 0.5, -2.25),
+                rotation: nalgebra_glm::quat(0.0, 0.38268343, 0.0, 0.9238795),
+                scale: nalgebra_glm::vec3(1.0, 2.0, 0.5),
+            },
+            node,
+        }
+    }
+
+    // A world using every node, mesh and light variant the format knows about
+    fn sample_world() -> World {
+        let mut world = World::default();
+        let mut scene = crate::world::Scene::default();
+        let viewport = scene.add_node(Node::Viewport(Viewport::Main {
+            dimension: crate::world::ViewportDimension {
+                width: 640,
+                height: 480,
+            },
+        }));
+        let nodes = [
+            Node::Empty,
+            Node::Viewport(Viewport::Empty),
+            Node::Viewport(Viewport::Sub {
+                dimension: crate::world::ViewportDimension {
+                    width: 320,
+                    height: 240,
+                },
+            }),
+            node_3d(Node3D::Empty, 0.0),
+            node_3d(
+                Node3D::Camera3D {
+                    camera: Camera3D::default(),
+                },
+                1.0,
+            ),
+            node_3d(
+                Node3D::Camera3D {
+                    camera: Camera3D {
+                        projection: crate::world::Projection::Perspective(
+                            crate::world::PerspectiveCamera {
+                                aspect_ratio: Some(1.5),
+                                y_fov_rad: 0.8,
+                                z_far: Some(250.0),
+                                z_near: 0.1,
+                            },
+                        ),
+                    },
+                },
+                2.0,
+            ),
+            node_3d(
+                Node3D::Camera3D {
+                    camera: Camera3D {
+                        projection: crate::world::Projection::Orthographic(
+                            crate::world::OrthographicCamera {
+                                x_mag: 4.0,
+                                y_mag: 3.0,
+                                z_far: 100.0,
+                                z_near: 0.5,
+                            },
+                        ),
+                    },
+                },
+                3.0,
+            ),
+            node_3d(
+                Node3D::DirectionalLight3D {
+                    light: DirectionalLight3D::default(),
+                },
+                4.0,
+            ),
+            node_3d(
+                Node3D::DirectionalLight3D {
+                    light: DirectionalLight3D {
+                        color: [1.0, 0.9, 0.8],
+                        intensity: 3.0,
+                        shadow: Some(crate::world::Shadow {
+                            depth_bias: 2.0,
+                            normal_bias: 0.5,
+                        }),
+                        cascades: crate::world::ShadowCascades {
+                            count: 2,
+                            max_distance: 30.0,
+                            split_blend: 0.25,
+                        },
+                    },
+                },
+                5.0,
+            ),
+            node_3d(
+                Node3D::OmniLight3D {
+                    light: OmniLight3D {
+                        color: [0.2, 0.4, 1.0],
+                        intensity: 8.0,
+                        range: 6.0,
+                    },
+                },
+                6.0,
+            ),
+            node_3d(
+                Node3D::SpotLight3D {
+                    light: SpotLight3D {
+                        inner_cone_angle_rad: 0.2,
+                        shadow: Some(crate::world::Shadow::default()),
+                        ..Default::default()
+                    },
+                },
+                7.0,
+            ),
+            Node::VisualInstance3D(VisualInstance3D::Empty),
+            Node::VisualInstance3D(VisualInstance3D::Geometry(Geometry::Label3D)),
+            Node::VisualInstance3D(VisualInstance3D::Geometry(Geometry::SpriteBase3D(
+                crate::world::SpriteBase3D::Sprite3D,
+            ))),
+            Node::VisualInstance3D(VisualInstance3D::Geometry(Geometry::MeshInstance3D(
+                crate::world::MeshInstance3D {
+                    mesh_reference: Some("triangle".to_string()),
+                },
+            ))),
+            Node::VisualInstance3D(VisualInstance3D::Geometry(Geometry::MeshInstance3D(
+                crate::world::MeshInstance3D {
+                    mesh_reference: None,
+                },
+            ))),
+        ];
+        let mut parent = viewport;
+        for node in nodes {
+            let index = scene.add_node(node);
+            scene.add_edge(parent, index);
+            parent = index;
+        }
+        world.scenes.push(scene);
+        world.scenes.push(crate::world::Scene::default());
+
+        world.meshes.insert(
+            "triangle".to_string(),
+            crate::world::Mesh::ArrayMesh(crate::world::ArrayMesh {
+                surfaces: vec![
+                    crate::world::Surface {
+                        attributes: crate::world::VertexAttributes {
+                            positions: vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+                            normals: Some(vec![[0.0, 0.0, 1.0]; 3]),
+                            uv0: Some(vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]),
+                            colors: Some(vec![[1.0, 0.5, 0.25, 1.0]; 3]),
+                            ..Default::default()
+                        },
+                        indices: Some(crate::world::Indices::U16(vec![0, 1, 2])),
+                        topology: crate::world::PrimitiveTopology::TriangleList,
+                        material: Some("textured".to_string()),
+                    },
+                    crate::world::Surface {
+                        attributes: crate::world::VertexAttributes {
+                            positions: vec![[0.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+                            ..Default::default()
+                        },
+                        indices: Some(crate::world::Indices::U32(vec![0, 1])),
+                        topology: crate::world::PrimitiveTopology::LineList,
+                        material: None,
+                    },
+                ],
+            }),
+        );
+        world.meshes.insert(
+            "sphere".to_string(),
+            crate::world::Mesh::PrimitiveMesh(crate::world::PrimitiveMesh {
+                shape: crate::world::PrimitiveShape::Sphere(crate::world::SphereShape::default()),
+                material: Some("textured".to_string()),
+            }),
+        );
+        world
+            .meshes
+            .insert("empty".to_string(), crate::world::Mesh::Empty);
+
+        world.materials.insert(
+            "textured".to_string(),
+            crate::world::Material {
+                base_color_factor: [0.5, 0.25, 1.0, 1.0],
+                base_color_texture: Some(crate::world::TextureReference {
+                    texture: "checker".to_string(),
+                    uv_set: 0,
+                }),
+                alpha_mode: crate::world::AlphaMode::Mask { cutoff: 0.5 },
+                double_sided: true,
+                ..Default::default()
+            },
+        );
+        world.textures.insert(
+            "checker".to_string(),
+            crate::world::Texture {
+                image: crate::world::Image {
+                    width: 2,
+                    height: 1,
+                    pixels: vec![255, 255, 255, 255, 0, 0, 0, 255],
+                    color_space: Some(crate::texture::ColorSpace::Linear),
+                },
+                sampler: crate::world::Sampler {
+                    mag_filter: crate::world::Filter::Nearest,
+                    wrap_u: crate::world::WrapMode::ClampToEdge,
+                    ..Default::default()
+                },
+            },
+        );
+        world
+    }
+
+    #[test]
+    fn ron_round_trip_preserves_the_world() {
+        let world = sample_world();
+        let saved = world.to_ron_string().unwrap();
+        let loaded = World::from_ron_str(&saved).unwrap();
+
+        // Registries are written sorted, so equal worlds serialize identically
+        assert_eq!(loaded.to_ron_string().unwrap(), saved);
+        assert_eq!(loaded.scenes.len(), 2);
+        let (scene, loaded_scene) = (&world.scenes[0], &loaded.scenes[0]);
+        assert_eq!(loaded_scene.node_count(), scene.node_count());
+        assert_eq!(loaded_scene.edge_count(), scene.edge_count());
+        for index in scene.node_indices() {
+            assert_eq!(loaded_scene.node_id(index), scene.node_id(index));
+        }
+        assert_eq!(loaded.materials["textured"], world.materials["textured"]);
+        assert_eq!(
+            loaded.textures["checker"].image.pixels,
+            [255, 255, 255, 255, 0, 0, 0, 255]
+        );
+    }
+
+    #[test]
+    fn ron_files_round_trip_through_disk() {
+        let path = std::env::temp_dir().join(format!("world-{}.ron", uuid::Uuid::new_v4()));
+        let world = sample_world();
+        world.save(&path).unwrap();
+        let loaded = World::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            loaded.unwrap().to_ron_string().unwrap(),
+            world.to_ron_string().unwrap()
+        );
+    }
+
+    #[test]
+    fn ron_rejects_newer_versions() {
+        let saved = sample_world().to_ron_string().unwrap();
+        let current = format!("version: {WORLD_FORMAT_VERSION},");
+        assert!(saved.contains(&current));
+        let newer = saved.replacen(
+            &current,
+            &format!("version: {},", WORLD_FORMAT_VERSION + 1),
+            1,
+        );
+        assert!(matches!(
+            World::from_ron_str(&newer),
+            Err(PersistenceError::UnsupportedVersion { found, supported })
+                if found == WORLD_FORMAT_VERSION + 1 && supported == WORLD_FORMAT_VERSION
+        ));
+    }
+
+    #[test]
+    fn version_1_scenes_are_given_node_ids() {
+        let world = World::from_ron_str(include_str!("../fixtures/world_v1.ron")).unwrap();
+        assert_eq!(world.scenes.len(), 1);
+        let scene = &world.scenes[0];
+        assert_eq!(scene.node_count(), 2);
+        assert_eq!(scene.edge_count(), 1);
+        let ids = scene
+            .node_indices()
+            .map(|index| scene.node_id(index).unwrap())
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(ids.len(), 2);
+        for id in ids {
+            assert!(scene.node(id).is_some());
+        }
+        assert!(world.meshes.contains_key("triangle"));
+
+        // Upgraded worlds are saved with the current version
+        let saved = world.to_ron_string().unwrap();
+        assert!(saved.contains(&format!("version: {WORLD_FORMAT_VERSION},")));
+        World::from_ron_str(&saved).unwrap();
+    }
+
+    #[test]
+    fn version_2_primitive_meshes_have_no_material() {
+        let world = World::from_ron_str(include_str!("../fixtures/world_v2.ron")).unwrap();
+        let crate::world::Mesh::PrimitiveMesh(mesh) = &world.meshes["box"] else {
+            panic!("box is not a primitive mesh");
+        };
+        assert!(mesh.material.is_none());
+        assert_eq!(world.scenes[0].node_count(), 1);
+    }
+
+    #[test]
+    fn version_3_lights_cast_no_shadows() {
+        let world = World::from_ron_str(include_str!("../fixtures/world_v3.ron")).unwrap();
+        let scene = &world.scenes[0];
+        let lights = scene
+            .node_weights()
+            .filter_map(|node| match node {
+                Node::Node3D { node, .. } => Some(node),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let [Node3D::DirectionalLight3D { light: directional }, Node3D::SpotLight3D { light: spot }] =
+            lights[..]
+        else {
+            panic!("expected a directional and a spot light, found {lights:?}");
+        };
+        assert_eq!(directional.intensity, 2.0);
+        assert_eq!(directional.shadow, None);
+        assert_eq!(
+            directional.cascades,
+            crate::world::ShadowCascades::default()
+        );
+        assert_eq!(spot.range, 8.0);
+        assert_eq!(spot.shadow, None);
+    }
+
+    #[test]
+    fn text_worlds_convert_to_binary_and_back() {
+        let directory = std::env::temp_dir();
+        let id = uuid::Uuid::new_v4();
+        let text_path = directory.join(format!("world-{id}.ron"));
+        let binary_path = directory.join(format!("world-{id}.spwb"));
+        let world = sample_world();
+        world.save(&text_path).unwrap();
+        let converted = convert_text_to_binary(&text_path, &binary_path);
+        let loaded = converted.and_then(|_| World::load_any(&binary_path));
+        std::fs::remove_file(&text_path).unwrap();
+        let _ = std::fs::remove_file(&binary_path);
+
+        assert_eq!(
+            loaded.unwrap().to_ron_string().unwrap(),
+            world.to_ron_string().unwrap()
+        );
+    }
+
+    #[test]
+    fn binary_chunks_are_aligned_and_manifests_hold_no_bulk_data() {
+        let bytes = sample_world().to_binary().unwrap();
+        let binary = BinaryWorld::parse(&bytes).unwrap();
+        assert_eq!(binary.chunks()[0].kind, ChunkKind::Manifest);
+        for chunk in binary.chunks() {
+            assert_eq!(chunk.offset as usize % CHUNK_ALIGNMENT, 0);
+        }
+        let manifest = binary.manifest();
+        assert!(manifest.world.textures["checker"].image.pixels.is_empty());
+        let crate::world::Mesh::ArrayMesh(mesh) = &manifest.world.meshes["triangle"] else {
+            panic!("triangle is not an array mesh");
+        };
+        assert!(mesh.surfaces[0].attributes.positions.is_empty());
+        assert!(mesh.surfaces[0].indices.is_none());
+    }
+
+    #[test]
+    fn truncated_binary_worlds_are_rejected() {
+        let bytes = sample_world().to_binary().unwrap();
+        let binary = BinaryWorld::parse(&bytes).unwrap();
+        let last_chunk = binary
+            .chunks()
+            .iter()
+            .max_by_key(|chunk| chunk.offset)
+            .unwrap();
+        let data_end = (last_chunk.offset + last_chunk.length) as usize;
+        // Inside the header, the chunk table and the last chunk
+        for length in [0, 3, HEADER_SIZE - 1, HEADER_SIZE + 1, data_end - 1] {
+            assert!(
+                matches!(
+                    BinaryWorld::parse(&bytes[..length]),
+                    Err(PersistenceError::Truncated)
+                ),
+                "accepted {length} of {} bytes",
+                bytes.len()
+            );
+        }
+    }
+
+    #[test]
+    fn binary_chunk_counts_past_the_end_are_rejected() {
+        let mut bytes = sample_world().to_binary().unwrap();
+        bytes[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(
+            BinaryWorld::parse(&bytes),
+            Err(PersistenceError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn binary_worlds_need_the_magic_bytes() {
+        let mut bytes = sample_world().to_binary().unwrap();
+        bytes[..4].copy_from_slice(b"RIFF");
+        assert!(matches!(
+            World::from_binary(&bytes),
+            Err(PersistenceError::InvalidMagic)
+        ));
+    }
+
+    #[test]
+    fn binary_worlds_need_the_current_version() {
+        let mut bytes = sample_world().to_binary().unwrap();
+        for version in [WORLD_FORMAT_VERSION - 1, WORLD_FORMAT_VERSION + 1] {
+            bytes[4..8].copy_from_slice(&version.to_le_bytes());
+            assert!(matches!(
+                World::from_binary(&bytes),
+                Err(PersistenceError::UnsupportedVersion { found, supported })
+                    if found == version && supported == WORLD_FORMAT_VERSION
+            ));
+        }
+    }
+
+    #[test]
+    fn binary_chunks_of_the_wrong_kind_are_rejected() {
+        let mut bytes = sample_world().to_binary().unwrap();
+        // Turn the manifest into a vertex stream
+        bytes[HEADER_SIZE..HEADER_SIZE + 4].copy_from_slice(&2_u32.to_le_bytes());
+        assert!(matches!(
+            World::from_binary(&bytes),
+            Err(PersistenceError::InvalidChunk { index: 0 })
+        ));
+    }
+}
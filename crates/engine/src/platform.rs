@@ -10,40 +10,106 @@ use wasm_bindgen::prelude::*;
 #[derive(Default)]
 pub struct EngineContext {
     pub pending_messages: Vec<crate::EngineMessage>,
+    pub events: crate::event::Events,
+    pub time: crate::time::Time,
+    pub input: crate::input::Input,
     pub world: crate::world::World,
 }
 
 pub trait State {
+    /// Called once after the window and renderer are created, before the first frame
+    fn on_start(
+        &mut self,
+        _engine_context: &mut EngineContext,
+        _renderer: &mut crate::Renderer<'_>,
+    ) {
+    }
+
+    /// Called on native after the gpu device was lost and a new renderer replaced the old
+    /// one, so anything made on the old device in `on_start` can be made again
+    fn on_renderer_recreated(
+        &mut self,
+        _engine_context: &mut EngineContext,
+        _renderer: &mut crate::Renderer<'_>,
+    ) {
+    }
+
+    /// Called at a fixed rate independent of the frame rate, before `update`
+    fn fixed_update(&mut self, _engine_context: &mut EngineContext, _fixed_delta_time: f32) {}
+
     fn update(&mut self, _engine_context: &mut EngineContext, _ui_context: &egui::Context);
-}
 
-#[derive(Default)]
-pub struct LaunchSettings {
-    pub window_title: String,
+    /// Receives every window event the gui didn't consume, before the engine handles it
+    fn on_window_event(
+        &mut self,
+        _engine_context: &mut EngineContext,
+        _event: &winit::event::WindowEvent,
+    ) {
+    }
+
+    /// Called when the window's close button is pressed or the quit action is triggered.
+    /// Return false to keep running, e.g. to ask about unsaved changes first and send
+    /// `EngineMessage::Quit` once confirmed.
+    fn on_close_requested(&mut self, _engine_context: &mut EngineContext) -> bool {
+        true
+    }
+
+    /// Called after the renderer has been resized, in physical pixels
+    fn on_resize(&mut self, _engine_context: &mut EngineContext, _width: u32, _height: u32) {}
+
+    /// Called once when the event loop is exiting, for any reason
+    fn on_exit(&mut self, _engine_context: &mut EngineContext) {}
 }
 
-pub fn start(state: impl State + 'static, _settings: LaunchSettings) {
+pub fn start(state: impl State + 'static, settings: crate::LaunchSettings) {
+    #[cfg(not(target_arch = "wasm32"))]
+    env_logger::init();
+
+    #[cfg(target_arch = "wasm32")]
+    {
+        std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+        console_log::init().expect("could not initialize logger");
+    }
+
+    let settings = settings.with_command_line();
+
     let event_loop = winit::event_loop::EventLoopBuilder::with_user_event()
         .build()
         .expect("Failed to create event loop");
-    let mut window_builder = winit::window::WindowBuilder::new();
+    let mut window_builder = winit::window::WindowBuilder::new()
+        .with_title(settings.window_title.clone())
+        .with_resizable(settings.resizable);
 
+    // On the web an inner size would be written into the canvas's style, overriding the page
     #[cfg(not(target_arch = "wasm32"))]
     {
-        window_builder = window_builder.with_title(_settings.window_title);
+        window_builder = window_builder.with_inner_size(winit::dpi::LogicalSize::new(
+            settings.width,
+            settings.height,
+        ));
+    }
+
+    if let Some((width, height)) = settings.min_size {
+        window_builder =
+            window_builder.with_min_inner_size(winit::dpi::LogicalSize::new(width, height));
     }
 
     #[cfg(target_arch = "wasm32")]
     {
         use winit::platform::web::WindowBuilderExtWebSys;
-        let canvas = web_sys::window()
-            .unwrap()
-            .document()
-            .unwrap()
-            .get_element_by_id("canvas")
-            .unwrap()
+        let document = web_sys::window().unwrap().document().unwrap();
+        document.set_title(&settings.window_title);
+        let canvas = document
+            .get_element_by_id(&settings.canvas_id)
+            .unwrap_or_else(|| panic!("No canvas element with id '{}'", settings.canvas_id))
             .dyn_into::<web_sys::HtmlCanvasElement>()
             .unwrap();
+        if settings.fit_canvas_to_parent {
+            let style = canvas.style();
+            style.set_property("width", "100%").unwrap();
+            style.set_property("height", "100%").unwrap();
+            style.set_property("display", "block").unwrap();
+        }
         window_builder = window_builder.with_canvas(Some(canvas));
     }
 
@@ -51,17 +117,35 @@ pub fn start(state: impl State + 'static, _settings: LaunchSettings) {
         .build(&event_loop)
         .expect("Failed to create window!");
 
+    window.set_fullscreen(fullscreen_mode(&window, settings.window_mode));
+
     #[cfg(not(target_arch = "wasm32"))]
-    {
-        env_logger::init();
-        pollster::block_on(run(event_loop, window, state));
-    }
+    pollster::block_on(run(event_loop, window, state, settings));
 
     #[cfg(target_arch = "wasm32")]
-    {
-        std::panic::set_hook(Box::new(console_error_panic_hook::hook));
-        console_log::init().expect("could not initialize logger");
-        wasm_bindgen_futures::spawn_local(run(event_loop, window, state));
+    wasm_bindgen_futures::spawn_local(run(event_loop, window, state, settings));
+}
+
+// Exclusive fullscreen uses the primary monitor's largest video mode, or borderless without one
+fn fullscreen_mode(
+    window: &winit::window::Window,
+    window_mode: crate::WindowMode,
+) -> Option<winit::window::Fullscreen> {
+    match window_mode {
+        crate::WindowMode::Windowed => None,
+        crate::WindowMode::Borderless => Some(winit::window::Fullscreen::Borderless(None)),
+        crate::WindowMode::Fullscreen => {
+            let video_mode = window.primary_monitor().and_then(|monitor| {
+                monitor.video_modes().max_by_key(|mode| {
+                    let size = mode.size();
+                    (size.width * size.height, mode.refresh_rate_millihertz())
+                })
+            });
+            Some(match video_mode {
+                Some(video_mode) => winit::window::Fullscreen::Exclusive(video_mode),
+                None => winit::window::Fullscreen::Borderless(None),
+            })
+        }
     }
 }
 
@@ -69,6 +153,7 @@ async fn run(
     event_loop: winit::event_loop::EventLoop<()>,
     window: winit::window::Window,
     mut state: impl State + 'static,
+    settings: crate::LaunchSettings,
 ) {
     let window = std::sync::Arc::new(window);
 
@@ -88,79 +173,172 @@ async fn run(
     event_loop.set_control_flow(winit::event_loop::ControlFlow::Poll);
 
     #[cfg(not(target_arch = "wasm32"))]
-    let (width, height) = (window.inner_size().width, window.inner_size().height.min(1));
+    let (width, height) = (
+        window.inner_size().width.max(1),
+        window.inner_size().height.max(1),
+    );
 
     #[cfg(target_arch = "wasm32")]
-    let (width, height) = (1280, 720);
-
-    let mut renderer = crate::renderer::Renderer::new(window.clone(), width, height).await;
+    let (width, height) = {
+        use winit::platform::web::WindowExtWebSys;
+        let canvas = window.canvas().expect("The window is backed by a canvas");
+        let (css_width, css_height) = match (canvas.client_width(), canvas.client_height()) {
+            (0, _) | (_, 0) => (settings.width as f64, settings.height as f64),
+            (width, height) => (width as f64, height as f64),
+        };
+        canvas_surface_size(css_width, css_height, window.scale_factor())
+    };
+
+    let mut renderer =
+        crate::renderer::Renderer::new(window.clone(), width, height, &settings.renderer).await;
 
     let mut engine_context = EngineContext::default();
 
-    let mut last_render_time = Instant::now();
+    let mut transform_error = None;
+
+    let mut frame_capturer = crate::capture::FrameCapturer::default();
+
+    state.on_start(&mut engine_context, &mut renderer);
+
+    // Empty while a lost renderer is being replaced, its surface has to be released before
+    // the window can be given a new one. On the web it stays empty once the device is lost.
+    let mut renderer = Some(renderer);
+
+    let mut last_frame = Instant::now();
+
+    let mut minimized = false;
+
+    // Set after the renderer is recreated, its egui textures have to be sent again
+    let mut reupload_gui_textures = false;
 
     event_loop
         .run(move |event, elwt| {
             match event {
-                winit::event::Event::AboutToWait => window.request_redraw(),
+                winit::event::Event::AboutToWait => {
+                    // Rendering pauses while minimized, checking back a few times a second
+                    let size = window.inner_size();
+                    let is_minimized = window.is_minimized().unwrap_or(false)
+                        || size.width == 0
+                        || size.height == 0;
+                    if is_minimized != minimized {
+                        log::info!("Window minimized: {is_minimized}");
+                        minimized = is_minimized;
+                        last_frame = Instant::now();
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    elwt.set_control_flow(match minimized {
+                        true => {
+                            winit::event_loop::ControlFlow::wait_duration(MINIMIZED_POLL_INTERVAL)
+                        }
+                        false => winit::event_loop::ControlFlow::Poll,
+                    });
+                    if !minimized {
+                        window.request_redraw();
+                    }
+                }
+
+                winit::event::Event::LoopExiting => state.on_exit(&mut engine_context),
 
                 winit::event::Event::WindowEvent { ref event, .. } => {
                     // Receive gui window event
-                    if gui_state.on_window_event(&window, event).consumed {
+                    let consumed = gui_state.on_window_event(&window, event).consumed;
+                    engine_context.input.handle_window_event(event, consumed);
+                    if consumed {
                         return;
                     }
 
+                    state.on_window_event(&mut engine_context, event);
+
                     // If the gui didn't consume the event, handle it
                     match event {
-                        winit::event::WindowEvent::KeyboardInput {
-                            event:
-                                winit::event::KeyEvent {
-                                    physical_key: winit::keyboard::PhysicalKey::Code(key_code),
-                                    ..
-                                },
-                            ..
-                        } => {
-                            // Exit by pressing the escape key
-                            if matches!(key_code, winit::keyboard::KeyCode::Escape) {
-                                elwt.exit();
-                            }
-                        }
-
-                        // Close button handler
-                        winit::event::WindowEvent::CloseRequested => {
+                        winit::event::WindowEvent::CloseRequested
+                            if state.on_close_requested(&mut engine_context) =>
+                        {
                             log::info!("The close button was pressed; stopping");
                             elwt.exit();
                         }
 
-                        #[cfg(not(target_arch = "wasm32"))]
+                        // Some platforms report a zero size when minimized
+                        winit::event::WindowEvent::Resized(size)
+                            if size.width == 0 || size.height == 0 => {}
+
+                        // On the web this follows the canvas's CSS size and device pixel ratio
                         winit::event::WindowEvent::Resized(winit::dpi::PhysicalSize {
                             width,
                             height,
                         }) => {
-                            let (width, height) = ((*width).max(1), (*height).max(1));
+                            let Some(renderer) = renderer.as_mut() else {
+                                return;
+                            };
                             log::info!("Resizing renderer surface to: ({width}, {height})");
-                            renderer.resize(width, height);
+                            renderer.resize(*width, *height);
+                            let (width, height) = renderer.size();
+                            state.on_resize(&mut engine_context, width, height);
+                            engine_context
+                                .events
+                                .push(crate::EngineEvent::Resized { width, height });
                         }
 
-                        winit::event::WindowEvent::RedrawRequested => {
-                            let now = Instant::now();
-                            let delta_time = now - last_render_time;
-                            last_render_time = now;
+                        winit::event::WindowEvent::Focused(focused) => {
+                            engine_context
+                                .events
+                                .push(crate::EngineEvent::FocusChanged { focused: *focused });
+                        }
+
+                        winit::event::WindowEvent::DroppedFile(path) => {
+                            engine_context
+                                .events
+                                .push(crate::EngineEvent::FileDropped { path: path.clone() });
+                        }
 
+                        winit::event::WindowEvent::RedrawRequested if minimized => {}
+
+                        winit::event::WindowEvent::RedrawRequested => {
                             let gui_input = gui_state.take_egui_input(&window);
                             gui_state.egui_ctx().begin_frame(gui_input);
 
+                            engine_context.events.update();
+
+                            let now = Instant::now();
+                            engine_context.time.update(now - last_frame);
+                            last_frame = now;
+                            while engine_context.time.next_fixed_step() {
+                                let fixed_delta_time = engine_context.time.fixed_delta_seconds();
+                                state.fixed_update(&mut engine_context, fixed_delta_time);
+                            }
+
                             state.update(&mut engine_context, gui_state.egui_ctx());
 
-                            engine_context
-                                .pending_messages
-                                .drain(..)
-                                .for_each(|message| match message {
-                                    crate::EngineMessage::Empty => {
-                                        log::info!("Empty message received");
-                                    }
+                            if engine_context
+                                .input
+                                .action_just_pressed(crate::input::ActionMap::QUIT)
+                                && state.on_close_requested(&mut engine_context)
+                            {
+                                log::info!("Quit action triggered; stopping");
+                                elwt.exit();
+                            }
+
+                            std::mem::take(&mut engine_context.pending_messages)
+                                .into_iter()
+                                .for_each(|message| {
+                                    handle_message(
+                                        message,
+                                        &mut engine_context,
+                                        &window,
+                                        elwt,
+                                        &mut frame_capturer,
+                                    )
                                 });
 
+                            // Only report transform errors when they change to avoid logging every frame
+                            let result = engine_context.world.update_transforms().err();
+                            if result != transform_error {
+                                if let Some(error) = result.as_ref() {
+                                    log::error!("{error}");
+                                }
+                                transform_error = result;
+                            }
+
                             let egui::FullOutput {
                                 textures_delta,
                                 shapes,
@@ -168,32 +346,80 @@ async fn run(
                                 ..
                             } = gui_state.egui_ctx().end_frame();
 
+                            let mut textures_delta = textures_delta;
+                            if std::mem::take(&mut reupload_gui_textures) {
+                                let font_image = gui_state.egui_ctx().fonts(|fonts| fonts.image());
+                                textures_delta.set.insert(
+                                    0,
+                                    (
+                                        egui::TextureId::default(),
+                                        egui::epaint::ImageDelta::full(
+                                            font_image,
+                                            egui::TextureOptions::default(),
+                                        ),
+                                    ),
+                                );
+                            }
+
                             let paint_jobs =
                                 gui_state.egui_ctx().tessellate(shapes, pixels_per_point);
 
-                            #[cfg(not(target_arch = "wasm32"))]
-                            let screen_descriptor = {
-                                let window_size = window.inner_size();
-                                egui_wgpu::ScreenDescriptor {
-                                    size_in_pixels: [window_size.width, window_size.height],
+                            // The application keeps running without a renderer to draw with
+                            if let Some(current_renderer) = renderer.as_mut() {
+                                let (width, height) = current_renderer.size();
+                                let screen_descriptor = egui_wgpu::ScreenDescriptor {
+                                    size_in_pixels: [width, height],
                                     pixels_per_point: window.scale_factor() as f32,
+                                };
+
+                                let captured_frame = current_renderer.render_frame(
+                                    screen_descriptor,
+                                    paint_jobs,
+                                    textures_delta,
+                                    &engine_context.world,
+                                    frame_capturer.request(),
+                                );
+                                if let Some(captured_frame) = captured_frame {
+                                    frame_capturer.finish_frame(captured_frame);
                                 }
-                            };
 
-                            #[cfg(target_arch = "wasm32")]
-                            let screen_descriptor = {
-                                egui_wgpu::ScreenDescriptor {
-                                    size_in_pixels: [1280, 720],
-                                    pixels_per_point: window.scale_factor() as f32,
+                                if let Some(reason) = current_renderer.take_device_lost() {
+                                    // There is no blocking on the web, so only native can rebuild
+                                    #[cfg(not(target_arch = "wasm32"))]
+                                    {
+                                        log::info!("Recreating the renderer");
+                                        let (width, height) = current_renderer.size();
+                                        // Two surfaces for one window at once can be rejected
+                                        drop(renderer.take());
+                                        let mut new_renderer =
+                                            pollster::block_on(crate::renderer::Renderer::new(
+                                                window.clone(),
+                                                width,
+                                                height,
+                                                &settings.renderer,
+                                            ));
+                                        state.on_renderer_recreated(
+                                            &mut engine_context,
+                                            &mut new_renderer,
+                                        );
+                                        renderer = Some(new_renderer);
+                                        gui_state.egui_ctx().forget_all_images();
+                                        reupload_gui_textures = true;
+                                    }
+                                    #[cfg(target_arch = "wasm32")]
+                                    {
+                                        log::error!(
+                                            "The gpu device was lost, rendering has stopped"
+                                        );
+                                        renderer = None;
+                                    }
+                                    engine_context
+                                        .events
+                                        .push(crate::EngineEvent::DeviceLost { reason });
                                 }
-                            };
+                            }
 
-                            renderer.render_frame(
-                                screen_descriptor,
-                                paint_jobs,
-                                textures_delta,
-                                delta_time,
-                            );
+                            engine_context.input.end_frame();
                         }
 
                         _ => {}
@@ -205,3 +431,158 @@ async fn run(
         })
         .unwrap();
 }
+
+#[cfg(not(target_arch = "wasm32"))]
+const MINIMIZED_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Size in physical pixels of a canvas laid out at the given CSS size.
+///
+/// Browsers report fractional CSS sizes and device pixel ratios (e.g. 1.25 when zoomed),
+/// so the product is rounded to the nearest pixel and never collapses to zero.
+pub fn canvas_surface_size(css_width: f64, css_height: f64, device_pixel_ratio: f64) -> (u32, u32) {
+    let physical = |css: f64| {
+        // `max` rather than `clamp` so a NaN from a detached canvas also becomes 1
+        (css * device_pixel_ratio)
+            .round()
+            .max(1.0)
+            .min(u32::MAX as f64) as u32
+    };
+    (physical(css_width), physical(css_height))
+}
+
+/// Fits a requested surface size to the device, between 1x1 and its largest texture size
+pub fn clamp_surface_size(width: u32, height: u32, max_dimension: u32) -> (u32, u32) {
+    (
+        width.clamp(1, max_dimension),
+        height.clamp(1, max_dimension),
+    )
+}
+
+// Applies a command queued by the application during `State::update`
+fn handle_message(
+    message: crate::EngineMessage,
+    engine_context: &mut EngineContext,
+    window: &winit::window::Window,
+    elwt: &winit::event_loop::EventLoopWindowTarget<()>,
+    frame_capturer: &mut crate::capture::FrameCapturer,
+) {
+    let world = &mut engine_context.world;
+    let result = match message {
+        crate::EngineMessage::Empty => {
+            log::info!("Empty message received");
+            Ok(())
+        }
+        crate::EngineMessage::Screenshot { path, include_ui } => {
+            frame_capturer.screenshot(path, include_ui);
+            Ok(())
+        }
+        crate::EngineMessage::StartCapture {
+            directory,
+            include_ui,
+            frame_count,
+        } => {
+            frame_capturer.start_sequence(directory, include_ui, frame_count);
+            Ok(())
+        }
+        crate::EngineMessage::StopCapture => {
+            frame_capturer.stop_sequence();
+            Ok(())
+        }
+        crate::EngineMessage::SpawnNode {
+            scene_index,
+            id,
+            node,
+            parent,
+        } => world.spawn_node(scene_index, id, node, parent).map(|_| ()),
+        crate::EngineMessage::DespawnNode { id } => world.despawn_node(id),
+        crate::EngineMessage::ReparentNode { id, parent } => world.reparent_node(id, parent),
+        crate::EngineMessage::SetTransform { id, transform } => {
+            world.set_node_transform(id, transform)
+        }
+        crate::EngineMessage::LoadScene { path } => {
+            let event = load_scene(world, path);
+            engine_context.events.push(event);
+            Ok(())
+        }
+        crate::EngineMessage::UnloadScene { scene_index } => {
+            world.unload_scene(scene_index).map(|_| ())
+        }
+        crate::EngineMessage::SetActiveCamera { id } => world.set_active_camera(id),
+        crate::EngineMessage::SetWindowTitle { title } => {
+            window.set_title(&title);
+            Ok(())
+        }
+        crate::EngineMessage::ToggleFullscreen => {
+            window.set_fullscreen(match window.fullscreen() {
+                Some(_) => None,
+                None => Some(winit::window::Fullscreen::Borderless(None)),
+            });
+            Ok(())
+        }
+        crate::EngineMessage::Quit => {
+            log::info!("Quit message received; stopping");
+            elwt.exit();
+            Ok(())
+        }
+    };
+    if let Err(error) = result {
+        log::error!("{error}");
+    }
+}
+
+// glTF files are imported, anything else is treated as a saved world
+fn load_scene(world: &mut crate::world::World, path: std::path::PathBuf) -> crate::EngineEvent {
+    let is_gltf = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| matches!(extension, "gltf" | "glb"));
+    let loaded = if is_gltf {
+        crate::import::import_gltf(&path).map_err(|error| error.to_string())
+    } else {
+        crate::world::World::load_any(&path).map_err(|error| error.to_string())
+    };
+    match loaded {
+        Ok(loaded) => {
+            let scenes = world.merge(loaded);
+            log::info!("Loaded {} scene(s) from {}", scenes.len(), path.display());
+            crate::EngineEvent::SceneLoaded { path, scenes }
+        }
+        Err(error) => {
+            log::error!("Failed to load scene {}: {error}", path.display());
+            crate::EngineEvent::AssetFailed { path, error }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canvas_size_scales_by_device_pixel_ratio() {
+        assert_eq!(canvas_surface_size(800.0, 600.0, 1.0), (800, 600));
+        assert_eq!(canvas_surface_size(800.0, 600.0, 2.0), (1600, 1200));
+    }
+
+    #[test]
+    fn canvas_size_rounds_fractional_pixels() {
+        // 1.25 is a common zoomed ratio, 333.3 * 1.25 = 416.625
+        assert_eq!(canvas_surface_size(333.3, 100.1, 1.25), (417, 125));
+        assert_eq!(canvas_surface_size(100.2, 100.6, 1.0), (100, 101));
+    }
+
+    #[test]
+    fn canvas_size_never_collapses_to_zero() {
+        assert_eq!(canvas_surface_size(0.0, 0.0, 1.0), (1, 1));
+        assert_eq!(canvas_surface_size(-10.0, 50.0, 2.0), (1, 100));
+        assert_eq!(canvas_surface_size(0.2, 0.4, 1.0), (1, 1));
+        assert_eq!(canvas_surface_size(f64::NAN, 10.0, 1.0), (1, 10));
+    }
+
+    #[test]
+    fn surface_size_is_clamped_to_the_device() {
+        assert_eq!(clamp_surface_size(1600, 1200, 2048), (1600, 1200));
+        assert_eq!(clamp_surface_size(5000, 1200, 2048), (2048, 1200));
+        assert_eq!(clamp_surface_size(0, 9000, 4096), (1, 4096));
+    }
+}
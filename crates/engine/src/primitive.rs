@@ -0,0 +1,571 @@
+/// CPU-side geometry generated for a primitive shape.
+///
+/// Shapes are centered on the origin with +Y up. Triangles use counter-clockwise winding
+/// when viewed from outside the shape, and UVs have their origin at the top left.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct PrimitiveGeometry {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+    pub topology: crate::world::PrimitiveTopology,
+}
+
+impl PrimitiveGeometry {
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    fn push_vertex(
+        &mut self,
+        position: nalgebra_glm::Vec3,
+        normal: nalgebra_glm::Vec3,
+        uv: nalgebra_glm::Vec2,
+    ) -> u32 {
+        let index = self.positions.len() as u32;
+        self.positions.push(position.into());
+        self.normals.push(normal.into());
+        self.uvs.push(uv.into());
+        index
+    }
+
+    // Adds a subdivided quad spanning center +/- u_half_extent +/- v_half_extent,
+    // where u x v points along the normal
+    fn push_grid(
+        &mut self,
+        center: nalgebra_glm::Vec3,
+        u_half_extent: nalgebra_glm::Vec3,
+        v_half_extent: nalgebra_glm::Vec3,
+        normal: nalgebra_glm::Vec3,
+        columns: u32,
+        rows: u32,
+    ) {
+        let first = self.positions.len() as u32;
+        for row in 0..=rows {
+            let t = row as f32 / rows as f32;
+            for column in 0..=columns {
+                let s = column as f32 / columns as f32;
+                let position =
+                    center + u_half_extent * (2.0 * s - 1.0) + v_half_extent * (2.0 * t - 1.0);
+                self.push_vertex(position, normal, nalgebra_glm::vec2(s, 1.0 - t));
+            }
+        }
+
+        let stride = columns + 1;
+        for row in 0..rows {
+            for column in 0..columns {
+                let a = first + row * stride + column;
+                let b = a + 1;
+                let c = a + stride;
+                let d = c + 1;
+                self.indices.extend_from_slice(&[a, b, c, b, d, c]);
+            }
+        }
+    }
+
+    // Connects consecutive rings of a surface of revolution, skipping triangles that
+    // collapse onto a pole
+    fn push_ring_strip(&mut self, first: u32, rings: &[RingKind], segments: u32) {
+        let stride = segments + 1;
+        for (ring, window) in rings.windows(2).enumerate() {
+            let ring = ring as u32;
+            for segment in 0..segments {
+                let a = first + ring * stride + segment;
+                let b = a + 1;
+                let c = a + stride;
+                let d = c + 1;
+                if window[0] != RingKind::Pole {
+                    self.indices.extend_from_slice(&[a, c, b]);
+                }
+                if window[1] != RingKind::Pole {
+                    self.indices.extend_from_slice(&[b, c, d]);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum RingKind {
+    Pole,
+    Ring,
+}
+
+impl crate::world::PrimitiveShape {
+    pub fn geometry(&self) -> PrimitiveGeometry {
+        match self {
+            Self::Triangle => triangle(),
+            Self::Box(shape) => cuboid(shape),
+            Self::Capsule(shape) => capsule(shape),
+            Self::Cylinder(shape) => cylinder(shape),
+            Self::Plane(shape) => plane(shape),
+            Self::Point => point(),
+            Self::Sphere(shape) => sphere(shape),
+        }
+    }
+}
+
+pub fn triangle() -> PrimitiveGeometry {
+    let mut geometry = PrimitiveGeometry::default();
+    let normal = nalgebra_glm::Vec3::z();
+    geometry.push_vertex(
+        nalgebra_glm::vec3(-1.0, -1.0, 0.0),
+        normal,
+        nalgebra_glm::vec2(0.0, 1.0),
+    );
+    geometry.push_vertex(
+        nalgebra_glm::vec3(1.0, -1.0, 0.0),
+        normal,
+        nalgebra_glm::vec2(1.0, 1.0),
+    );
+    geometry.push_vertex(
+        nalgebra_glm::vec3(0.0, 1.0, 0.0),
+        normal,
+        nalgebra_glm::vec2(0.5, 0.0),
+    );
+    geometry.indices = vec![0, 1, 2];
+    geometry
+}
+
+pub fn point() -> PrimitiveGeometry {
+    let mut geometry = PrimitiveGeometry {
+        topology: crate::world::PrimitiveTopology::PointList,
+        ..Default::default()
+    };
+    geometry.push_vertex(
+        nalgebra_glm::Vec3::zeros(),
+        nalgebra_glm::Vec3::y(),
+        nalgebra_glm::Vec2::zeros(),
+    );
+    geometry.indices = vec![0];
+    geometry
+}
+
+pub fn plane(shape: &crate::world::PlaneShape) -> PrimitiveGeometry {
+    let mut geometry = PrimitiveGeometry::default();
+    let cells = shape.subdivisions + 1;
+    geometry.push_grid(
+        nalgebra_glm::Vec3::zeros(),
+        nalgebra_glm::Vec3::x() * (shape.size.x * 0.5),
+        -nalgebra_glm::Vec3::z() * (shape.size.y * 0.5),
+        nalgebra_glm::Vec3::y(),
+        cells,
+        cells,
+    );
+    geometry
+}
+
+pub fn cuboid(shape: &crate::world::BoxShape) -> PrimitiveGeometry {
+    let mut geometry = PrimitiveGeometry::default();
+    let half = shape.size * 0.5;
+    let cells = shape.subdivisions + 1;
+    let (x, y, z) = (
+        nalgebra_glm::Vec3::x(),
+        nalgebra_glm::Vec3::y(),
+        nalgebra_glm::Vec3::z(),
+    );
+
+    // (normal, u, v, half extent along normal, half extent along u, half extent along v)
+    let faces = [
+        (x, -z, y, half.x, half.z, half.y),
+        (-x, z, y, half.x, half.z, half.y),
+        (y, x, -z, half.y, half.x, half.z),
+        (-y, x, z, half.y, half.x, half.z),
+        (z, x, y, half.z, half.x, half.y),
+        (-z, -x, y, half.z, half.x, half.y),
+    ];
+    for (normal, u, v, normal_extent, u_extent, v_extent) in faces {
+        geometry.push_grid(
+            normal * normal_extent,
+            u * u_extent,
+            v * v_extent,
+            normal,
+            cells,
+            cells,
+        );
+    }
+    geometry
+}
+
+pub fn sphere(shape: &crate::world::SphereShape) -> PrimitiveGeometry {
+    let mut geometry = PrimitiveGeometry::default();
+    let segments = shape.segments.max(3);
+    let rings = shape.rings.max(2);
+
+    let mut ring_kinds = Vec::new();
+    for ring in 0..=rings {
+        let phi = std::f32::consts::PI * ring as f32 / rings as f32;
+        for segment in 0..=segments {
+            let theta = std::f32::consts::TAU * segment as f32 / segments as f32;
+            let normal = revolve(phi, theta);
+            geometry.push_vertex(
+                normal * shape.radius,
+                normal,
+                nalgebra_glm::vec2(segment as f32 / segments as f32, ring as f32 / rings as f32),
+            );
+        }
+        ring_kinds.push(if ring == 0 || ring == rings {
+            RingKind::Pole
+        } else {
+            RingKind::Ring
+        });
+    }
+    geometry.push_ring_strip(0, &ring_kinds, segments);
+    geometry
+}
+
+pub fn capsule(shape: &crate::world::CapsuleShape) -> PrimitiveGeometry {
+    let mut geometry = PrimitiveGeometry::default();
+    let segments = shape.segments.max(3);
+    let rings = shape.rings.max(1);
+    let half_cylinder_height = (shape.height * 0.5 - shape.radius).max(0.0);
+
+    // Each hemisphere ends on the equator so the cylinder section between them keeps
+    // horizontal normals
+    let mut ring_kinds = Vec::new();
+    for hemisphere in 0..2 {
+        let y_offset = if hemisphere == 0 {
+            half_cylinder_height
+        } else {
+            -half_cylinder_height
+        };
+        for ring in 0..=rings {
+            let phi =
+                std::f32::consts::FRAC_PI_2 * (hemisphere * rings + ring) as f32 / rings as f32;
+            for segment in 0..=segments {
+                let theta = std::f32::consts::TAU * segment as f32 / segments as f32;
+                let normal = revolve(phi, theta);
+                let position = normal * shape.radius + nalgebra_glm::vec3(0.0, y_offset, 0.0);
+                let v = 0.5 - position.y / shape.height.max(f32::EPSILON);
+                geometry.push_vertex(
+                    position,
+                    normal,
+                    nalgebra_glm::vec2(segment as f32 / segments as f32, v),
+                );
+            }
+            let is_pole = (hemisphere == 0 && ring == 0) || (hemisphere == 1 && ring == rings);
+            ring_kinds.push(if is_pole {
+                RingKind::Pole
+            } else {
+                RingKind::Ring
+            });
+        }
+    }
+
+    let stride = segments + 1;
+    let hemisphere_rings = rings as usize + 1;
+    geometry.push_ring_strip(0, &ring_kinds[..hemisphere_rings], segments);
+    geometry.push_ring_strip(
+        hemisphere_rings as u32 * stride,
+        &ring_kinds[hemisphere_rings..],
+        segments,
+    );
+    if half_cylinder_height > 0.0 {
+        geometry.push_ring_strip(rings * stride, &[RingKind::Ring, RingKind::Ring], segments);
+    }
+    geometry
+}
+
+pub fn cylinder(shape: &crate::world::CylinderShape) -> PrimitiveGeometry {
+    let mut geometry = PrimitiveGeometry::default();
+    let segments = shape.segments.max(3);
+    let rings = shape.rings.max(1);
+    let half_height = shape.height * 0.5;
+    let slope = (shape.bottom_radius - shape.top_radius) / shape.height.max(f32::EPSILON);
+
+    for ring in 0..=rings {
+        let t = ring as f32 / rings as f32;
+        let y = half_height - shape.height * t;
+        let radius = nalgebra_glm::lerp_scalar(shape.top_radius, shape.bottom_radius, t);
+        for segment in 0..=segments {
+            let theta = std::f32::consts::TAU * segment as f32 / segments as f32;
+            let (sin, cos) = theta.sin_cos();
+            geometry.push_vertex(
+                nalgebra_glm::vec3(sin * radius, y, cos * radius),
+                nalgebra_glm::normalize(&nalgebra_glm::vec3(sin, slope, cos)),
+                nalgebra_glm::vec2(segment as f32 / segments as f32, t),
+            );
+        }
+    }
+
+    // A ring collapses onto a pole when its radius is zero, as with cones
+    let ring_kinds = (0..=rings)
+        .map(|ring| {
+            let t = ring as f32 / rings as f32;
+            if nalgebra_glm::lerp_scalar(shape.top_radius, shape.bottom_radius, t) == 0.0 {
+                RingKind::Pole
+            } else {
+                RingKind::Ring
+            }
+        })
+        .collect::<Vec<_>>();
+    geometry.push_ring_strip(0, &ring_kinds, segments);
+
+    if shape.cap_top && shape.top_radius > 0.0 {
+        push_cap(
+            &mut geometry,
+            half_height,
+            shape.top_radius,
+            segments,
+            nalgebra_glm::Vec3::y(),
+        );
+    }
+    if shape.cap_bottom && shape.bottom_radius > 0.0 {
+        push_cap(
+            &mut geometry,
+            -half_height,
+            shape.bottom_radius,
+            segments,
+            -nalgebra_glm::Vec3::y(),
+        );
+    }
+    geometry
+}
+
+fn push_cap(
+    geometry: &mut PrimitiveGeometry,
+    y: f32,
+    radius: f32,
+    segments: u32,
+    normal: nalgebra_glm::Vec3,
+) {
+    let center = geometry.push_vertex(
+        nalgebra_glm::vec3(0.0, y, 0.0),
+        normal,
+        nalgebra_glm::vec2(0.5, 0.5),
+    );
+    for segment in 0..=segments {
+        let theta = std::f32::consts::TAU * segment as f32 / segments as f32;
+        let (sin, cos) = theta.sin_cos();
+        geometry.push_vertex(
+            nalgebra_glm::vec3(sin * radius, y, cos * radius),
+            normal,
+            nalgebra_glm::vec2(0.5 + sin * 0.5, 0.5 + cos * 0.5),
+        );
+    }
+    for segment in 0..segments {
+        let current = center + 1 + segment;
+        let next = current + 1;
+        if normal.y > 0.0 {
+            geometry.indices.extend_from_slice(&[center, current, next]);
+        } else {
+            geometry.indices.extend_from_slice(&[center, next, current]);
+        }
+    }
+}
+
+// Unit direction for a polar angle measured from +Y and an azimuth measured from +Z towards +X
+fn revolve(phi: f32, theta: f32) -> nalgebra_glm::Vec3 {
+    nalgebra_glm::vec3(phi.sin() * theta.sin(), phi.cos(), phi.sin() * theta.cos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{
+        BoxShape, CapsuleShape, CylinderShape, PlaneShape, PrimitiveShape, SphereShape,
+    };
+
+    fn sphere_shape(segments: u32, rings: u32) -> SphereShape {
+        SphereShape {
+            radius: 2.0,
+            segments,
+            rings,
+        }
+    }
+
+    fn capsule_shape(segments: u32, rings: u32) -> CapsuleShape {
+        CapsuleShape {
+            radius: 0.5,
+            height: 3.0,
+            segments,
+            rings,
+        }
+    }
+
+    fn cylinder_shape(top_radius: f32, bottom_radius: f32) -> CylinderShape {
+        CylinderShape {
+            top_radius,
+            bottom_radius,
+            height: 2.0,
+            segments: 12,
+            rings: 3,
+            cap_top: true,
+            cap_bottom: true,
+        }
+    }
+
+    // Every shape with triangles, and enough of them that a bad index would show
+    fn solid_shapes() -> Vec<PrimitiveShape> {
+        vec![
+            PrimitiveShape::Box(BoxShape {
+                size: nalgebra_glm::vec3(1.0, 2.0, 3.0),
+                subdivisions: 2,
+            }),
+            PrimitiveShape::Capsule(capsule_shape(10, 4)),
+            PrimitiveShape::Cylinder(cylinder_shape(0.5, 1.0)),
+            PrimitiveShape::Cylinder(cylinder_shape(0.0, 1.0)),
+            PrimitiveShape::Sphere(sphere_shape(10, 6)),
+        ]
+    }
+
+    fn flat_shapes() -> Vec<(PrimitiveShape, nalgebra_glm::Vec3)> {
+        vec![
+            (PrimitiveShape::Triangle, nalgebra_glm::Vec3::z()),
+            (
+                PrimitiveShape::Plane(PlaneShape {
+                    size: nalgebra_glm::vec2(4.0, 2.0),
+                    subdivisions: 3,
+                }),
+                nalgebra_glm::Vec3::y(),
+            ),
+        ]
+    }
+
+    fn triangles(geometry: &PrimitiveGeometry) -> impl Iterator<Item = [usize; 3]> + '_ {
+        geometry
+            .indices
+            .chunks_exact(3)
+            .map(|triangle| [0, 1, 2].map(|corner| triangle[corner] as usize))
+    }
+
+    fn counts(shape: PrimitiveShape) -> (usize, usize) {
+        let geometry = shape.geometry();
+        (geometry.vertex_count(), geometry.indices.len())
+    }
+
+    #[test]
+    fn vertex_and_index_counts() {
+        assert_eq!(counts(PrimitiveShape::Triangle), (3, 3));
+        assert_eq!(counts(PrimitiveShape::Point), (1, 1));
+        // A 4x4 grid of cells
+        let plane = PlaneShape {
+            subdivisions: 3,
+            ..Default::default()
+        };
+        assert_eq!(counts(PrimitiveShape::Plane(plane)), (25, 96));
+        // A 3x3 grid of cells on each face
+        let cuboid = BoxShape {
+            subdivisions: 2,
+            ..Default::default()
+        };
+        assert_eq!(counts(PrimitiveShape::Box(cuboid)), (6 * 16, 6 * 54));
+        // Rings next to the poles are fans
+        assert_eq!(
+            counts(PrimitiveShape::Sphere(sphere_shape(10, 6))),
+            (7 * 11, 3 * (2 * 6 - 2) * 10)
+        );
+        // Two fanned hemispheres and the cylinder between them
+        assert_eq!(
+            counts(PrimitiveShape::Capsule(capsule_shape(10, 4))),
+            (2 * 5 * 11, 3 * (2 * (2 * 4 - 1) + 2) * 10)
+        );
+        assert_eq!(
+            counts(PrimitiveShape::Cylinder(cylinder_shape(0.5, 1.0))),
+            (4 * 13 + 2 * 14, 3 * (2 * 3 * 12 + 2 * 12))
+        );
+        // A cone's tip is a pole and has no cap
+        assert_eq!(
+            counts(PrimitiveShape::Cylinder(cylinder_shape(0.0, 1.0))),
+            (4 * 13 + 14, 3 * ((2 * 3 - 1) * 12 + 12))
+        );
+    }
+
+    #[test]
+    fn small_segment_and_ring_counts_are_raised() {
+        assert_eq!(
+            counts(PrimitiveShape::Sphere(sphere_shape(0, 0))),
+            counts(PrimitiveShape::Sphere(sphere_shape(3, 2)))
+        );
+        assert_eq!(
+            counts(PrimitiveShape::Capsule(capsule_shape(1, 0))),
+            counts(PrimitiveShape::Capsule(capsule_shape(3, 1)))
+        );
+    }
+
+    #[test]
+    fn capsules_without_a_cylinder_skip_it() {
+        let capsule = CapsuleShape {
+            radius: 1.0,
+            height: 2.0,
+            segments: 10,
+            rings: 4,
+        };
+        assert_eq!(
+            counts(PrimitiveShape::Capsule(capsule)),
+            (2 * 5 * 11, 3 * 2 * (2 * 4 - 1) * 10)
+        );
+    }
+
+    #[test]
+    fn indices_are_in_range() {
+        let shapes = solid_shapes()
+            .into_iter()
+            .chain(flat_shapes().into_iter().map(|(shape, _)| shape))
+            .chain([PrimitiveShape::Point]);
+        for shape in shapes {
+            let geometry = shape.geometry();
+            assert_eq!(geometry.normals.len(), geometry.vertex_count());
+            assert_eq!(geometry.uvs.len(), geometry.vertex_count());
+            assert!(
+                geometry
+                    .indices
+                    .iter()
+                    .all(|&index| (index as usize) < geometry.vertex_count()),
+                "{shape:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn triangles_wind_counter_clockwise_around_their_normals() {
+        let shapes = solid_shapes()
+            .into_iter()
+            .chain(flat_shapes().into_iter().map(|(shape, _)| shape));
+        for shape in shapes {
+            let geometry = shape.geometry();
+            assert_eq!(
+                geometry.topology,
+                crate::world::PrimitiveTopology::TriangleList
+            );
+            for [a, b, c] in triangles(&geometry) {
+                let [a_position, b_position, c_position] =
+                    [a, b, c].map(|index| nalgebra_glm::Vec3::from(geometry.positions[index]));
+                let face_normal =
+                    nalgebra_glm::cross(&(b_position - a_position), &(c_position - a_position));
+                let vertex_normal = [a, b, c]
+                    .iter()
+                    .map(|&index| nalgebra_glm::Vec3::from(geometry.normals[index]))
+                    .sum::<nalgebra_glm::Vec3>();
+                assert!(
+                    nalgebra_glm::dot(&face_normal, &vertex_normal) > 0.0,
+                    "{shape:?} winds {a}, {b}, {c} clockwise"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn normals_are_unit_length_and_point_outwards() {
+        for shape in solid_shapes() {
+            let geometry = shape.geometry();
+            for (position, normal) in geometry.positions.iter().zip(&geometry.normals) {
+                let (position, normal) = (
+                    nalgebra_glm::Vec3::from(*position),
+                    nalgebra_glm::Vec3::from(*normal),
+                );
+                assert!((normal.norm() - 1.0).abs() < 1e-5, "{shape:?}");
+                // Every solid is convex and centered on the origin
+                assert!(
+                    nalgebra_glm::dot(&normal, &position) > 0.0,
+                    "{shape:?} points {normal:?} inwards at {position:?}"
+                );
+            }
+        }
+        for (shape, expected) in flat_shapes() {
+            for normal in shape.geometry().normals {
+                assert_eq!(nalgebra_glm::Vec3::from(normal), expected, "{shape:?}");
+            }
+        }
+    }
+}
@@ -1,6 +1,8 @@
 pub struct Renderer<'window> {
     gpu: Gpu<'window>,
     depth_texture_view: wgpu::TextureView,
+    // Multisampled color target resolved into the frame, only present with MSAA enabled
+    msaa_texture_view: Option<wgpu::TextureView>,
     egui_renderer: egui_wgpu::Renderer,
     scene: Scene,
 }
@@ -12,31 +14,50 @@ impl<'window> Renderer<'window> {
         window: impl Into<wgpu::SurfaceTarget<'window>>,
         width: u32,
         height: u32,
+        settings: &crate::settings::RendererSettings,
     ) -> Self {
-        let gpu = Gpu::new_async(window, width, height).await;
-        let depth_texture_view = gpu.create_depth_texture(width, height);
+        let gpu = Gpu::new_async(window, width, height, settings).await;
+        Self::from_gpu(gpu)
+    }
+
+    fn from_gpu(gpu: Gpu<'window>) -> Self {
+        let depth_texture_view = gpu.create_depth_texture(gpu.width(), gpu.height());
+        let msaa_texture_view = gpu.create_msaa_texture(gpu.width(), gpu.height());
 
         let egui_renderer = egui_wgpu::Renderer::new(
             &gpu.device,
             gpu.surface_config.format,
             Some(Self::DEPTH_FORMAT),
-            1,
+            gpu.sample_count,
         );
 
-        let scene = Scene::new(&gpu.device, gpu.surface_format);
+        let scene = Scene::new(
+            &gpu.device,
+            &gpu.queue,
+            gpu.surface_format,
+            gpu.sample_count,
+        );
 
         Self {
             gpu,
             depth_texture_view,
+            msaa_texture_view,
             egui_renderer,
             scene,
         }
     }
 
-    #[cfg(not(target_arch = "wasm32"))]
+    /// Resizes the frame, clamped to what the device supports
     pub fn resize(&mut self, width: u32, height: u32) {
         self.gpu.resize(width, height);
+        let (width, height) = self.size();
         self.depth_texture_view = self.gpu.create_depth_texture(width, height);
+        self.msaa_texture_view = self.gpu.create_msaa_texture(width, height);
+    }
+
+    /// Size of the frame in physical pixels
+    pub fn size(&self) -> (u32, u32) {
+        (self.gpu.width(), self.gpu.height())
     }
 
     pub fn render_frame(
@@ -44,12 +65,15 @@ impl<'window> Renderer<'window> {
         screen_descriptor: egui_wgpu::ScreenDescriptor,
         paint_jobs: Vec<egui::ClippedPrimitive>,
         textures_delta: egui::TexturesDelta,
-        delta_time: crate::Duration,
-    ) {
-        let delta_time = delta_time.as_secs_f32();
-
-        self.scene
-            .update(&self.gpu.queue, self.gpu.aspect_ratio(), delta_time);
+        world: &crate::world::World,
+        capture: CaptureRequest,
+    ) -> Option<CapturedFrame> {
+        self.scene.update(
+            &self.gpu.device,
+            &self.gpu.queue,
+            world,
+            (self.gpu.width(), self.gpu.height()),
+        );
 
         for (id, image_delta) in &textures_delta.set {
             self.egui_renderer
@@ -60,6 +84,19 @@ impl<'window> Renderer<'window> {
             self.egui_renderer.free_texture(id);
         }
 
+        // Texture updates above still apply to skipped frames so the ui doesn't lose them
+        let frame = match self.gpu.acquire_frame() {
+            Ok(frame) => frame,
+            Err(wgpu::SurfaceError::Timeout) => {
+                log::warn!("Timed out acquiring the next frame, skipping it");
+                return None;
+            }
+            Err(error) => {
+                log::error!("Failed to acquire the next frame: {error}");
+                return None;
+            }
+        };
+
         let mut encoder = self
             .gpu
             .device
@@ -75,25 +112,25 @@ impl<'window> Renderer<'window> {
             &screen_descriptor,
         );
 
-        let surface_texture = self
-            .gpu
-            .surface
-            .get_current_texture()
-            .expect("Failed to get surface texture!");
-
-        let surface_texture_view =
-            surface_texture
-                .texture
-                .create_view(&wgpu::TextureViewDescriptor {
-                    label: wgpu::Label::default(),
-                    aspect: wgpu::TextureAspect::default(),
-                    format: Some(self.gpu.surface_format),
-                    dimension: None,
-                    base_mip_level: 0,
-                    mip_level_count: None,
-                    base_array_layer: 0,
-                    array_layer_count: None,
-                });
+        let surface_texture_view = frame.texture().create_view(&wgpu::TextureViewDescriptor {
+            label: wgpu::Label::default(),
+            aspect: wgpu::TextureAspect::default(),
+            format: Some(self.gpu.surface_format),
+            dimension: None,
+            base_mip_level: 0,
+            mip_level_count: None,
+            base_array_layer: 0,
+            array_layer_count: None,
+        });
+
+        // With MSAA both passes draw into the multisampled target and resolve into the frame
+        let (color_view, resolve_target) = match self.msaa_texture_view.as_ref() {
+            Some(msaa_texture_view) => (msaa_texture_view, Some(&surface_texture_view)),
+            None => (&surface_texture_view, None),
+        };
+
+        encoder.insert_debug_marker("Render shadows");
+        self.scene.render_shadows(&mut encoder);
 
         encoder.insert_debug_marker("Render scene");
 
@@ -105,8 +142,8 @@ impl<'window> Renderer<'window> {
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &surface_texture_view,
-                    resolve_target: None,
+                    view: color_view,
+                    resolve_target,
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color {
                             r: 0.19,
@@ -129,51 +166,337 @@ impl<'window> Renderer<'window> {
                 occlusion_query_set: None,
             });
             self.scene.render(&mut render_pass);
+        }
+
+        // The scene is copied out before the ui pass so captures can leave the overlay out
+        let scene_readback = capture
+            .scene
+            .then(|| self.gpu.copy_to_readback(&mut encoder, frame.texture()));
 
+        encoder.insert_debug_marker("Render ui");
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("UI Render Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: color_view,
+                    resolve_target,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.depth_texture_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
             self.egui_renderer
                 .render(&mut render_pass, &paint_jobs, &screen_descriptor);
         }
 
+        let overlay_readback = capture
+            .overlay
+            .then(|| self.gpu.copy_to_readback(&mut encoder, frame.texture()));
+
         self.gpu.queue.submit(std::iter::once(encoder.finish()));
-        surface_texture.present();
+        frame.present();
+
+        Some(CapturedFrame {
+            scene: scene_readback.map(|readback| readback.and_then(|r| r.read(&self.gpu.device))),
+            overlay: overlay_readback
+                .map(|readback| readback.and_then(|r| r.read(&self.gpu.device))),
+        })
+    }
+
+    /// Returns the reason the gpu device was lost, once, if it has been since the last call.
+    ///
+    /// Everything created on the old device is unusable afterwards, including this renderer.
+    pub fn take_device_lost(&self) -> Option<String> {
+        self.gpu
+            .device_lost
+            .lock()
+            .ok()
+            .and_then(|mut device_lost| device_lost.reason.take())
+    }
+}
+
+impl Renderer<'static> {
+    /// Creates a renderer that draws into an offscreen texture instead of a window surface.
+    ///
+    /// Set `force_fallback_adapter` to render on a software adapter such as llvmpipe or
+    /// lavapipe, which makes output reproducible on machines without a gpu.
+    pub async fn new_headless(width: u32, height: u32, force_fallback_adapter: bool) -> Self {
+        let gpu = Gpu::new_headless_async(width, height, force_fallback_adapter).await;
+        Self::from_gpu(gpu)
+    }
+
+    /// Whether `new_headless` will find an adapter. Machines without a gpu may have none,
+    /// and a fallback adapter needs a software renderer such as Mesa's llvmpipe.
+    pub async fn headless_adapter_available(force_fallback_adapter: bool) -> bool {
+        Gpu::request_headless_adapter(force_fallback_adapter)
+            .await
+            .is_some()
+    }
+
+    /// Renders the world without any egui overlay
+    pub fn render_world(&mut self, world: &crate::world::World) {
+        let screen_descriptor = egui_wgpu::ScreenDescriptor {
+            size_in_pixels: [self.gpu.width(), self.gpu.height()],
+            pixels_per_point: 1.0,
+        };
+        self.render_frame(
+            screen_descriptor,
+            Vec::new(),
+            egui::TexturesDelta::default(),
+            world,
+            CaptureRequest::default(),
+        );
+    }
+
+    /// Copies the last rendered frame of a headless renderer back to cpu memory as RGBA8.
+    ///
+    /// Returns `None` when the renderer draws to a window surface.
+    pub fn read_pixels(&self) -> Option<Result<crate::world::Image, CaptureError>> {
+        match &self.gpu.target {
+            RenderTarget::Surface(_) => None,
+            RenderTarget::Offscreen(texture) => Some(self.gpu.read_texture(texture)),
+        }
+    }
+}
+
+/// Which versions of a frame should be copied back to the cpu after rendering
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CaptureRequest {
+    /// The 3D scene without the egui overlay
+    pub scene: bool,
+    /// The final frame including the egui overlay
+    pub overlay: bool,
+}
+
+/// Images read back for a `CaptureRequest`, `None` where nothing was requested
+#[derive(Default, Debug)]
+pub struct CapturedFrame {
+    pub scene: Option<Result<crate::world::Image, CaptureError>>,
+    pub overlay: Option<Result<crate::world::Image, CaptureError>>,
+}
+
+#[derive(Debug)]
+pub enum CaptureError {
+    // The render target was not created with `COPY_SRC`, some surfaces don't support it
+    Unsupported,
+    Map(wgpu::BufferAsyncError),
+    // Mapping finishes asynchronously on the web, so frames can't be read back synchronously
+    NotReady,
+}
+
+impl std::fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unsupported => write!(f, "The render target does not support being copied"),
+            Self::Map(error) => write!(f, "Failed to map readback buffer: {error}"),
+            Self::NotReady => write!(f, "Readback buffer was not mapped in time"),
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {}
+
+// A pending copy of a color texture into a mappable buffer
+pub struct Readback {
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    format: wgpu::TextureFormat,
+}
+
+impl Readback {
+    /// Blocks until the copy has landed, then packs the rows tightly and swizzles BGRA to RGBA
+    pub fn read(self, device: &wgpu::Device) -> Result<crate::world::Image, CaptureError> {
+        let slice = self.buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver
+            .try_recv()
+            .map_err(|_| CaptureError::NotReady)?
+            .map_err(CaptureError::Map)?;
+
+        let unpadded_bytes_per_row = self.width * 4;
+        let mut pixels = Vec::with_capacity((unpadded_bytes_per_row * self.height) as usize);
+        {
+            let data = slice.get_mapped_range();
+            data.chunks(self.padded_bytes_per_row as usize)
+                .for_each(|row| pixels.extend_from_slice(&row[..unpadded_bytes_per_row as usize]));
+        }
+        self.buffer.unmap();
+
+        if matches!(
+            self.format,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb
+        ) {
+            pixels
+                .chunks_exact_mut(4)
+                .for_each(|pixel| pixel.swap(0, 2));
+        }
+
+        Ok(crate::world::Image {
+            width: self.width,
+            height: self.height,
+            pixels,
+            color_space: None,
+        })
+    }
+}
+
+/// Where the renderer's color output ends up
+pub enum RenderTarget<'window> {
+    Surface(wgpu::Surface<'window>),
+    Offscreen(wgpu::Texture),
+}
+
+/// The color texture being drawn this frame
+pub enum Frame<'a> {
+    Surface(wgpu::SurfaceTexture),
+    Offscreen(&'a wgpu::Texture),
+}
+
+impl Frame<'_> {
+    pub fn texture(&self) -> &wgpu::Texture {
+        match self {
+            Self::Surface(surface_texture) => &surface_texture.texture,
+            Self::Offscreen(texture) => texture,
+        }
+    }
+
+    pub fn present(self) {
+        if let Self::Surface(surface_texture) = self {
+            surface_texture.present();
+        }
     }
 }
 
 pub struct Gpu<'window> {
-    pub surface: wgpu::Surface<'window>,
+    pub target: RenderTarget<'window>,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub surface_config: wgpu::SurfaceConfiguration,
     pub surface_format: wgpu::TextureFormat,
+    pub sample_count: u32,
+    // Shared with the device lost callback, which may run on another thread
+    device_lost: std::sync::Arc<std::sync::Mutex<DeviceLost>>,
+}
+
+#[derive(Default)]
+struct DeviceLost {
+    reason: Option<String>,
+    // wgpu 0.19 reports dropping the device as a loss with an unknown reason
+    dropping: bool,
+}
+
+impl Drop for Gpu<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut device_lost) = self.device_lost.lock() {
+            device_lost.dropping = true;
+        }
+    }
 }
 
 impl<'window> Gpu<'window> {
-    pub fn aspect_ratio(&self) -> f32 {
-        self.surface_config.width as f32 / self.surface_config.height.max(1) as f32
+    // egui expects a non-srgb target, so offscreen rendering matches what a window would get
+    const OFFSCREEN_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+    pub fn width(&self) -> u32 {
+        self.surface_config.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.surface_config.height
     }
 
-    #[cfg(not(target_arch = "wasm32"))]
     pub fn resize(&mut self, width: u32, height: u32) {
+        let (width, height) = crate::platform::clamp_surface_size(
+            width,
+            height,
+            self.device.limits().max_texture_dimension_2d,
+        );
         self.surface_config.width = width;
         self.surface_config.height = height;
-        self.surface.configure(&self.device, &self.surface_config);
+        match &mut self.target {
+            RenderTarget::Surface(surface) => surface.configure(&self.device, &self.surface_config),
+            RenderTarget::Offscreen(texture) => {
+                *texture = Self::create_offscreen_texture(&self.device, width, height)
+            }
+        }
+    }
+
+    /// Gets the texture to draw the next frame into.
+    ///
+    /// An outdated, lost or suboptimal surface is reconfigured and acquired once more, any
+    /// other error means the frame should be skipped.
+    pub fn acquire_frame(&self) -> Result<Frame<'_>, wgpu::SurfaceError> {
+        let surface = match &self.target {
+            RenderTarget::Surface(surface) => surface,
+            RenderTarget::Offscreen(texture) => return Ok(Frame::Offscreen(texture)),
+        };
+        match surface.get_current_texture() {
+            Ok(surface_texture) if !surface_texture.suboptimal => {
+                return Ok(Frame::Surface(surface_texture))
+            }
+            Ok(_) | Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+                log::info!("Reconfiguring the surface");
+                surface.configure(&self.device, &self.surface_config);
+            }
+            Err(error) => return Err(error),
+        }
+        surface.get_current_texture().map(Frame::Surface)
     }
 
     pub fn create_depth_texture(&self, width: u32, height: u32) -> wgpu::TextureView {
-        let texture = self.device.create_texture(
+        Self::create_depth_view(
+            &self.device,
+            "Depth Texture",
+            width,
+            height,
+            self.sample_count,
+        )
+    }
+
+    // Multisampled depth can't be sampled, so it is only bindable without MSAA
+    fn create_depth_view(
+        device: &wgpu::Device,
+        label: &str,
+        width: u32,
+        height: u32,
+        sample_count: u32,
+    ) -> wgpu::TextureView {
+        let texture = device.create_texture(
             &(wgpu::TextureDescriptor {
-                label: Some("Depth Texture"),
+                label: Some(label),
                 size: wgpu::Extent3d {
                     width,
                     height,
                     depth_or_array_layers: 1,
                 },
                 mip_level_count: 1,
-                sample_count: 1,
+                sample_count,
                 dimension: wgpu::TextureDimension::D2,
                 format: wgpu::TextureFormat::Depth32Float,
-                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
-                    | wgpu::TextureUsages::TEXTURE_BINDING,
+                usage: match sample_count {
+                    1 => {
+                        wgpu::TextureUsages::RENDER_ATTACHMENT
+                            | wgpu::TextureUsages::TEXTURE_BINDING
+                    }
+                    _ => wgpu::TextureUsages::RENDER_ATTACHMENT,
+                },
                 view_formats: &[],
             }),
         );
@@ -189,59 +512,128 @@ impl<'window> Gpu<'window> {
         })
     }
 
+    pub fn create_msaa_texture(&self, width: u32, height: u32) -> Option<wgpu::TextureView> {
+        if self.sample_count == 1 {
+            return None;
+        }
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("MSAA Color Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: self.sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.surface_format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        Some(texture.create_view(&wgpu::TextureViewDescriptor::default()))
+    }
+
+    fn create_offscreen_texture(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Color Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::OFFSCREEN_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        })
+    }
+
+    /// Records a copy of a color texture into a new mappable buffer
+    pub fn copy_to_readback(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+    ) -> Result<Readback, CaptureError> {
+        if !texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+            return Err(CaptureError::Unsupported);
+        }
+
+        let (width, height) = (texture.width(), texture.height());
+        let padded_bytes_per_row = (width * 4).div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
+
+        Ok(Readback {
+            buffer,
+            width,
+            height,
+            padded_bytes_per_row,
+            format: texture.format(),
+        })
+    }
+
+    /// Copies a color texture back to cpu memory as RGBA8
+    pub fn read_texture(
+        &self,
+        texture: &wgpu::Texture,
+    ) -> Result<crate::world::Image, CaptureError> {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Readback Encoder"),
+            });
+        let readback = self.copy_to_readback(&mut encoder, texture)?;
+        self.queue.submit(std::iter::once(encoder.finish()));
+        readback.read(&self.device)
+    }
+
     pub async fn new_async(
         window: impl Into<wgpu::SurfaceTarget<'window>>,
         width: u32,
         height: u32,
+        settings: &crate::settings::RendererSettings,
     ) -> Self {
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::util::backend_bits_from_env().unwrap_or_else(wgpu::Backends::all),
-            ..Default::default()
-        });
+        let instance = Self::create_instance(settings.backend);
 
         let surface = instance.create_surface(window).unwrap();
 
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
+                power_preference: settings.power_preference.into(),
                 compatible_surface: Some(&surface),
                 force_fallback_adapter: false,
             })
             .await
             .expect("Failed to request adapter!");
-        let (device, queue) = {
-            log::info!("WGPU Adapter Features: {:#?}", adapter.features());
-            adapter
-                .request_device(
-                    &wgpu::DeviceDescriptor {
-                        label: Some("WGPU Device"),
-
-                        #[cfg(not(target_arch = "wasm32"))]
-                        required_features: wgpu::Features::default(),
-
-                        #[cfg(all(target_arch = "wasm32", feature = "webgpu"))]
-                        required_features: wgpu::Features::all_webgpu_mask(),
-
-                        #[cfg(all(target_arch = "wasm32", feature = "webgl"))]
-                        required_features: wgpu::Features::default(),
-
-                        #[cfg(not(target_arch = "wasm32"))]
-                        required_limits: wgpu::Limits {
-                            max_texture_dimension_2d: 4096, // Allow higher resolutions on native
-                            ..wgpu::Limits::downlevel_defaults()
-                        },
-
-                        #[cfg(all(target_arch = "wasm32", feature = "webgpu"))]
-                        required_limits: wgpu::Limits::default(),
+        let (device, queue) = Self::request_device(&adapter).await;
+        let device_lost = Self::watch_device_lost(&device);
 
-                        #[cfg(all(target_arch = "wasm32", feature = "webgl"))]
-                        required_limits: wgpu::Limits::downlevel_webgl2_defaults(),
-                    },
-                    None,
-                )
-                .await
-                .expect("Failed to request a device!")
-        };
+        let (width, height) = crate::platform::clamp_surface_size(
+            width,
+            height,
+            device.limits().max_texture_dimension_2d,
+        );
 
         let surface_capabilities = surface.get_capabilities(&adapter);
 
@@ -253,12 +645,17 @@ impl<'window> Gpu<'window> {
             .find(|f| !f.is_srgb()) // egui wants a non-srgb surface texture
             .unwrap_or(surface_capabilities.formats[0]);
 
+        // Copying out of the surface is only needed for screenshots, so it's optional
         let surface_config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | (surface_capabilities.usages & wgpu::TextureUsages::COPY_SRC),
             format: surface_format,
             width,
             height,
-            present_mode: surface_capabilities.present_modes[0],
+            present_mode: Self::select_present_mode(
+                settings.present_mode.into(),
+                &surface_capabilities.present_modes,
+            ),
             alpha_mode: surface_capabilities.alpha_modes[0],
             view_formats: vec![],
             desired_maximum_frame_latency: 2,
@@ -266,260 +663,2119 @@ impl<'window> Gpu<'window> {
 
         surface.configure(&device, &surface_config);
 
+        let sample_count =
+            Self::select_sample_count(&adapter, surface_format, settings.msaa_samples);
+
         Self {
-            surface,
+            target: RenderTarget::Surface(surface),
             device,
             queue,
             surface_config,
             surface_format,
+            sample_count,
+            device_lost,
         }
     }
-}
 
-struct Scene {
-    pub model: nalgebra_glm::Mat4,
-    pub vertex_buffer: wgpu::Buffer,
-    pub index_buffer: wgpu::Buffer,
-    pub uniform: UniformBinding,
-    pub pipeline: wgpu::RenderPipeline,
-}
+    fn create_instance(backend: Option<crate::settings::Backend>) -> wgpu::Instance {
+        let backends = match backend {
+            Some(backend) => backend.into(),
+            None => wgpu::util::backend_bits_from_env().unwrap_or_else(wgpu::Backends::all),
+        };
+        wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends,
+            ..Default::default()
+        })
+    }
 
-impl Scene {
-    pub fn new(device: &wgpu::Device, surface_format: wgpu::TextureFormat) -> Self {
-        let vertex_buffer = wgpu::util::DeviceExt::create_buffer_init(
-            device,
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(&VERTICES),
-                usage: wgpu::BufferUsages::VERTEX,
-            },
-        );
-        let index_buffer = wgpu::util::DeviceExt::create_buffer_init(
-            device,
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("index Buffer"),
-                contents: bytemuck::cast_slice(&INDICES),
-                usage: wgpu::BufferUsages::INDEX,
-            },
-        );
-        let uniform = UniformBinding::new(device);
-        let pipeline = Self::create_pipeline(device, surface_format, &uniform);
-        Self {
-            model: nalgebra_glm::Mat4::identity(),
-            uniform,
-            pipeline,
-            vertex_buffer,
-            index_buffer,
+    // The automatic modes always resolve to something supported, explicit ones may not exist
+    fn select_present_mode(
+        requested: wgpu::PresentMode,
+        supported: &[wgpu::PresentMode],
+    ) -> wgpu::PresentMode {
+        match requested {
+            wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync => requested,
+            _ if supported.contains(&requested) => requested,
+            _ => {
+                log::warn!("Present mode {requested:?} is not supported, falling back to vsync");
+                wgpu::PresentMode::AutoVsync
+            }
         }
     }
 
-    pub fn render<'rpass>(&'rpass self, renderpass: &mut wgpu::RenderPass<'rpass>) {
-        renderpass.set_pipeline(&self.pipeline);
-        renderpass.set_bind_group(0, &self.uniform.bind_group, &[]);
+    // Both the color and depth targets have to support the sample count
+    fn select_sample_count(
+        adapter: &wgpu::Adapter,
+        color_format: wgpu::TextureFormat,
+        requested: u32,
+    ) -> u32 {
+        let supported = |format| {
+            adapter
+                .get_texture_format_features(format)
+                .flags
+                .sample_count_supported(requested)
+        };
+        if requested <= 1 {
+            return 1;
+        }
+        if supported(color_format) && supported(Renderer::DEPTH_FORMAT) {
+            return requested;
+        }
+        log::warn!("{requested}x MSAA is not supported by this adapter, disabling it");
+        1
+    }
+
+    // Dropping the device also reports it as lost, which is expected and ignored
+    fn watch_device_lost(device: &wgpu::Device) -> std::sync::Arc<std::sync::Mutex<DeviceLost>> {
+        let device_lost = std::sync::Arc::new(std::sync::Mutex::new(DeviceLost::default()));
+        let callback_device_lost = device_lost.clone();
+        device.set_device_lost_callback(move |reason, message| {
+            let Ok(mut device_lost) = callback_device_lost.lock() else {
+                return;
+            };
+            if device_lost.dropping
+                || matches!(
+                    reason,
+                    wgpu::DeviceLostReason::Dropped | wgpu::DeviceLostReason::ReplacedCallback
+                )
+            {
+                return;
+            }
+            log::error!("The gpu device was lost ({reason:?}): {message}");
+            device_lost.reason = Some(format!("{reason:?}: {message}"));
+        });
+        device_lost
+    }
+
+    async fn request_device(adapter: &wgpu::Adapter) -> (wgpu::Device, wgpu::Queue) {
+        log::info!("WGPU Adapter Features: {:#?}", adapter.features());
+        adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: Some("WGPU Device"),
+
+                    #[cfg(not(target_arch = "wasm32"))]
+                    required_features: wgpu::Features::default(),
 
-        renderpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        renderpass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+                    #[cfg(all(target_arch = "wasm32", feature = "webgpu"))]
+                    required_features: wgpu::Features::all_webgpu_mask(),
 
-        renderpass.draw_indexed(0..(INDICES.len() as _), 0, 0..1);
+                    #[cfg(all(target_arch = "wasm32", feature = "webgl"))]
+                    required_features: wgpu::Features::default(),
+
+                    #[cfg(not(target_arch = "wasm32"))]
+                    required_limits: wgpu::Limits {
+                        max_texture_dimension_2d: 4096, // Allow higher resolutions on native
+                        ..wgpu::Limits::downlevel_defaults()
+                    },
+
+                    #[cfg(all(target_arch = "wasm32", feature = "webgpu"))]
+                    required_limits: wgpu::Limits::default(),
+
+                    #[cfg(all(target_arch = "wasm32", feature = "webgl"))]
+                    required_limits: wgpu::Limits::downlevel_webgl2_defaults(),
+                },
+                None,
+            )
+            .await
+            .expect("Failed to request a device!")
     }
+}
 
-    pub fn update(&mut self, queue: &wgpu::Queue, aspect_ratio: f32, delta_time: f32) {
-        let projection =
-            nalgebra_glm::perspective_lh_zo(aspect_ratio, 80_f32.to_radians(), 0.1, 1000.0);
-        let view = nalgebra_glm::look_at_lh(
-            &nalgebra_glm::vec3(0.0, 0.0, 3.0),
-            &nalgebra_glm::vec3(0.0, 0.0, 0.0),
-            &nalgebra_glm::Vec3::y(),
-        );
-        self.model = nalgebra_glm::rotate(
-            &self.model,
-            30_f32.to_radians() * delta_time,
-            &nalgebra_glm::Vec3::y(),
-        );
-        self.uniform.update_buffer(
-            queue,
-            0,
-            UniformBuffer {
-                mvp: projection * view * self.model,
-            },
-        );
+impl Gpu<'static> {
+    async fn request_headless_adapter(force_fallback_adapter: bool) -> Option<wgpu::Adapter> {
+        Self::create_instance(None)
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                compatible_surface: None,
+                force_fallback_adapter,
+            })
+            .await
     }
 
-    fn create_pipeline(
-        device: &wgpu::Device,
-        surface_format: wgpu::TextureFormat,
-        uniform: &UniformBinding,
-    ) -> wgpu::RenderPipeline {
-        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(SHADER_SOURCE)),
-        });
+    pub async fn new_headless_async(width: u32, height: u32, force_fallback_adapter: bool) -> Self {
+        let adapter = Self::request_headless_adapter(force_fallback_adapter)
+            .await
+            .expect("Failed to request adapter!");
+        log::info!("Headless adapter: {:?}", adapter.get_info());
+        let (device, queue) = Self::request_device(&adapter).await;
+        let device_lost = Self::watch_device_lost(&device);
 
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
-            bind_group_layouts: &[&uniform.bind_group_layout],
-            push_constant_ranges: &[],
-        });
+        // Kept so the offscreen path shares sizing and format bookkeeping with the surface path
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: Self::OFFSCREEN_FORMAT,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+            desired_maximum_frame_latency: 2,
+        };
 
-        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader_module,
-                entry_point: "vertex_main",
-                buffers: &[Vertex::description(&Vertex::vertex_attributes())],
-            },
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleStrip,
-                strip_index_format: Some(wgpu::IndexFormat::Uint32),
-                front_face: wgpu::FrontFace::Cw,
-                cull_mode: None,
+        let texture = Self::create_offscreen_texture(&device, width, height);
+
+        Self {
+            target: RenderTarget::Offscreen(texture),
+            device,
+            queue,
+            surface_config,
+            surface_format: Self::OFFSCREEN_FORMAT,
+            sample_count: 1,
+            device_lost,
+        }
+    }
+}
+
+struct Scene {
+    pub meshes: std::collections::HashMap<crate::world::MeshId, GpuMesh>,
+    pub materials: std::collections::HashMap<crate::world::MaterialId, GpuMaterial>,
+    // Keyed by whether the texture is sampled as sRGB, as the same image may be used both ways
+    pub textures: std::collections::HashMap<(crate::world::TextureId, bool), GpuTexture>,
+    pub fallback_texture: GpuTexture,
+    // Textures with the same filter and wrap modes share a sampler
+    pub samplers: std::collections::HashMap<crate::world::Sampler, wgpu::Sampler>,
+    pub material_bind_group_layout: wgpu::BindGroupLayout,
+    // Materials referenced by a surface but missing from the world, so each is only reported once
+    pub missing_materials: std::collections::HashSet<crate::world::MaterialId>,
+    pub draws: Vec<DrawCommand>,
+    pub instance_buffer: wgpu::Buffer,
+    pub instance_capacity: usize,
+    pub uniform: UniformBinding,
+    pub lighting: LightingBinding,
+    pub shadows: ShadowPass,
+    pub pipelines: Vec<wgpu::RenderPipeline>,
+    pub pipeline_ids: std::collections::HashMap<PipelineKey, usize>,
+    pub surface_format: wgpu::TextureFormat,
+    pub sample_count: u32,
+}
+
+// Geometry uploaded to the gpu for a single entry in the world's mesh registry
+struct GpuMesh {
+    pub surfaces: Vec<GpuSurface>,
+    // The world's revision of the mesh when it was uploaded
+    pub revision: u64,
+}
+
+struct GpuSurface {
+    pub vertex_buffer: wgpu::Buffer,
+    pub vertex_count: u32,
+    pub index_buffer: Option<GpuIndices>,
+    pub geometry_key: GeometryKey,
+    pub material: Option<crate::world::MaterialId>,
+}
+
+struct GpuIndices {
+    pub buffer: wgpu::Buffer,
+    pub format: wgpu::IndexFormat,
+    pub count: u32,
+}
+
+// The parts of a surface's geometry that need their own pipeline
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct GeometryKey {
+    pub layout: Vec<crate::world::VertexAttribute>,
+    pub topology: crate::world::PrimitiveTopology,
+    pub strip_index_format: Option<wgpu::IndexFormat>,
+}
+
+// Surfaces sharing geometry and material keys share a pipeline. Surfaces without a material
+// use a debug pipeline showing vertex colors or normals.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PipelineKey {
+    pub geometry: GeometryKey,
+    pub material: Option<MaterialKey>,
+}
+
+// The parts of a material that need their own pipeline, everything else is a uniform or texture
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+struct MaterialKey {
+    pub unlit: bool,
+    pub alpha_mode: BlendMode,
+    pub double_sided: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum BlendMode {
+    Opaque,
+    Mask,
+    Blend,
+}
+
+impl From<&crate::world::Material> for MaterialKey {
+    fn from(material: &crate::world::Material) -> Self {
+        Self {
+            unlit: material.unlit,
+            alpha_mode: match material.alpha_mode {
+                crate::world::AlphaMode::Opaque => BlendMode::Opaque,
+                crate::world::AlphaMode::Mask { .. } => BlendMode::Mask,
+                crate::world::AlphaMode::Blend => BlendMode::Blend,
+            },
+            double_sided: material.double_sided,
+        }
+    }
+}
+
+// A material's uniform buffer and textures, rebuilt whenever the world's copy changes
+struct GpuMaterial {
+    pub source: crate::world::Material,
+    // Revision of each slot's texture when the bind group was created, `None` if missing
+    pub texture_revisions: [Option<u64>; 5],
+    pub key: MaterialKey,
+    // Keeps the uniform buffer and textures alive
+    pub bind_group: wgpu::BindGroup,
+}
+
+struct GpuTexture {
+    pub view: wgpu::TextureView,
+    // Key into the scene's sampler cache
+    pub sampler: crate::world::Sampler,
+    // The world's revision of the texture when it was uploaded
+    pub revision: u64,
+}
+
+// One draw of a single mesh surface for a contiguous range of instances in the instance buffer
+struct DrawCommand {
+    pub pipeline: usize,
+    // Depth only pipeline for the shadow pass, surfaces that don't cast shadows have none
+    pub shadow_pipeline: Option<usize>,
+    pub material: Option<crate::world::MaterialId>,
+    pub mesh_id: crate::world::MeshId,
+    pub surface_index: usize,
+    pub instances: std::ops::Range<u32>,
+}
+
+impl Scene {
+    const INITIAL_INSTANCE_CAPACITY: usize = 256;
+
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        surface_format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let uniform = UniformBinding::new(device);
+        let instance_buffer = Self::create_instance_buffer(device, Self::INITIAL_INSTANCE_CAPACITY);
+        Self {
+            meshes: std::collections::HashMap::new(),
+            materials: std::collections::HashMap::new(),
+            textures: std::collections::HashMap::new(),
+            fallback_texture: Self::create_fallback_texture(device, queue),
+            samplers: std::collections::HashMap::new(),
+            material_bind_group_layout: Self::create_material_bind_group_layout(device),
+            missing_materials: std::collections::HashSet::new(),
+            draws: Vec::new(),
+            instance_buffer,
+            instance_capacity: Self::INITIAL_INSTANCE_CAPACITY,
+            uniform,
+            lighting: LightingBinding::new(device),
+            shadows: ShadowPass::new(device),
+            pipelines: Vec::new(),
+            pipeline_ids: std::collections::HashMap::new(),
+            surface_format,
+            sample_count,
+        }
+    }
+
+    pub fn render<'rpass>(&'rpass self, renderpass: &mut wgpu::RenderPass<'rpass>) {
+        renderpass.set_bind_group(0, &self.uniform.bind_group, &[]);
+        renderpass.set_bind_group(2, &self.lighting.bind_group, &[]);
+        renderpass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+
+        // Draws are sorted by pipeline and material, so state only changes between batches
+        let mut current_pipeline = None;
+        let mut current_material = None;
+        for draw in self.draws.iter() {
+            let Some(surface) = self
+                .meshes
+                .get(&draw.mesh_id)
+                .and_then(|mesh| mesh.surfaces.get(draw.surface_index))
+            else {
+                continue;
+            };
+            if current_pipeline != Some(draw.pipeline) {
+                renderpass.set_pipeline(&self.pipelines[draw.pipeline]);
+                current_pipeline = Some(draw.pipeline);
+                current_material = None;
+            }
+            if current_material != draw.material.as_ref() {
+                if let Some(material) = draw
+                    .material
+                    .as_ref()
+                    .and_then(|material_id| self.materials.get(material_id))
+                {
+                    renderpass.set_bind_group(1, &material.bind_group, &[]);
+                }
+                current_material = draw.material.as_ref();
+            }
+            renderpass.set_vertex_buffer(0, surface.vertex_buffer.slice(..));
+            match surface.index_buffer.as_ref() {
+                Some(indices) => {
+                    renderpass.set_index_buffer(indices.buffer.slice(..), indices.format);
+                    renderpass.draw_indexed(0..indices.count, 0, draw.instances.clone());
+                }
+                None => renderpass.draw(0..surface.vertex_count, draw.instances.clone()),
+            }
+        }
+    }
+
+    // Draws every shadow caster once per atlas slot, each slot into its own tile
+    pub fn render_shadows(&self, encoder: &mut wgpu::CommandEncoder) {
+        if self.shadows.slot_count == 0 {
+            return;
+        }
+        let mut renderpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Shadow Pass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.lighting.shadow_atlas,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        renderpass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+
+        let tile_size = self.lighting.shadow_tile_size();
+        for slot in 0..self.shadows.slot_count {
+            let x = slot % crate::shadow::SHADOW_ATLAS_TILES * tile_size;
+            let y = slot / crate::shadow::SHADOW_ATLAS_TILES * tile_size;
+            renderpass.set_viewport(
+                x as f32,
+                y as f32,
+                tile_size as f32,
+                tile_size as f32,
+                0.0,
+                1.0,
+            );
+            renderpass.set_scissor_rect(x, y, tile_size, tile_size);
+            renderpass.set_bind_group(
+                0,
+                &self.shadows.bind_group,
+                &[slot * self.shadows.view_stride],
+            );
+
+            let mut current_pipeline = None;
+            for draw in self.draws.iter() {
+                let Some(shadow_pipeline) = draw.shadow_pipeline else {
+                    continue;
+                };
+                let Some(surface) = self
+                    .meshes
+                    .get(&draw.mesh_id)
+                    .and_then(|mesh| mesh.surfaces.get(draw.surface_index))
+                else {
+                    continue;
+                };
+                if current_pipeline != Some(shadow_pipeline) {
+                    renderpass.set_pipeline(&self.shadows.pipelines[shadow_pipeline]);
+                    current_pipeline = Some(shadow_pipeline);
+                }
+                renderpass.set_vertex_buffer(0, surface.vertex_buffer.slice(..));
+                match surface.index_buffer.as_ref() {
+                    Some(indices) => {
+                        renderpass.set_index_buffer(indices.buffer.slice(..), indices.format);
+                        renderpass.draw_indexed(0..indices.count, 0, draw.instances.clone());
+                    }
+                    None => renderpass.draw(0..surface.vertex_count, draw.instances.clone()),
+                }
+            }
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        world: &crate::world::World,
+        target_size: (u32, u32),
+    ) {
+        let aspect_ratio = target_size.0 as f32 / target_size.1.max(1) as f32;
+        // Meshes are cached by id and uploaded again whenever the world changes their revision
+        self.meshes
+            .retain(|mesh_id, _| world.meshes.contains_key(mesh_id));
+        for (mesh_id, mesh) in world.meshes.iter() {
+            let revision = world.revisions.mesh(mesh_id);
+            if self
+                .meshes
+                .get(mesh_id)
+                .is_some_and(|gpu_mesh| gpu_mesh.revision == revision)
+            {
+                continue;
+            }
+            let gpu_mesh = self.upload_mesh(device, mesh_id, mesh, revision);
+            self.meshes.insert(mesh_id.clone(), gpu_mesh);
+        }
+
+        // Textures are uploaded on first use by a material and, like meshes, again whenever
+        // their revision changes
+        self.textures.retain(|(texture_id, _), gpu_texture| {
+            world.textures.contains_key(texture_id)
+                && world.revisions.texture(texture_id) == gpu_texture.revision
+        });
+
+        // Materials are cheap to rebuild, so edits to the world's copy or its textures are
+        // picked up
+        self.materials
+            .retain(|material_id, _| world.materials.contains_key(material_id));
+        for (material_id, material) in world.materials.iter() {
+            if self.materials.get(material_id).is_some_and(|gpu_material| {
+                gpu_material.source == *material
+                    && gpu_material.texture_revisions == Self::texture_revisions(world, material)
+            }) {
+                continue;
+            }
+            let gpu_material = self.upload_material(device, queue, world, material_id, material);
+            self.materials.insert(material_id.clone(), gpu_material);
+        }
+
+        let (view, projection, camera_position) = match world.active_camera() {
+            Some((scene_index, node_index)) => {
+                let camera = world
+                    .camera(scene_index, node_index)
+                    .cloned()
+                    .unwrap_or_default();
+                let camera_transform = world
+                    .global_transform(scene_index, node_index)
+                    .unwrap_or_else(nalgebra_glm::Mat4::identity);
+                (
+                    nalgebra_glm::inverse(&camera_transform),
+                    camera.projection_matrix(aspect_ratio),
+                    camera_transform.column(3).xyz(),
+                )
+            }
+            None => {
+                let camera_position = nalgebra_glm::vec3(0.0, 0.0, 3.0);
+                (
+                    nalgebra_glm::look_at_rh(
+                        &camera_position,
+                        &nalgebra_glm::vec3(0.0, 0.0, 0.0),
+                        &nalgebra_glm::Vec3::y(),
+                    ),
+                    crate::world::Camera3D::default().projection_matrix(aspect_ratio),
+                    camera_position,
+                )
+            }
+        };
+        self.uniform.update_buffer(
+            queue,
+            0,
+            UniformBuffer {
+                view_projection: projection * view,
+                camera_position: nalgebra_glm::vec3_to_vec4(&camera_position),
+            },
+        );
+        let shadow_views =
+            self.lighting
+                .update(device, queue, world, &view, &projection, target_size);
+        self.shadows.update(queue, &shadow_views);
+
+        // Group instances by mesh so each mesh is drawn once
+        let mut instances_by_mesh =
+            std::collections::BTreeMap::<crate::world::MeshId, Vec<InstanceData>>::new();
+        for (scene_index, scene) in world.scenes.iter().enumerate() {
+            for node_index in scene.node_indices() {
+                let crate::world::Node::VisualInstance3D(crate::world::VisualInstance3D::Geometry(
+                    crate::world::Geometry::MeshInstance3D(crate::world::MeshInstance3D {
+                        mesh_reference: Some(mesh_id),
+                    }),
+                )) = &scene[node_index]
+                else {
+                    continue;
+                };
+                if !self.meshes.contains_key(mesh_id) {
+                    continue;
+                }
+                let Some(model) = world.global_transform(scene_index, node_index) else {
+                    continue;
+                };
+                instances_by_mesh
+                    .entry(mesh_id.clone())
+                    .or_default()
+                    .push(InstanceData::new(&model));
+            }
+        }
+
+        self.draws.clear();
+        let mut blended_draws = Vec::new();
+        let mut instances = Vec::new();
+        for (mesh_id, mesh_instances) in instances_by_mesh.into_iter() {
+            let start = instances.len() as u32;
+            instances.extend(mesh_instances);
+            let mesh_instances = start..instances.len() as u32;
+
+            for (surface_index, pipeline_key, material_id) in self.surface_pipelines(&mesh_id) {
+                let pipeline = self.pipeline_id(device, pipeline_key.clone());
+                let blended = pipeline_key
+                    .material
+                    .is_some_and(|material| material.alpha_mode == BlendMode::Blend);
+                if !blended {
+                    // Only triangles have an area to cast shadows with. Masked surfaces cast
+                    // them as if they were opaque.
+                    let shadow_pipeline = match pipeline_key.geometry.topology {
+                        crate::world::PrimitiveTopology::TriangleList
+                        | crate::world::PrimitiveTopology::TriangleStrip => {
+                            Some(self.shadows.pipeline_id(device, &pipeline_key.geometry))
+                        }
+                        _ => None,
+                    };
+                    self.draws.push(DrawCommand {
+                        pipeline,
+                        shadow_pipeline,
+                        material: material_id,
+                        mesh_id: mesh_id.clone(),
+                        surface_index,
+                        instances: mesh_instances.clone(),
+                    });
+                    continue;
+                }
+                // Transparent instances are drawn one by one so they can be sorted by distance
+                for instance in mesh_instances.clone() {
+                    let position = nalgebra_glm::make_vec3(&instances[instance as usize].model[3]);
+                    let distance = nalgebra_glm::distance2(&position, &camera_position);
+                    blended_draws.push((
+                        distance,
+                        DrawCommand {
+                            pipeline,
+                            shadow_pipeline: None,
+                            material: material_id.clone(),
+                            mesh_id: mesh_id.clone(),
+                            surface_index,
+                            instances: instance..instance + 1,
+                        },
+                    ));
+                }
+            }
+        }
+
+        // Opaque surfaces are batched by pipeline then material, transparent ones are drawn
+        // afterwards from back to front
+        self.draws.sort_by(|a, b| {
+            (a.pipeline, &a.material, &a.mesh_id, a.surface_index).cmp(&(
+                b.pipeline,
+                &b.material,
+                &b.mesh_id,
+                b.surface_index,
+            ))
+        });
+        blended_draws.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        self.draws
+            .extend(blended_draws.into_iter().map(|(_, draw)| draw));
+
+        if instances.len() > self.instance_capacity {
+            self.instance_capacity = instances.len().next_power_of_two();
+            self.instance_buffer = Self::create_instance_buffer(device, self.instance_capacity);
+        }
+        queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
+    }
+
+    // The pipeline key and material of each surface of a mesh
+    fn surface_pipelines(
+        &mut self,
+        mesh_id: &crate::world::MeshId,
+    ) -> Vec<(usize, PipelineKey, Option<crate::world::MaterialId>)> {
+        let Some(mesh) = self.meshes.get(mesh_id) else {
+            return Vec::new();
+        };
+        let mut surfaces = Vec::with_capacity(mesh.surfaces.len());
+        for (surface_index, surface) in mesh.surfaces.iter().enumerate() {
+            let material = surface.material.as_ref().and_then(|material_id| {
+                match self.materials.get(material_id) {
+                    Some(material) => Some((material_id.clone(), material.key)),
+                    None => {
+                        if self.missing_materials.insert(material_id.clone()) {
+                            log::warn!("Mesh '{mesh_id}' uses missing material '{material_id}'");
+                        }
+                        None
+                    }
+                }
+            });
+            let (material_id, material_key) = material.unzip();
+            surfaces.push((
+                surface_index,
+                PipelineKey {
+                    geometry: surface.geometry_key.clone(),
+                    material: material_key,
+                },
+                material_id,
+            ));
+        }
+        surfaces
+    }
+
+    fn pipeline_id(&mut self, device: &wgpu::Device, key: PipelineKey) -> usize {
+        if let Some(pipeline_id) = self.pipeline_ids.get(&key) {
+            return *pipeline_id;
+        }
+        let pipeline = Self::create_pipeline(
+            device,
+            self.surface_format,
+            self.sample_count,
+            &self.uniform,
+            &self.material_bind_group_layout,
+            &self.lighting.bind_group_layout,
+            &key,
+        );
+        self.pipelines.push(pipeline);
+        self.pipeline_ids.insert(key, self.pipelines.len() - 1);
+        self.pipelines.len() - 1
+    }
+
+    fn upload_mesh(
+        &mut self,
+        device: &wgpu::Device,
+        mesh_id: &crate::world::MeshId,
+        mesh: &crate::world::Mesh,
+        revision: u64,
+    ) -> GpuMesh {
+        let surfaces = mesh
+            .surfaces()
+            .iter()
+            .enumerate()
+            .filter_map(|(surface_index, surface)| {
+                if let Err(error) = surface.validate() {
+                    log::warn!("Skipping surface {surface_index} of mesh '{mesh_id}': {error}");
+                    return None;
+                }
+                Some(self.upload_surface(device, mesh_id, surface))
+            })
+            .collect();
+        GpuMesh { surfaces, revision }
+    }
+
+    fn upload_surface(
+        &self,
+        device: &wgpu::Device,
+        mesh_id: &crate::world::MeshId,
+        surface: &crate::world::Surface,
+    ) -> GpuSurface {
+        let vertex_buffer = wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("Vertex Buffer ({mesh_id})")),
+                contents: &surface.attributes.interleave(),
+                usage: wgpu::BufferUsages::VERTEX,
+            },
+        );
+
+        let index_buffer = surface.indices.as_ref().map(|indices| {
+            let (contents, format) = match indices {
+                crate::world::Indices::U16(indices) => {
+                    // Buffer writes must be a multiple of four bytes
+                    let mut contents = bytemuck::cast_slice::<u16, u8>(indices).to_vec();
+                    contents.resize(contents.len().next_multiple_of(4), 0);
+                    (contents, wgpu::IndexFormat::Uint16)
+                }
+                crate::world::Indices::U32(indices) => (
+                    bytemuck::cast_slice::<u32, u8>(indices).to_vec(),
+                    wgpu::IndexFormat::Uint32,
+                ),
+            };
+            GpuIndices {
+                buffer: wgpu::util::DeviceExt::create_buffer_init(
+                    device,
+                    &wgpu::util::BufferInitDescriptor {
+                        label: Some(&format!("Index Buffer ({mesh_id})")),
+                        contents: &contents,
+                        usage: wgpu::BufferUsages::INDEX,
+                    },
+                ),
+                format,
+                count: indices.len() as _,
+            }
+        });
+
+        let strip_index_format = match surface.topology {
+            crate::world::PrimitiveTopology::LineStrip
+            | crate::world::PrimitiveTopology::TriangleStrip => {
+                index_buffer.as_ref().map(|indices| indices.format)
+            }
+            _ => None,
+        };
+        GpuSurface {
+            vertex_buffer,
+            vertex_count: surface.attributes.vertex_count() as _,
+            index_buffer,
+            geometry_key: GeometryKey {
+                layout: surface.attributes.layout(),
+                topology: surface.topology,
+                strip_index_format,
+            },
+            material: surface.material.clone(),
+        }
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instance Buffer"),
+            size: (capacity * std::mem::size_of::<InstanceData>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn upload_material(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        world: &crate::world::World,
+        material_id: &crate::world::MaterialId,
+        material: &crate::world::Material,
+    ) -> GpuMaterial {
+        let buffer = wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("Material Buffer ({material_id})")),
+                contents: bytemuck::cast_slice(&[MaterialUniform::from(material)]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            },
+        );
+
+        for slot in TextureSlot::ALL {
+            let Some(reference) = slot.reference(material) else {
+                continue;
+            };
+            let key = (reference.texture.clone(), slot.srgb());
+            if self.textures.contains_key(&key) {
+                continue;
+            }
+            let Some(texture) = world.textures.get(&reference.texture) else {
+                log::warn!(
+                    "Material '{material_id}' uses missing texture '{}'",
+                    reference.texture
+                );
+                continue;
+            };
+            let revision = world.revisions.texture(&reference.texture);
+            if let Some(gpu_texture) = Self::upload_texture(
+                device,
+                queue,
+                &reference.texture,
+                texture,
+                slot.srgb(),
+                revision,
+            ) {
+                self.textures.insert(key, gpu_texture);
+            }
+        }
+
+        let textures = TextureSlot::ALL.map(|slot| {
+            slot.reference(material)
+                .and_then(|reference| self.textures.get(&(reference.texture.clone(), slot.srgb())))
+                .unwrap_or(&self.fallback_texture)
+        });
+        for texture in &textures {
+            self.samplers
+                .entry(texture.sampler)
+                .or_insert_with(|| Self::create_sampler(device, &texture.sampler));
+        }
+        let mut entries = vec![wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+        }];
+        for (slot, texture) in textures.iter().enumerate() {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 1 + 2 * slot as u32,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            });
+            entries.push(wgpu::BindGroupEntry {
+                binding: 2 + 2 * slot as u32,
+                resource: wgpu::BindingResource::Sampler(&self.samplers[&texture.sampler]),
+            });
+        }
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("Material Bind Group ({material_id})")),
+            layout: &self.material_bind_group_layout,
+            entries: &entries,
+        });
+
+        GpuMaterial {
+            source: material.clone(),
+            texture_revisions: Self::texture_revisions(world, material),
+            key: MaterialKey::from(material),
+            bind_group,
+        }
+    }
+
+    // Uploads an image with its full mip chain. sRGB images use an sRGB format so sampling
+    // and filtering happen on linear values. `srgb` is how the material samples the texture,
+    // images that know their color space are uploaded in it regardless.
+    fn upload_texture(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        texture_id: &crate::world::TextureId,
+        texture: &crate::world::Texture,
+        srgb: bool,
+        revision: u64,
+    ) -> Option<GpuTexture> {
+        let image = &texture.image;
+        if image.width == 0
+            || image.height == 0
+            || (image.width as usize)
+                .checked_mul(image.height as usize)
+                .and_then(|texel_count| texel_count.checked_mul(4))
+                != Some(image.pixels.len())
+        {
+            log::warn!(
+                "Skipping texture '{texture_id}': {}x{} image has {} bytes of pixels",
+                image.width,
+                image.height,
+                image.pixels.len()
+            );
+            return None;
+        }
+        let color_space = image.color_space.unwrap_or(match srgb {
+            true => crate::texture::ColorSpace::Srgb,
+            false => crate::texture::ColorSpace::Linear,
+        });
+        let mips = crate::texture::generate_mips(image, color_space);
+        let gpu_texture = wgpu::util::DeviceExt::create_texture_with_data(
+            device,
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some(&format!("Texture ({texture_id})")),
+                size: wgpu::Extent3d {
+                    width: image.width,
+                    height: image.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: mips.len() as u32,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: match color_space {
+                    crate::texture::ColorSpace::Srgb => wgpu::TextureFormat::Rgba8UnormSrgb,
+                    crate::texture::ColorSpace::Linear => wgpu::TextureFormat::Rgba8Unorm,
+                },
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            &mips
+                .iter()
+                .flat_map(|mip| mip.pixels.iter().copied())
+                .collect::<Vec<_>>(),
+        );
+        Some(GpuTexture {
+            view: gpu_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            sampler: texture.sampler,
+            revision,
+        })
+    }
+
+    fn texture_revisions(
+        world: &crate::world::World,
+        material: &crate::world::Material,
+    ) -> [Option<u64>; 5] {
+        TextureSlot::ALL.map(|slot| {
+            let texture_id = &slot.reference(material)?.texture;
+            world
+                .textures
+                .contains_key(texture_id)
+                .then(|| world.revisions.texture(texture_id))
+        })
+    }
+
+    fn create_sampler(device: &wgpu::Device, sampler: &crate::world::Sampler) -> wgpu::Sampler {
+        device.create_sampler(&wgpu::SamplerDescriptor {
+            label: None,
+            address_mode_u: sampler.wrap_u.into(),
+            address_mode_v: sampler.wrap_v.into(),
+            mag_filter: sampler.mag_filter.into(),
+            min_filter: sampler.min_filter.into(),
+            mipmap_filter: sampler.mipmap_filter.into(),
+            ..Default::default()
+        })
+    }
+
+    // Bound in place of missing textures, white leaves the factors it is multiplied with as is
+    fn create_fallback_texture(device: &wgpu::Device, queue: &wgpu::Queue) -> GpuTexture {
+        let texture = crate::world::Texture {
+            image: crate::world::Image {
+                width: 1,
+                height: 1,
+                pixels: vec![255; 4],
+                color_space: None,
+            },
+            sampler: crate::world::Sampler::default(),
+        };
+        // Never part of the texture cache, so its revision is unused
+        Self::upload_texture(device, queue, &"fallback".to_string(), &texture, false, 0)
+            .expect("The fallback texture is valid")
+    }
+
+    fn create_material_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        let mut entries = vec![wgpu::BindGroupLayoutEntry {
+            binding: 0,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        }];
+        for slot in 0..TextureSlot::ALL.len() as u32 {
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 1 + 2 * slot,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            });
+            entries.push(wgpu::BindGroupLayoutEntry {
+                binding: 2 + 2 * slot,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            });
+        }
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("material_bind_group_layout"),
+            entries: &entries,
+        })
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        surface_format: wgpu::TextureFormat,
+        sample_count: u32,
+        uniform: &UniformBinding,
+        material_bind_group_layout: &wgpu::BindGroupLayout,
+        lighting_bind_group_layout: &wgpu::BindGroupLayout,
+        key: &PipelineKey,
+    ) -> wgpu::RenderPipeline {
+        let source = match key.material {
+            Some(material) => {
+                material_shader_source(&key.geometry.layout, material, !surface_format.is_srgb())
+            }
+            None => shader_source(&key.geometry.layout),
+        };
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Owned(source)),
+        });
+
+        let vertex_attributes = vertex_attributes(&key.geometry.layout);
+
+        let bind_group_layouts = match key.material {
+            Some(_) => vec![
+                &uniform.bind_group_layout,
+                material_bind_group_layout,
+                lighting_bind_group_layout,
+            ],
+            None => vec![&uniform.bind_group_layout],
+        };
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+
+        let alpha_mode = key.material.map(|material| material.alpha_mode);
+        let double_sided = key.material.is_some_and(|material| material.double_sided);
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vertex_main",
+                buffers: &[
+                    wgpu::VertexBufferLayout {
+                        array_stride: key
+                            .geometry
+                            .layout
+                            .iter()
+                            .map(crate::world::VertexAttribute::size)
+                            .sum::<usize>()
+                            as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &vertex_attributes,
+                    },
+                    InstanceData::description(&InstanceData::vertex_attributes()),
+                ],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: key.geometry.topology.into(),
+                strip_index_format: key.geometry.strip_index_format,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: match key.geometry.topology {
+                    crate::world::PrimitiveTopology::TriangleList
+                    | crate::world::PrimitiveTopology::TriangleStrip
+                        if !double_sided =>
+                    {
+                        Some(wgpu::Face::Back)
+                    }
+                    _ => None,
+                },
                 polygon_mode: wgpu::PolygonMode::Fill,
                 conservative: false,
                 unclipped_depth: false,
             },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: Renderer::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Renderer::DEPTH_FORMAT,
+                // Transparent surfaces are sorted instead and must not hide what is behind them
+                depth_write_enabled: alpha_mode != Some(BlendMode::Blend),
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: "fragment_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: surface_format,
+                    blend: match alpha_mode {
+                        Some(BlendMode::Opaque | BlendMode::Mask) => None,
+                        Some(BlendMode::Blend) | None => Some(wgpu::BlendState::ALPHA_BLENDING),
+                    },
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        })
+    }
+}
+
+fn vertex_format(attribute: crate::world::VertexAttribute) -> wgpu::VertexFormat {
+    match attribute {
+        crate::world::VertexAttribute::Position | crate::world::VertexAttribute::Normal => {
+            wgpu::VertexFormat::Float32x3
+        }
+        crate::world::VertexAttribute::Tangent
+        | crate::world::VertexAttribute::Color
+        | crate::world::VertexAttribute::Weights => wgpu::VertexFormat::Float32x4,
+        crate::world::VertexAttribute::Uv0 | crate::world::VertexAttribute::Uv1 => {
+            wgpu::VertexFormat::Float32x2
+        }
+        crate::world::VertexAttribute::Joints => wgpu::VertexFormat::Uint16x4,
+    }
+}
+
+// Each attribute has a fixed shader location so generated shaders agree on inputs
+fn shader_location(attribute: crate::world::VertexAttribute) -> wgpu::ShaderLocation {
+    match attribute {
+        crate::world::VertexAttribute::Position => 0,
+        crate::world::VertexAttribute::Normal => 1,
+        crate::world::VertexAttribute::Tangent => 2,
+        crate::world::VertexAttribute::Uv0 => 3,
+        crate::world::VertexAttribute::Uv1 => 4,
+        crate::world::VertexAttribute::Color => 5,
+        crate::world::VertexAttribute::Joints => 6,
+        crate::world::VertexAttribute::Weights => 7,
+    }
+}
+
+fn vertex_attributes(layout: &[crate::world::VertexAttribute]) -> Vec<wgpu::VertexAttribute> {
+    let mut offset = 0;
+    layout
+        .iter()
+        .map(|attribute| {
+            let vertex_attribute = wgpu::VertexAttribute {
+                format: vertex_format(*attribute),
+                offset,
+                shader_location: shader_location(*attribute),
+            };
+            offset += attribute.size() as wgpu::BufferAddress;
+            vertex_attribute
+        })
+        .collect()
+}
+
+fn shader_source(layout: &[crate::world::VertexAttribute]) -> String {
+    // Surfaces with no material, or one missing from the world, show their vertex colors or
+    // normal directions
+    let vertex_color = if layout.contains(&crate::world::VertexAttribute::Color) {
+        "vert.color"
+    } else if layout.contains(&crate::world::VertexAttribute::Normal) {
+        "vec4<f32>(normalize(vert.normal) * 0.5 + 0.5, 1.0)"
+    } else {
+        "vec4<f32>(1.0, 1.0, 1.0, 1.0)"
+    };
+
+    SHADER_SOURCE
+        .replace("{{vertex_inputs}}", &vertex_inputs(layout))
+        .replace("{{vertex_color}}", vertex_color)
+}
+
+// Attributes missing from the layout are replaced with defaults, so one shader template
+// covers every vertex layout
+fn material_shader_source(
+    layout: &[crate::world::VertexAttribute],
+    material: MaterialKey,
+    encode_srgb: bool,
+) -> String {
+    let has = |attribute| layout.contains(&attribute);
+    let uv0 = match has(crate::world::VertexAttribute::Uv0) {
+        true => "vert.uv0",
+        false => "vec2<f32>(0.0, 0.0)",
+    };
+    MATERIAL_SHADER_SOURCE
+        .replace("{{vertex_inputs}}", &vertex_inputs(layout))
+        .replace(
+            "{{normal}}",
+            match has(crate::world::VertexAttribute::Normal) {
+                true => "vert.normal",
+                false => "vec3<f32>(0.0, 0.0, 1.0)",
+            },
+        )
+        .replace(
+            "{{tangent}}",
+            match has(crate::world::VertexAttribute::Tangent) {
+                true => "vert.tangent",
+                false => "vec4<f32>(1.0, 0.0, 0.0, 1.0)",
+            },
+        )
+        .replace("{{uv0}}", uv0)
+        .replace(
+            "{{uv1}}",
+            match has(crate::world::VertexAttribute::Uv1) {
+                true => "vert.uv1",
+                false => uv0,
+            },
+        )
+        .replace(
+            "{{color}}",
+            match has(crate::world::VertexAttribute::Color) {
+                true => "vert.color",
+                false => "vec4<f32>(1.0, 1.0, 1.0, 1.0)",
+            },
+        )
+        .replace(
+            "{{has_normals}}",
+            &has(crate::world::VertexAttribute::Normal).to_string(),
+        )
+        .replace(
+            "{{has_tangents}}",
+            &has(crate::world::VertexAttribute::Tangent).to_string(),
+        )
+        .replace("{{lit}}", &(!material.unlit).to_string())
+        .replace(
+            "{{alpha_mode}}",
+            match material.alpha_mode {
+                BlendMode::Opaque => "0u",
+                BlendMode::Mask => "1u",
+                BlendMode::Blend => "2u",
+            },
+        )
+        .replace("{{encode_srgb}}", &encode_srgb.to_string())
+        .replace(
+            "{{max_directional_lights}}",
+            &crate::lighting::MAX_DIRECTIONAL_LIGHTS.to_string(),
+        )
+        .replace(
+            "{{shadow_atlas_tiles}}",
+            &format!("{}u", crate::shadow::SHADOW_ATLAS_TILES),
+        )
+        .replace("{{shadow_slots}}", &crate::shadow::SHADOW_SLOTS.to_string())
+        .replace(
+            "{{shadow_texel_size_count}}",
+            &(crate::shadow::SHADOW_SLOTS / 4).to_string(),
+        )
+}
+
+fn vertex_inputs(layout: &[crate::world::VertexAttribute]) -> String {
+    layout
+        .iter()
+        .map(|attribute| {
+            let (name, ty) = match attribute {
+                crate::world::VertexAttribute::Position => ("position", "vec3<f32>"),
+                crate::world::VertexAttribute::Normal => ("normal", "vec3<f32>"),
+                crate::world::VertexAttribute::Tangent => ("tangent", "vec4<f32>"),
+                crate::world::VertexAttribute::Uv0 => ("uv0", "vec2<f32>"),
+                crate::world::VertexAttribute::Uv1 => ("uv1", "vec2<f32>"),
+                crate::world::VertexAttribute::Color => ("color", "vec4<f32>"),
+                crate::world::VertexAttribute::Joints => ("joints", "vec4<u32>"),
+                crate::world::VertexAttribute::Weights => ("weights", "vec4<f32>"),
+            };
+            format!(
+                "    @location({}) {name}: {ty},\n",
+                shader_location(*attribute)
+            )
+        })
+        .collect()
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct InstanceData {
+    model: [[f32; 4]; 4],
+    // Inverse transpose of the model matrix, keeps normals perpendicular under non-uniform scale
+    normal: [[f32; 3]; 3],
+}
+
+impl InstanceData {
+    pub fn new(model: &nalgebra_glm::Mat4) -> Self {
+        let normal = nalgebra_glm::mat4_to_mat3(model)
+            .try_inverse()
+            .map(|inverse| inverse.transpose())
+            .unwrap_or_else(nalgebra_glm::Mat3::identity);
+        Self {
+            model: (*model).into(),
+            normal: normal.into(),
+        }
+    }
+
+    pub fn vertex_attributes() -> Vec<wgpu::VertexAttribute> {
+        wgpu::vertex_attr_array![
+            8 => Float32x4,
+            9 => Float32x4,
+            10 => Float32x4,
+            11 => Float32x4,
+            12 => Float32x3,
+            13 => Float32x3,
+            14 => Float32x3,
+        ]
+        .to_vec()
+    }
+
+    pub fn description(attributes: &[wgpu::VertexAttribute]) -> wgpu::VertexBufferLayout<'_> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<InstanceData>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct UniformBuffer {
+    view_projection: nalgebra_glm::Mat4,
+    camera_position: nalgebra_glm::Vec4,
+}
+
+struct UniformBinding {
+    pub buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+}
+
+impl UniformBinding {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let buffer = wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Uniform Buffer"),
+                contents: bytemuck::cast_slice(&[UniformBuffer::default()]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            },
+        );
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("uniform_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some("uniform_bind_group"),
+        });
+
+        Self {
+            buffer,
+            bind_group,
+            bind_group_layout,
+        }
+    }
+
+    pub fn update_buffer(
+        &mut self,
+        queue: &wgpu::Queue,
+        offset: wgpu::BufferAddress,
+        uniform_buffer: UniformBuffer,
+    ) {
+        queue.write_buffer(
+            &self.buffer,
+            offset,
+            bytemuck::cast_slice(&[uniform_buffer]),
+        )
+    }
+}
+
+// Matches `Lighting` in MATERIAL_SHADER_SOURCE
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightingUniform {
+    view: nalgebra_glm::Mat4,
+    directional_lights: [DirectionalLightData; crate::lighting::MAX_DIRECTIONAL_LIGHTS],
+    // Tiles across, tiles down, depth slices and the width of the light index texture
+    cluster_grid: [u32; 4],
+    // Size of a cluster tile in pixels
+    tile_size: [f32; 2],
+    near: f32,
+    slice_scale: f32,
+    directional_count: u32,
+    // Size of a shadow atlas texel in uv units
+    shadow_texel: f32,
+    _padding: [u32; 2],
+    // Light space view projection of each shadow atlas slot
+    shadow_views: [nalgebra_glm::Mat4; crate::shadow::SHADOW_SLOTS],
+    // World size of each slot's texels, four slots per element
+    shadow_texel_sizes: [[f32; 4]; crate::shadow::SHADOW_SLOTS / 4],
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct DirectionalLightData {
+    direction: [f32; 4],
+    radiance: [f32; 4],
+    // View depth where each cascade ends
+    cascade_splits: [f32; 4],
+    // First shadow atlas slot and cascade count, which is zero without shadows
+    shadow_slots: [u32; 4],
+    // Depth and normal bias in shadow map texels
+    shadow_bias: [f32; 4],
+}
+
+// The scene's lights and their clustered index lists. Omni and spot lights live in textures
+// rather than storage buffers so the same shader runs on WebGL.
+struct LightingBinding {
+    pub buffer: wgpu::Buffer,
+    // `LIGHT_TEXELS` texels per light, one light per row
+    pub light_texture: wgpu::Texture,
+    // The offset and count of each cluster's light indices, one slice per row
+    pub cluster_texture: wgpu::Texture,
+    // Light indices of all clusters, wrapped into rows of `INDEX_TEXTURE_WIDTH`
+    pub index_texture: wgpu::Texture,
+    // Depth maps of every shadow casting light, a placeholder texel until a light casts one
+    pub shadow_atlas: wgpu::TextureView,
+    pub shadow_atlas_size: u32,
+    pub shadow_sampler: wgpu::Sampler,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+    // Dropped lights and shadows are only reported once rather than every frame
+    pub overflow_reported: bool,
+    pub shadow_overflow_reported: bool,
+}
+
+impl LightingBinding {
+    const LIGHT_TEXELS: u32 = 4;
+    // Split into `SHADOW_ATLAS_TILES` tiles along each side, capped by the device's limits
+    const SHADOW_ATLAS_SIZE: u32 = 4096;
+    const INDEX_TEXTURE_WIDTH: u32 = 1024;
+    const INITIAL_LIGHT_CAPACITY: u32 = 64;
+    const INITIAL_INDEX_ROWS: u32 = 16;
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        let buffer = wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Lighting Buffer"),
+                contents: bytemuck::cast_slice(&[LightingUniform::default()]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            },
+        );
+        let light_texture = Self::create_light_texture(device, Self::INITIAL_LIGHT_CAPACITY);
+        let cluster_texture = Self::create_data_texture(
+            device,
+            "Light Cluster Texture",
+            wgpu::TextureFormat::Rg32Uint,
+            crate::lighting::CLUSTER_TILES_X * crate::lighting::CLUSTER_TILES_Y,
+            crate::lighting::CLUSTER_SLICES,
+        );
+        let index_texture = Self::create_index_texture(device, Self::INITIAL_INDEX_ROWS);
+        let shadow_atlas = Gpu::create_depth_view(device, "Shadow Atlas", 1, 1, 1);
+        // Linear filtering blends four depth comparisons, smoothing the edges between taps
+        let shadow_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                texture_entry(1, wgpu::TextureSampleType::Float { filterable: false }),
+                texture_entry(2, wgpu::TextureSampleType::Uint),
+                texture_entry(3, wgpu::TextureSampleType::Uint),
+                texture_entry(4, wgpu::TextureSampleType::Depth),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+            ],
+            label: Some("lighting_bind_group_layout"),
+        });
+        let bind_group = Self::create_bind_group(
+            device,
+            &bind_group_layout,
+            &buffer,
+            [&light_texture, &cluster_texture, &index_texture],
+            &shadow_atlas,
+            &shadow_sampler,
+        );
+
+        Self {
+            buffer,
+            light_texture,
+            cluster_texture,
+            index_texture,
+            shadow_atlas,
+            shadow_atlas_size: 1,
+            shadow_sampler,
+            bind_group_layout,
+            bind_group,
+            overflow_reported: false,
+            shadow_overflow_reported: false,
+        }
+    }
+
+    // Atlas tiles are square, one per shadow map
+    pub fn shadow_tile_size(&self) -> u32 {
+        self.shadow_atlas_size / crate::shadow::SHADOW_ATLAS_TILES
+    }
+
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        world: &crate::world::World,
+        view: &nalgebra_glm::Mat4,
+        projection: &nalgebra_glm::Mat4,
+        target_size: (u32, u32),
+    ) -> Vec<crate::shadow::ShadowView> {
+        let mut lights = crate::lighting::collect_lights(world);
+        let max_dimension = device.limits().max_texture_dimension_2d;
+        if lights.directional.len() > crate::lighting::MAX_DIRECTIONAL_LIGHTS
+            || lights.point.len() > max_dimension as usize
+        {
+            if !self.overflow_reported {
+                log::warn!(
+                    "Too many lights, only {} directional and {max_dimension} omni or spot \
+                     lights are drawn",
+                    crate::lighting::MAX_DIRECTIONAL_LIGHTS,
+                );
+                self.overflow_reported = true;
+            }
+            lights
+                .directional
+                .truncate(crate::lighting::MAX_DIRECTIONAL_LIGHTS);
+            lights.point.truncate(max_dimension as usize);
+        }
+        let mut clusters = crate::lighting::build_clusters(&lights.point, view, projection);
+
+        let mut resized = false;
+        let casts_shadows = lights
+            .directional
+            .iter()
+            .any(|light| light.shadow.is_some())
+            || lights.point.iter().any(|light| light.shadow.is_some());
+        if casts_shadows && self.shadow_atlas_size == 1 {
+            self.shadow_atlas_size = Self::SHADOW_ATLAS_SIZE.min(max_dimension);
+            self.shadow_atlas = Gpu::create_depth_view(
+                device,
+                "Shadow Atlas",
+                self.shadow_atlas_size,
+                self.shadow_atlas_size,
+                1,
+            );
+            resized = true;
+        }
+        let shadows =
+            crate::shadow::plan_shadows(&lights, view, projection, self.shadow_tile_size());
+        if shadows.overflowed && !self.shadow_overflow_reported {
+            log::warn!(
+                "Too many shadow casting lights, only {} shadow maps fit in the atlas",
+                crate::shadow::SHADOW_SLOTS
+            );
+            self.shadow_overflow_reported = true;
+        }
+
+        if lights.point.len() as u32 > self.light_texture.height() {
+            let capacity = (lights.point.len() as u32).next_power_of_two();
+            self.light_texture = Self::create_light_texture(device, capacity.min(max_dimension));
+            resized = true;
+        }
+        let index_rows = (clusters.indices.len() as u32).div_ceil(Self::INDEX_TEXTURE_WIDTH);
+        if index_rows > self.index_texture.height() {
+            let rows = index_rows.next_power_of_two().min(max_dimension);
+            self.index_texture = Self::create_index_texture(device, rows);
+            resized = true;
+        }
+        if resized {
+            self.bind_group = Self::create_bind_group(
+                device,
+                &self.bind_group_layout,
+                &self.buffer,
+                [
+                    &self.light_texture,
+                    &self.cluster_texture,
+                    &self.index_texture,
+                ],
+                &self.shadow_atlas,
+                &self.shadow_sampler,
+            );
+        }
+
+        // Clusters whose indices don't fit in the largest possible texture lose their tail
+        let index_capacity = self.index_texture.height() * Self::INDEX_TEXTURE_WIDTH;
+        if clusters.indices.len() as u32 > index_capacity {
+            for range in clusters.ranges.iter_mut() {
+                range[1] = range[1].min(index_capacity.saturating_sub(range[0]));
+            }
+            clusters.indices.truncate(index_capacity as usize);
+        }
+
+        let mut directional_lights =
+            [DirectionalLightData::default(); crate::lighting::MAX_DIRECTIONAL_LIGHTS];
+        for ((data, light), shadow) in directional_lights
+            .iter_mut()
+            .zip(&lights.directional)
+            .zip(&shadows.directional)
+        {
+            *data = DirectionalLightData {
+                direction: nalgebra_glm::vec3_to_vec4(&light.direction).into(),
+                radiance: nalgebra_glm::vec3_to_vec4(&light.radiance).into(),
+                ..Default::default()
+            };
+            if let Some(shadow) = shadow {
+                data.cascade_splits = shadow.splits;
+                data.shadow_slots = [shadow.first_slot, shadow.cascade_count, 0, 0];
+                data.shadow_bias = [
+                    shadow.settings.depth_bias,
+                    shadow.settings.normal_bias,
+                    0.0,
+                    0.0,
+                ];
+            }
+        }
+        let mut shadow_views = [nalgebra_glm::Mat4::identity(); crate::shadow::SHADOW_SLOTS];
+        let mut shadow_texel_sizes = [[0.0; 4]; crate::shadow::SHADOW_SLOTS / 4];
+        for (slot, shadow_view) in shadows.views.iter().enumerate() {
+            shadow_views[slot] = shadow_view.view_projection;
+            shadow_texel_sizes[slot / 4][slot % 4] = shadow_view.texel_size;
+        }
+        let uniform = LightingUniform {
+            view: *view,
+            directional_lights,
+            cluster_grid: [
+                crate::lighting::CLUSTER_TILES_X,
+                crate::lighting::CLUSTER_TILES_Y,
+                crate::lighting::CLUSTER_SLICES,
+                Self::INDEX_TEXTURE_WIDTH,
+            ],
+            tile_size: [
+                target_size.0 as f32 / crate::lighting::CLUSTER_TILES_X as f32,
+                target_size.1 as f32 / crate::lighting::CLUSTER_TILES_Y as f32,
+            ],
+            near: clusters.near,
+            slice_scale: clusters.slice_scale,
+            directional_count: lights.directional.len() as u32,
+            shadow_texel: 1.0 / self.shadow_atlas_size as f32,
+            _padding: [0; 2],
+            shadow_views,
+            shadow_texel_sizes,
+        };
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+        let light_texels = lights
+            .point
+            .iter()
+            .zip(&shadows.point)
+            .flat_map(|(light, shadow)| {
+                // Lights without shadows have a negative slot
+                let shadow = match shadow {
+                    Some(shadow) => [
+                        shadow.slot as f32,
+                        shadow.settings.depth_bias,
+                        shadow.settings.normal_bias,
+                        0.0,
+                    ],
+                    None => [-1.0, 0.0, 0.0, 0.0],
+                };
+                [
+                    [
+                        light.position.x,
+                        light.position.y,
+                        light.position.z,
+                        light.range,
+                    ],
+                    [
+                        light.direction.x,
+                        light.direction.y,
+                        light.direction.z,
+                        light.cone_scale,
+                    ],
+                    [
+                        light.radiance.x,
+                        light.radiance.y,
+                        light.radiance.z,
+                        light.cone_offset,
+                    ],
+                    shadow,
+                ]
+            })
+            .collect::<Vec<_>>();
+        Self::write_rows(
+            queue,
+            &self.light_texture,
+            bytemuck::cast_slice(&light_texels),
+            lights.point.len() as u32,
+        );
+        Self::write_rows(
+            queue,
+            &self.cluster_texture,
+            bytemuck::cast_slice(&clusters.ranges),
+            crate::lighting::CLUSTER_SLICES,
+        );
+        let mut indices = clusters.indices;
+        indices.resize(
+            (indices.len() as u32).next_multiple_of(Self::INDEX_TEXTURE_WIDTH) as usize,
+            0,
+        );
+        Self::write_rows(
+            queue,
+            &self.index_texture,
+            bytemuck::cast_slice(&indices),
+            indices.len() as u32 / Self::INDEX_TEXTURE_WIDTH,
+        );
+        shadows.views
+    }
+
+    // Writes the first rows of a texture, `data` covers the texture's full width
+    fn write_rows(queue: &wgpu::Queue, texture: &wgpu::Texture, data: &[u8], rows: u32) {
+        if rows == 0 {
+            return;
+        }
+        queue.write_texture(
+            texture.as_image_copy(),
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(data.len() as u32 / rows),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: texture.width(),
+                height: rows,
+                depth_or_array_layers: 1,
             },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader_module,
-                entry_point: "fragment_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: surface_format,
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            multiview: None,
-        })
+        );
     }
-}
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct Vertex {
-    position: [f32; 4],
-    color: [f32; 4],
-}
+    fn create_light_texture(device: &wgpu::Device, capacity: u32) -> wgpu::Texture {
+        Self::create_data_texture(
+            device,
+            "Light Texture",
+            wgpu::TextureFormat::Rgba32Float,
+            Self::LIGHT_TEXELS,
+            capacity,
+        )
+    }
 
-impl Vertex {
-    pub fn vertex_attributes() -> Vec<wgpu::VertexAttribute> {
-        wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x4].to_vec()
+    fn create_index_texture(device: &wgpu::Device, rows: u32) -> wgpu::Texture {
+        Self::create_data_texture(
+            device,
+            "Light Index Texture",
+            wgpu::TextureFormat::R32Uint,
+            Self::INDEX_TEXTURE_WIDTH,
+            rows,
+        )
     }
 
-    pub fn description(attributes: &[wgpu::VertexAttribute]) -> wgpu::VertexBufferLayout {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes,
-        }
+    fn create_data_texture(
+        device: &wgpu::Device,
+        label: &str,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) -> wgpu::Texture {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        })
     }
-}
 
-#[repr(C)]
-#[derive(Default, Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-struct UniformBuffer {
-    mvp: nalgebra_glm::Mat4,
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        buffer: &wgpu::Buffer,
+        textures: [&wgpu::Texture; 3],
+        shadow_atlas: &wgpu::TextureView,
+        shadow_sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        let views = textures.map(|texture| texture.create_view(&Default::default()));
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&views[0]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&views[1]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&views[2]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(shadow_atlas),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::Sampler(shadow_sampler),
+                },
+            ],
+            label: Some("lighting_bind_group"),
+        })
+    }
 }
 
-struct UniformBinding {
+// Renders the depth of every shadow caster into the atlas slots planned by `LightingBinding`
+struct ShadowPass {
+    // The view projection of each slot, `view_stride` bytes apart for dynamic offsets
     pub buffer: wgpu::Buffer,
-    pub bind_group: wgpu::BindGroup,
+    pub view_stride: u32,
     pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+    // Depth only pipelines only depend on the surface's geometry
+    pub pipelines: Vec<wgpu::RenderPipeline>,
+    pub pipeline_ids: std::collections::HashMap<GeometryKey, usize>,
+    pub slot_count: u32,
 }
 
-impl UniformBinding {
+impl ShadowPass {
     pub fn new(device: &wgpu::Device) -> Self {
-        let buffer = wgpu::util::DeviceExt::create_buffer_init(
-            device,
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Uniform Buffer"),
-                contents: bytemuck::cast_slice(&[UniformBuffer::default()]),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            },
-        );
-
+        let view_size = std::mem::size_of::<nalgebra_glm::Mat4>() as u32;
+        let view_stride =
+            view_size.next_multiple_of(device.limits().min_uniform_buffer_offset_alignment);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Shadow View Buffer"),
+            size: (view_stride as usize * crate::shadow::SHADOW_SLOTS) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[wgpu::BindGroupLayoutEntry {
                 binding: 0,
                 visibility: wgpu::ShaderStages::VERTEX,
                 ty: wgpu::BindingType::Buffer {
                     ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(view_size as u64),
                 },
                 count: None,
             }],
-            label: Some("uniform_bind_group_layout"),
+            label: Some("shadow_bind_group_layout"),
         });
-
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &bind_group_layout,
             entries: &[wgpu::BindGroupEntry {
                 binding: 0,
-                resource: buffer.as_entire_binding(),
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(view_size as u64),
+                }),
             }],
-            label: Some("uniform_bind_group"),
+            label: Some("shadow_bind_group"),
         });
 
         Self {
             buffer,
-            bind_group,
+            view_stride,
             bind_group_layout,
+            bind_group,
+            pipelines: Vec::new(),
+            pipeline_ids: std::collections::HashMap::new(),
+            slot_count: 0,
         }
     }
 
-    pub fn update_buffer(
-        &mut self,
-        queue: &wgpu::Queue,
-        offset: wgpu::BufferAddress,
-        uniform_buffer: UniformBuffer,
-    ) {
-        queue.write_buffer(
-            &self.buffer,
-            offset,
-            bytemuck::cast_slice(&[uniform_buffer]),
-        )
+    pub fn update(&mut self, queue: &wgpu::Queue, views: &[crate::shadow::ShadowView]) {
+        self.slot_count = views.len() as u32;
+        let mut contents = vec![0; self.view_stride as usize * views.len()];
+        for (slot, view) in views.iter().enumerate() {
+            let offset = slot * self.view_stride as usize;
+            let matrix = bytemuck::bytes_of(&view.view_projection);
+            contents[offset..offset + matrix.len()].copy_from_slice(matrix);
+        }
+        if !contents.is_empty() {
+            queue.write_buffer(&self.buffer, 0, &contents);
+        }
+    }
+
+    pub fn pipeline_id(&mut self, device: &wgpu::Device, key: &GeometryKey) -> usize {
+        if let Some(pipeline_id) = self.pipeline_ids.get(key) {
+            return *pipeline_id;
+        }
+        let pipeline = Self::create_pipeline(device, &self.bind_group_layout, key);
+        self.pipelines.push(pipeline);
+        self.pipeline_ids
+            .insert(key.clone(), self.pipelines.len() - 1);
+        self.pipelines.len() - 1
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        key: &GeometryKey,
+    ) -> wgpu::RenderPipeline {
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shadow Shader"),
+            source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(SHADOW_SHADER_SOURCE)),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        // Only positions are read, the stride still skips over the other attributes
+        let vertex_attributes = vertex_attributes(&key.layout)
+            .into_iter()
+            .filter(|attribute| {
+                attribute.shader_location
+                    == shader_location(crate::world::VertexAttribute::Position)
+            })
+            .collect::<Vec<_>>();
+        let instance_attributes = InstanceData::vertex_attributes();
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Shadow Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: "vertex_main",
+                buffers: &[
+                    wgpu::VertexBufferLayout {
+                        array_stride: key
+                            .layout
+                            .iter()
+                            .map(crate::world::VertexAttribute::size)
+                            .sum::<usize>()
+                            as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &vertex_attributes,
+                    },
+                    // Just the model matrix
+                    InstanceData::description(&instance_attributes[..4]),
+                ],
+            },
+            // Both faces cast, so open meshes and planes shadow from either side
+            primitive: wgpu::PrimitiveState {
+                topology: key.topology.into(),
+                strip_index_format: key.strip_index_format,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+                unclipped_depth: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: Renderer::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: None,
+            multiview: None,
+        })
+    }
+}
+
+// Matches `Material` in MATERIAL_SHADER_SOURCE
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct MaterialUniform {
+    base_color_factor: [f32; 4],
+    emissive_factor: [f32; 3],
+    metallic_factor: f32,
+    roughness_factor: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+    alpha_cutoff: f32,
+    // UV set of the base color, metallic-roughness, normal and occlusion textures
+    uv_sets: [u32; 4],
+    emissive_uv_set: u32,
+    has_normal_texture: u32,
+    _padding: [u32; 2],
+}
+
+impl From<&crate::world::Material> for MaterialUniform {
+    fn from(material: &crate::world::Material) -> Self {
+        let uv_set = |slot: TextureSlot| {
+            slot.reference(material)
+                .map(|reference| reference.uv_set)
+                .unwrap_or(0)
+        };
+        Self {
+            base_color_factor: material.base_color_factor,
+            emissive_factor: material.emissive_factor,
+            metallic_factor: material.metallic_factor,
+            roughness_factor: material.roughness_factor,
+            normal_scale: material.normal_scale,
+            occlusion_strength: material.occlusion_strength,
+            alpha_cutoff: match material.alpha_mode {
+                crate::world::AlphaMode::Mask { cutoff } => cutoff,
+                _ => 0.0,
+            },
+            uv_sets: [
+                uv_set(TextureSlot::BaseColor),
+                uv_set(TextureSlot::MetallicRoughness),
+                uv_set(TextureSlot::Normal),
+                uv_set(TextureSlot::Occlusion),
+            ],
+            emissive_uv_set: uv_set(TextureSlot::Emissive),
+            has_normal_texture: material.normal_texture.is_some() as u32,
+            _padding: [0; 2],
+        }
+    }
+}
+
+// Material textures in binding order
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum TextureSlot {
+    BaseColor,
+    MetallicRoughness,
+    Normal,
+    Occlusion,
+    Emissive,
+}
+
+impl TextureSlot {
+    const ALL: [Self; 5] = [
+        Self::BaseColor,
+        Self::MetallicRoughness,
+        Self::Normal,
+        Self::Occlusion,
+        Self::Emissive,
+    ];
+
+    fn reference(
+        self,
+        material: &crate::world::Material,
+    ) -> Option<&crate::world::TextureReference> {
+        match self {
+            Self::BaseColor => material.base_color_texture.as_ref(),
+            Self::MetallicRoughness => material.metallic_roughness_texture.as_ref(),
+            Self::Normal => material.normal_texture.as_ref(),
+            Self::Occlusion => material.occlusion_texture.as_ref(),
+            Self::Emissive => material.emissive_texture.as_ref(),
+        }
+    }
+
+    // Colors are authored in sRGB, everything else holds linear data
+    fn srgb(self) -> bool {
+        matches!(self, Self::BaseColor | Self::Emissive)
+    }
+}
+
+impl From<crate::world::Filter> for wgpu::FilterMode {
+    fn from(filter: crate::world::Filter) -> Self {
+        match filter {
+            crate::world::Filter::Nearest => Self::Nearest,
+            crate::world::Filter::Linear => Self::Linear,
+        }
     }
 }
 
-const VERTICES: [Vertex; 3] = [
-    Vertex {
-        position: [1.0, -1.0, 0.0, 1.0],
-        color: [1.0, 0.0, 0.0, 1.0],
-    },
-    Vertex {
-        position: [-1.0, -1.0, 0.0, 1.0],
-        color: [0.0, 1.0, 0.0, 1.0],
-    },
-    Vertex {
-        position: [0.0, 1.0, 0.0, 1.0],
-        color: [0.0, 0.0, 1.0, 1.0],
-    },
-];
+impl From<crate::world::WrapMode> for wgpu::AddressMode {
+    fn from(wrap_mode: crate::world::WrapMode) -> Self {
+        match wrap_mode {
+            crate::world::WrapMode::ClampToEdge => Self::ClampToEdge,
+            crate::world::WrapMode::MirroredRepeat => Self::MirrorRepeat,
+            crate::world::WrapMode::Repeat => Self::Repeat,
+        }
+    }
+}
 
-const INDICES: [u32; 3] = [0, 1, 2]; // Clockwise winding order
+impl From<crate::world::PrimitiveTopology> for wgpu::PrimitiveTopology {
+    fn from(topology: crate::world::PrimitiveTopology) -> Self {
+        match topology {
+            crate::world::PrimitiveTopology::PointList => Self::PointList,
+            crate::world::PrimitiveTopology::LineList => Self::LineList,
+            crate::world::PrimitiveTopology::LineStrip => Self::LineStrip,
+            crate::world::PrimitiveTopology::TriangleList => Self::TriangleList,
+            crate::world::PrimitiveTopology::TriangleStrip => Self::TriangleStrip,
+        }
+    }
+}
 
 const SHADER_SOURCE: &str = "
 struct Uniform {
-    mvp: mat4x4<f32>,
+    view_projection: mat4x4<f32>,
+    camera_position: vec4<f32>,
 };
 
 @group(0) @binding(0)
 var<uniform> ubo: Uniform;
 
 struct VertexInput {
-    @location(0) position: vec4<f32>,
-    @location(1) color: vec4<f32>,
+{{vertex_inputs}}};
+struct InstanceInput {
+    @location(8) model_0: vec4<f32>,
+    @location(9) model_1: vec4<f32>,
+    @location(10) model_2: vec4<f32>,
+    @location(11) model_3: vec4<f32>,
 };
 struct VertexOutput {
     @builtin(position) position: vec4<f32>,
@@ -527,10 +2783,11 @@ struct VertexOutput {
 };
 
 @vertex
-fn vertex_main(vert: VertexInput) -> VertexOutput {
+fn vertex_main(vert: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
     var out: VertexOutput;
-    out.color = vert.color;
-    out.position = ubo.mvp * vert.position;
+    out.color = {{vertex_color}};
+    out.position = ubo.view_projection * model * vec4<f32>(vert.position, 1.0);
     return out;
 };
 
@@ -539,3 +2796,380 @@ fn fragment_main(in: VertexOutput) -> @location(0) vec4<f32> {
     return vec4<f32>(in.color);
 }
 ";
+
+const SHADOW_SHADER_SOURCE: &str = "
+@group(0) @binding(0)
+var<uniform> view_projection: mat4x4<f32>;
+
+struct InstanceInput {
+    @location(8) model_0: vec4<f32>,
+    @location(9) model_1: vec4<f32>,
+    @location(10) model_2: vec4<f32>,
+    @location(11) model_3: vec4<f32>,
+};
+
+@vertex
+fn vertex_main(@location(0) position: vec3<f32>, instance: InstanceInput) -> @builtin(position) vec4<f32> {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    return view_projection * model * vec4<f32>(position, 1.0);
+}
+";
+
+// glTF 2.0 metallic-roughness shading, see Appendix B of the specification
+const MATERIAL_SHADER_SOURCE: &str = "
+const LIT: bool = {{lit}};
+const HAS_NORMALS: bool = {{has_normals}};
+const HAS_TANGENTS: bool = {{has_tangents}};
+const ENCODE_SRGB: bool = {{encode_srgb}};
+
+const ALPHA_MODE: u32 = {{alpha_mode}};
+const ALPHA_MODE_MASK: u32 = 1u;
+const ALPHA_MODE_BLEND: u32 = 2u;
+
+const PI: f32 = 3.14159265359;
+
+const AMBIENT_RADIANCE: vec3<f32> = vec3<f32>(0.03, 0.03, 0.03);
+
+const SHADOW_ATLAS_TILES: u32 = {{shadow_atlas_tiles}};
+
+struct Uniform {
+    view_projection: mat4x4<f32>,
+    camera_position: vec4<f32>,
+};
+
+struct Material {
+    base_color_factor: vec4<f32>,
+    emissive_factor: vec3<f32>,
+    metallic_factor: f32,
+    roughness_factor: f32,
+    normal_scale: f32,
+    occlusion_strength: f32,
+    alpha_cutoff: f32,
+    uv_sets: vec4<u32>,
+    emissive_uv_set: u32,
+    has_normal_texture: u32,
+};
+
+struct DirectionalLight {
+    direction: vec4<f32>,
+    radiance: vec4<f32>,
+    cascade_splits: vec4<f32>,
+    shadow_slots: vec4<u32>,
+    shadow_bias: vec4<f32>,
+};
+
+struct Lighting {
+    view: mat4x4<f32>,
+    directional_lights: array<DirectionalLight, {{max_directional_lights}}>,
+    cluster_grid: vec4<u32>,
+    tile_size: vec2<f32>,
+    near: f32,
+    slice_scale: f32,
+    directional_count: u32,
+    shadow_texel: f32,
+    shadow_views: array<mat4x4<f32>, {{shadow_slots}}>,
+    shadow_texel_sizes: array<vec4<f32>, {{shadow_texel_size_count}}>,
+};
+
+@group(0) @binding(0)
+var<uniform> ubo: Uniform;
+
+@group(1) @binding(0)
+var<uniform> material: Material;
+@group(1) @binding(1)
+var base_color_texture: texture_2d<f32>;
+@group(1) @binding(2)
+var base_color_sampler: sampler;
+@group(1) @binding(3)
+var metallic_roughness_texture: texture_2d<f32>;
+@group(1) @binding(4)
+var metallic_roughness_sampler: sampler;
+@group(1) @binding(5)
+var normal_texture: texture_2d<f32>;
+@group(1) @binding(6)
+var normal_sampler: sampler;
+@group(1) @binding(7)
+var occlusion_texture: texture_2d<f32>;
+@group(1) @binding(8)
+var occlusion_sampler: sampler;
+@group(1) @binding(9)
+var emissive_texture: texture_2d<f32>;
+@group(1) @binding(10)
+var emissive_sampler: sampler;
+
+@group(2) @binding(0)
+var<uniform> lighting: Lighting;
+@group(2) @binding(1)
+var light_texture: texture_2d<f32>;
+@group(2) @binding(2)
+var cluster_texture: texture_2d<u32>;
+@group(2) @binding(3)
+var light_index_texture: texture_2d<u32>;
+@group(2) @binding(4)
+var shadow_atlas: texture_depth_2d;
+@group(2) @binding(5)
+var shadow_sampler: sampler_comparison;
+
+struct VertexInput {
+{{vertex_inputs}}};
+struct InstanceInput {
+    @location(8) model_0: vec4<f32>,
+    @location(9) model_1: vec4<f32>,
+    @location(10) model_2: vec4<f32>,
+    @location(11) model_3: vec4<f32>,
+    @location(12) normal_0: vec3<f32>,
+    @location(13) normal_1: vec3<f32>,
+    @location(14) normal_2: vec3<f32>,
+};
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) tangent: vec4<f32>,
+    @location(3) uv0: vec2<f32>,
+    @location(4) uv1: vec2<f32>,
+    @location(5) color: vec4<f32>,
+};
+
+@vertex
+fn vertex_main(vert: VertexInput, instance: InstanceInput) -> VertexOutput {
+    let model = mat4x4<f32>(instance.model_0, instance.model_1, instance.model_2, instance.model_3);
+    let normal_matrix = mat3x3<f32>(instance.normal_0, instance.normal_1, instance.normal_2);
+    let world_position = model * vec4<f32>(vert.position, 1.0);
+    let tangent = {{tangent}};
+    var out: VertexOutput;
+    out.position = ubo.view_projection * world_position;
+    out.world_position = world_position.xyz;
+    out.normal = normal_matrix * {{normal}};
+    out.tangent = vec4<f32>((model * vec4<f32>(tangent.xyz, 0.0)).xyz, tangent.w);
+    out.uv0 = {{uv0}};
+    out.uv1 = {{uv1}};
+    out.color = {{color}};
+    return out;
+};
+
+fn material_uv(in: VertexOutput, uv_set: u32) -> vec2<f32> {
+    return select(in.uv0, in.uv1, uv_set == 1u);
+}
+
+// Tangent frame from screen space derivatives, for meshes without tangents
+fn cotangent_frame(
+    normal: vec3<f32>,
+    position_dx: vec3<f32>,
+    position_dy: vec3<f32>,
+    uv_dx: vec2<f32>,
+    uv_dy: vec2<f32>,
+) -> mat3x3<f32> {
+    let dy_perpendicular = cross(position_dy, normal);
+    let dx_perpendicular = cross(normal, position_dx);
+    let tangent = dy_perpendicular * uv_dx.x + dx_perpendicular * uv_dy.x;
+    let bitangent = dy_perpendicular * uv_dx.y + dx_perpendicular * uv_dy.y;
+    let scale = inverseSqrt(max(max(dot(tangent, tangent), dot(bitangent, bitangent)), 1e-12));
+    return mat3x3<f32>(tangent * scale, bitangent * scale, normal);
+}
+
+// Outgoing radiance towards the viewer for light arriving from one direction
+fn shade_light(
+    normal: vec3<f32>,
+    view: vec3<f32>,
+    light: vec3<f32>,
+    radiance: vec3<f32>,
+    diffuse_color: vec3<f32>,
+    f0: vec3<f32>,
+    roughness: f32,
+) -> vec3<f32> {
+    let half_vector = normalize(view + light);
+    let n_dot_l = max(dot(normal, light), 0.0);
+    let n_dot_v = max(dot(normal, view), 1e-4);
+    let n_dot_h = max(dot(normal, half_vector), 0.0);
+    let v_dot_h = max(dot(view, half_vector), 0.0);
+
+    let alpha = roughness * roughness;
+    let alpha_squared = alpha * alpha;
+    let d_denominator = n_dot_h * n_dot_h * (alpha_squared - 1.0) + 1.0;
+    let distribution = alpha_squared / (PI * d_denominator * d_denominator);
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let geometry = n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+    let fresnel = f0 + (1.0 - f0) * pow(1.0 - v_dot_h, 5.0);
+
+    let specular = distribution * geometry * fresnel / max(4.0 * n_dot_v * n_dot_l, 1e-4);
+    let diffuse = (1.0 - fresnel) * diffuse_color / PI;
+    return (diffuse + specular) * radiance * n_dot_l;
+}
+
+// Texel in the cluster texture holding the light list of the cluster a fragment falls in
+fn light_cluster(frag_coord: vec2<f32>, world_position: vec3<f32>) -> vec2<i32> {
+    let grid = lighting.cluster_grid;
+    let tile = min(vec2<u32>(frag_coord / lighting.tile_size), grid.xy - 1u);
+    let depth = max(-(lighting.view * vec4<f32>(world_position, 1.0)).z, lighting.near);
+    let slice = min(u32(log(depth / lighting.near) * lighting.slice_scale), grid.z - 1u);
+    return vec2<i32>(i32(tile.y * grid.x + tile.x), i32(slice));
+}
+
+fn light_index(index: u32) -> i32 {
+    let width = lighting.cluster_grid.w;
+    let texel = vec2<i32>(i32(index % width), i32(index / width));
+    return i32(textureLoad(light_index_texture, texel, 0).r);
+}
+
+fn shadow_texel_size(slot: u32) -> f32 {
+    return lighting.shadow_texel_sizes[slot / 4u][slot % 4u];
+}
+
+// Fraction of a point lit according to one shadow map slot, filtered over 3x3 texels
+fn shadow_factor(slot: u32, position: vec3<f32>) -> f32 {
+    let clip = lighting.shadow_views[slot] * vec4<f32>(position, 1.0);
+    let ndc = clip.xyz / clip.w;
+    // Nothing outside the map is known to be shadowed
+    if clip.w <= 0.0 || any(abs(ndc.xy) > vec2<f32>(1.0)) || ndc.z > 1.0 {
+        return 1.0;
+    }
+    let tile_scale = 1.0 / f32(SHADOW_ATLAS_TILES);
+    let tile = vec2<f32>(f32(slot % SHADOW_ATLAS_TILES), f32(slot / SHADOW_ATLAS_TILES)) * tile_scale;
+    let uv = tile + (ndc.xy * vec2<f32>(0.5, -0.5) + 0.5) * tile_scale;
+    // Taps stay half a texel inside the tile so filtering never reads a neighbouring map
+    let texel = lighting.shadow_texel;
+    let min_uv = tile + 0.5 * texel;
+    let max_uv = tile + tile_scale - 0.5 * texel;
+    var lit = 0.0;
+    for (var y = -1; y <= 1; y += 1) {
+        for (var x = -1; x <= 1; x += 1) {
+            let tap = clamp(uv + vec2<f32>(f32(x), f32(y)) * texel, min_uv, max_uv);
+            lit += textureSampleCompareLevel(shadow_atlas, shadow_sampler, tap, ndc.z);
+        }
+    }
+    return lit / 9.0;
+}
+
+// Biases move the point towards the light and off the surface, by texels of the map sampled
+fn directional_shadow(light: DirectionalLight, position: vec3<f32>, geometric_normal: vec3<f32>) -> f32 {
+    let depth = -(lighting.view * vec4<f32>(position, 1.0)).z;
+    for (var cascade = 0u; cascade < light.shadow_slots.y; cascade += 1u) {
+        if depth < light.cascade_splits[cascade] {
+            let slot = light.shadow_slots.x + cascade;
+            let offset = geometric_normal * light.shadow_bias.y - light.direction.xyz * light.shadow_bias.x;
+            return shadow_factor(slot, position + offset * shadow_texel_size(slot));
+        }
+    }
+    return 1.0;
+}
+
+fn shade_point_light(
+    index: i32,
+    position: vec3<f32>,
+    geometric_normal: vec3<f32>,
+    normal: vec3<f32>,
+    view: vec3<f32>,
+    diffuse_color: vec3<f32>,
+    f0: vec3<f32>,
+    roughness: f32,
+) -> vec3<f32> {
+    let position_range = textureLoad(light_texture, vec2<i32>(0, index), 0);
+    let direction_scale = textureLoad(light_texture, vec2<i32>(1, index), 0);
+    let radiance_offset = textureLoad(light_texture, vec2<i32>(2, index), 0);
+    let shadow = textureLoad(light_texture, vec2<i32>(3, index), 0);
+
+    let to_light = position_range.xyz - position;
+    let distance_squared = max(dot(to_light, to_light), 1e-4);
+    let light = to_light * inverseSqrt(distance_squared);
+    // Inverse square falloff, windowed to reach zero at the light's range
+    let range_ratio = distance_squared / (position_range.w * position_range.w);
+    let window = clamp(1.0 - range_ratio * range_ratio, 0.0, 1.0);
+    let cone = clamp(dot(direction_scale.xyz, -light) * direction_scale.w + radiance_offset.w, 0.0, 1.0);
+    var attenuation = window * window * cone * cone / distance_squared;
+    // Spot shadow texels grow with the distance from the light
+    if attenuation > 0.0 && shadow.x >= 0.0 {
+        let slot = u32(shadow.x);
+        let texel_size = shadow_texel_size(slot) * sqrt(distance_squared);
+        let offset = geometric_normal * shadow.z + light * shadow.y;
+        attenuation *= shadow_factor(slot, position + offset * texel_size);
+    }
+    return shade_light(normal, view, light, radiance_offset.rgb * attenuation, diffuse_color, f0, roughness);
+}
+
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let higher = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    let lower = color * 12.92;
+    return select(higher, lower, color <= vec3<f32>(0.0031308));
+}
+
+// Shading happens in linear space, non-sRGB targets need the transfer function applied here
+fn output_color(color: vec4<f32>) -> vec4<f32> {
+    let clamped = clamp(color, vec4<f32>(0.0), vec4<f32>(1.0));
+    if ENCODE_SRGB {
+        return vec4<f32>(linear_to_srgb(clamped.rgb), clamped.a);
+    }
+    return clamped;
+}
+
+@fragment
+fn fragment_main(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4<f32> {
+    // Textures and derivatives are only defined in uniform control flow, so they come first
+    let base_color_sample = textureSample(base_color_texture, base_color_sampler, material_uv(in, material.uv_sets.x));
+    let metallic_roughness_sample = textureSample(metallic_roughness_texture, metallic_roughness_sampler, material_uv(in, material.uv_sets.y));
+    let normal_uv = material_uv(in, material.uv_sets.z);
+    let normal_sample = textureSample(normal_texture, normal_sampler, normal_uv).xyz;
+    let occlusion_sample = textureSample(occlusion_texture, occlusion_sampler, material_uv(in, material.uv_sets.w));
+    let emissive_sample = textureSample(emissive_texture, emissive_sampler, material_uv(in, material.emissive_uv_set));
+    let position_dx = dpdx(in.world_position);
+    let position_dy = dpdy(in.world_position);
+    let uv_dx = dpdx(normal_uv);
+    let uv_dy = dpdy(normal_uv);
+
+    var base_color = material.base_color_factor * in.color * base_color_sample;
+    if ALPHA_MODE == ALPHA_MODE_MASK && base_color.a < material.alpha_cutoff {
+        discard;
+    }
+    if ALPHA_MODE != ALPHA_MODE_BLEND {
+        base_color.a = 1.0;
+    }
+    if !LIT {
+        return output_color(base_color);
+    }
+
+    let view = normalize(ubo.camera_position.xyz - in.world_position);
+
+    // Without normals every surface faces the viewer. Shadow biases use the normal before
+    // normal mapping, which follows the geometry the shadow maps were rendered from.
+    var normal = view;
+    var geometric_normal = view;
+    if HAS_NORMALS {
+        normal = normalize(in.normal);
+        var tangent_frame = cotangent_frame(normal, position_dx, position_dy, uv_dx, uv_dy);
+        if HAS_TANGENTS {
+            let tangent = normalize(in.tangent.xyz - normal * dot(normal, in.tangent.xyz));
+            tangent_frame = mat3x3<f32>(tangent, cross(normal, tangent) * in.tangent.w, normal);
+        }
+        // Back faces of double-sided materials are lit as if they were front faces
+        if !front_facing {
+            tangent_frame = -tangent_frame;
+            normal = -normal;
+        }
+        geometric_normal = normal;
+        if material.has_normal_texture != 0u {
+            let scale = vec3<f32>(material.normal_scale, material.normal_scale, 1.0);
+            normal = normalize(tangent_frame * ((normal_sample * 2.0 - 1.0) * scale));
+        }
+    }
+
+    let metallic = clamp(material.metallic_factor * metallic_roughness_sample.b, 0.0, 1.0);
+    let roughness = clamp(material.roughness_factor * metallic_roughness_sample.g, 0.04, 1.0);
+    let occlusion = 1.0 + material.occlusion_strength * (occlusion_sample.r - 1.0);
+    let f0 = mix(vec3<f32>(0.04), base_color.rgb, metallic);
+    let diffuse_color = base_color.rgb * (1.0 - metallic);
+
+    var color = AMBIENT_RADIANCE * (diffuse_color + f0) * occlusion;
+    for (var i = 0u; i < lighting.directional_count; i += 1u) {
+        let light = lighting.directional_lights[i];
+        let shadow = directional_shadow(light, in.world_position, geometric_normal);
+        color += shade_light(normal, view, -light.direction.xyz, light.radiance.rgb * shadow, diffuse_color, f0, roughness);
+    }
+    let light_range = textureLoad(cluster_texture, light_cluster(in.position.xy, in.world_position), 0).xy;
+    for (var i = 0u; i < light_range.y; i += 1u) {
+        let index = light_index(light_range.x + i);
+        color += shade_point_light(index, in.world_position, geometric_normal, normal, view, diffuse_color, f0, roughness);
+    }
+    color += material.emissive_factor * emissive_sample.rgb;
+    return output_color(vec4<f32>(color, base_color.a));
+}
+";
@@ -0,0 +1,598 @@
+/// Window and renderer options used when the engine starts.
+///
+/// Values come from the application, then an optional RON config file, then command line
+/// flags, each overriding the last. See `LaunchSettings::apply_args` for the flags.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct LaunchSettings {
+    pub window_title: String,
+    /// Initial inner size in logical pixels. On the web the canvas's CSS size is used
+    /// instead, this only applies while it has no layout yet.
+    pub width: u32,
+    pub height: u32,
+    pub min_size: Option<(u32, u32)>,
+    pub resizable: bool,
+    pub window_mode: WindowMode,
+    /// Id of the canvas element to render into on the web
+    pub canvas_id: String,
+    /// Stretches the canvas over its parent element on the web instead of leaving its size
+    /// to the page's styling
+    pub fit_canvas_to_parent: bool,
+    pub renderer: RendererSettings,
+}
+
+impl Default for LaunchSettings {
+    fn default() -> Self {
+        Self {
+            window_title: String::new(),
+            width: 1280,
+            height: 720,
+            min_size: None,
+            resizable: true,
+            window_mode: WindowMode::default(),
+            canvas_id: "canvas".to_string(),
+            fit_canvas_to_parent: false,
+            renderer: RendererSettings::default(),
+        }
+    }
+}
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum WindowMode {
+    #[default]
+    Windowed,
+    // Covers the current monitor without changing its video mode
+    Borderless,
+    // Takes over the primary monitor at its largest video mode
+    Fullscreen,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct RendererSettings {
+    pub present_mode: PresentMode,
+    /// Samples per pixel for multisample anti-aliasing, 1 disables it
+    pub msaa_samples: u32,
+    pub power_preference: PowerPreference,
+    /// Graphics api to use, `None` picks from the `WGPU_BACKEND` variable or any available
+    pub backend: Option<Backend>,
+}
+
+impl Default for RendererSettings {
+    fn default() -> Self {
+        Self {
+            present_mode: PresentMode::default(),
+            msaa_samples: 1,
+            power_preference: PowerPreference::default(),
+            backend: None,
+        }
+    }
+}
+
+// Mirrors `wgpu::PresentMode`, modes the surface doesn't support fall back to `AutoVsync`
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum PresentMode {
+    #[default]
+    AutoVsync,
+    AutoNoVsync,
+    Fifo,
+    FifoRelaxed,
+    Immediate,
+    Mailbox,
+}
+
+impl From<PresentMode> for wgpu::PresentMode {
+    fn from(present_mode: PresentMode) -> Self {
+        match present_mode {
+            PresentMode::AutoVsync => Self::AutoVsync,
+            PresentMode::AutoNoVsync => Self::AutoNoVsync,
+            PresentMode::Fifo => Self::Fifo,
+            PresentMode::FifoRelaxed => Self::FifoRelaxed,
+            PresentMode::Immediate => Self::Immediate,
+            PresentMode::Mailbox => Self::Mailbox,
+        }
+    }
+}
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum PowerPreference {
+    #[default]
+    None,
+    LowPower,
+    HighPerformance,
+}
+
+impl From<PowerPreference> for wgpu::PowerPreference {
+    fn from(power_preference: PowerPreference) -> Self {
+        match power_preference {
+            PowerPreference::None => Self::None,
+            PowerPreference::LowPower => Self::LowPower,
+            PowerPreference::HighPerformance => Self::HighPerformance,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum Backend {
+    Vulkan,
+    Metal,
+    Dx12,
+    Gl,
+    BrowserWebGpu,
+}
+
+impl From<Backend> for wgpu::Backends {
+    fn from(backend: Backend) -> Self {
+        match backend {
+            Backend::Vulkan => Self::VULKAN,
+            Backend::Metal => Self::METAL,
+            Backend::Dx12 => Self::DX12,
+            Backend::Gl => Self::GL,
+            Backend::BrowserWebGpu => Self::BROWSER_WEBGPU,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SettingsError {
+    Persistence(crate::persistence::PersistenceError),
+    MissingValue { flag: String },
+    InvalidValue { flag: String, value: String },
+}
+
+impl std::fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Persistence(error) => write!(f, "Failed to load settings file: {error}"),
+            Self::MissingValue { flag } => write!(f, "Missing value for {flag}"),
+            Self::InvalidValue { flag, value } => write!(f, "Invalid value for {flag}: {value}"),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl From<crate::persistence::PersistenceError> for SettingsError {
+    fn from(error: crate::persistence::PersistenceError) -> Self {
+        Self::Persistence(error)
+    }
+}
+
+impl LaunchSettings {
+    /// Reads settings from a RON file, fields missing from the file keep their defaults
+    pub fn load(
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<Self, crate::persistence::PersistenceError> {
+        let mut settings = Self::default();
+        settings.apply_file(path)?;
+        Ok(settings)
+    }
+
+    /// Overrides settings from a RON file, fields missing from the file keep their values
+    pub fn apply_file(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), crate::persistence::PersistenceError> {
+        self.apply_ron_str(&std::fs::read_to_string(path)?)
+    }
+
+    fn apply_ron_str(&mut self, ron: &str) -> Result<(), crate::persistence::PersistenceError> {
+        ron::from_str::<SettingsFile>(ron)?.apply(self);
+        Ok(())
+    }
+
+    pub fn save(
+        &self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), crate::persistence::PersistenceError> {
+        let config = ron::ser::PrettyConfig::default().indentor("    ".to_string());
+        std::fs::write(path, ron::ser::to_string_pretty(self, config)?)?;
+        Ok(())
+    }
+
+    /// Applies the process's command line arguments, logging anything that fails to parse
+    pub fn with_command_line(mut self) -> Self {
+        if let Err(error) = self.apply_args(std::env::args().skip(1)) {
+            log::error!("{error}");
+        }
+        self
+    }
+
+    /// Overrides settings from command line style arguments.
+    ///
+    /// `--config <path>` is applied first wherever it appears, then these flags are applied
+    /// in order. Values may also be passed as `--flag=value`. Unknown arguments are ignored
+    /// so applications can mix in their own.
+    ///
+    /// `--title <text>`, `--width <px>`, `--height <px>`, `--min-size <w>x<h>`,
+    /// `--resizable`, `--fixed-size`, `--windowed`, `--borderless`, `--fullscreen`,
+    /// `--vsync`, `--no-vsync`, `--present-mode <mode>`, `--msaa <samples>`,
+    /// `--power <none|low|high>`, `--backend <vulkan|metal|dx12|gl|webgpu>`, `--canvas <id>`,
+    /// `--fit-canvas`
+    pub fn apply_args(
+        &mut self,
+        args: impl IntoIterator<Item = String>,
+    ) -> Result<(), SettingsError> {
+        let mut flags = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                continue;
+            }
+            let (flag, value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+                None if Self::takes_value(&arg) => {
+                    let value = args.next();
+                    (arg, value)
+                }
+                None => (arg, None),
+            };
+            flags.push((flag, value));
+        }
+
+        if let Some((_, config)) = flags.iter().find(|(flag, _)| flag == "--config") {
+            let path = config.as_ref().ok_or_else(|| SettingsError::MissingValue {
+                flag: "--config".to_string(),
+            })?;
+            self.apply_file(path)?;
+        }
+
+        for (flag, value) in flags {
+            self.apply_flag(&flag, value)?;
+        }
+        Ok(())
+    }
+
+    fn takes_value(flag: &str) -> bool {
+        matches!(
+            flag,
+            "--config"
+                | "--title"
+                | "--width"
+                | "--height"
+                | "--min-size"
+                | "--present-mode"
+                | "--msaa"
+                | "--power"
+                | "--backend"
+                | "--canvas"
+        )
+    }
+
+    fn apply_flag(&mut self, flag: &str, value: Option<String>) -> Result<(), SettingsError> {
+        let invalid = |value: &str| SettingsError::InvalidValue {
+            flag: flag.to_string(),
+            value: value.to_string(),
+        };
+        let value = match value {
+            Some(value) => value,
+            None if Self::takes_value(flag) => {
+                return Err(SettingsError::MissingValue {
+                    flag: flag.to_string(),
+                })
+            }
+            None => String::new(),
+        };
+
+        match flag {
+            "--title" => self.window_title = value,
+            "--width" => self.width = value.parse().map_err(|_| invalid(&value))?,
+            "--height" => self.height = value.parse().map_err(|_| invalid(&value))?,
+            "--min-size" => {
+                let (width, height) = value.split_once('x').ok_or_else(|| invalid(&value))?;
+                self.min_size = Some((
+                    width.parse().map_err(|_| invalid(&value))?,
+                    height.parse().map_err(|_| invalid(&value))?,
+                ));
+            }
+            "--resizable" => self.resizable = true,
+            "--fixed-size" => self.resizable = false,
+            "--windowed" => self.window_mode = WindowMode::Windowed,
+            "--borderless" => self.window_mode = WindowMode::Borderless,
+            "--fullscreen" => self.window_mode = WindowMode::Fullscreen,
+            "--vsync" => self.renderer.present_mode = PresentMode::AutoVsync,
+            "--no-vsync" => self.renderer.present_mode = PresentMode::AutoNoVsync,
+            "--present-mode" => {
+                self.renderer.present_mode = match value.to_lowercase().as_str() {
+                    "auto-vsync" | "vsync" => PresentMode::AutoVsync,
+                    "auto-no-vsync" | "no-vsync" => PresentMode::AutoNoVsync,
+                    "fifo" => PresentMode::Fifo,
+                    "fifo-relaxed" => PresentMode::FifoRelaxed,
+                    "immediate" => PresentMode::Immediate,
+                    "mailbox" => PresentMode::Mailbox,
+                    _ => return Err(invalid(&value)),
+                }
+            }
+            "--msaa" => {
+                self.renderer.msaa_samples = value
+                    .parse()
+                    .ok()
+                    .filter(|samples: &u32| samples.is_power_of_two())
+                    .ok_or_else(|| invalid(&value))?
+            }
+            "--power" => {
+                self.renderer.power_preference = match value.to_lowercase().as_str() {
+                    "none" => PowerPreference::None,
+                    "low" | "low-power" => PowerPreference::LowPower,
+                    "high" | "high-performance" => PowerPreference::HighPerformance,
+                    _ => return Err(invalid(&value)),
+                }
+            }
+            "--backend" => {
+                self.renderer.backend = Some(match value.to_lowercase().as_str() {
+                    "vulkan" => Backend::Vulkan,
+                    "metal" => Backend::Metal,
+                    "dx12" => Backend::Dx12,
+                    "gl" => Backend::Gl,
+                    "webgpu" => Backend::BrowserWebGpu,
+                    _ => return Err(invalid(&value)),
+                })
+            }
+            "--canvas" => self.canvas_id = value,
+            "--fit-canvas" => self.fit_canvas_to_parent = true,
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+// A config file's view of `LaunchSettings`, where every field it leaves out is `None` so it
+// can be applied over settings the application already chose. Fields are read as their plain
+// values, the file is written for `LaunchSettings` and has no `Some(..)` around them.
+#[derive(Default, serde::Deserialize)]
+#[serde(default, rename = "LaunchSettings")]
+struct SettingsFile {
+    #[serde(deserialize_with = "present")]
+    window_title: Option<String>,
+    #[serde(deserialize_with = "present")]
+    width: Option<u32>,
+    #[serde(deserialize_with = "present")]
+    height: Option<u32>,
+    #[serde(deserialize_with = "present")]
+    min_size: Option<Option<(u32, u32)>>,
+    #[serde(deserialize_with = "present")]
+    resizable: Option<bool>,
+    #[serde(deserialize_with = "present")]
+    window_mode: Option<WindowMode>,
+    #[serde(deserialize_with = "present")]
+    canvas_id: Option<String>,
+    #[serde(deserialize_with = "present")]
+    fit_canvas_to_parent: Option<bool>,
+    renderer: RendererSettingsFile,
+}
+
+#[derive(Default, serde::Deserialize)]
+#[serde(default, rename = "RendererSettings")]
+struct RendererSettingsFile {
+    #[serde(deserialize_with = "present")]
+    present_mode: Option<PresentMode>,
+    #[serde(deserialize_with = "present")]
+    msaa_samples: Option<u32>,
+    #[serde(deserialize_with = "present")]
+    power_preference: Option<PowerPreference>,
+    #[serde(deserialize_with = "present")]
+    backend: Option<Option<Backend>>,
+}
+
+// Any field the file has becomes `Some`, including a `None` that clears an optional setting
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: serde::Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+fn set<T>(field: &mut T, value: Option<T>) {
+    if let Some(value) = value {
+        *field = value;
+    }
+}
+
+impl SettingsFile {
+    fn apply(self, settings: &mut LaunchSettings) {
+        set(&mut settings.window_title, self.window_title);
+        set(&mut settings.width, self.width);
+        set(&mut settings.height, self.height);
+        set(&mut settings.min_size, self.min_size);
+        set(&mut settings.resizable, self.resizable);
+        set(&mut settings.window_mode, self.window_mode);
+        set(&mut settings.canvas_id, self.canvas_id);
+        set(
+            &mut settings.fit_canvas_to_parent,
+            self.fit_canvas_to_parent,
+        );
+
+        let renderer = self.renderer;
+        set(&mut settings.renderer.present_mode, renderer.present_mode);
+        set(&mut settings.renderer.msaa_samples, renderer.msaa_samples);
+        set(
+            &mut settings.renderer.power_preference,
+            renderer.power_preference,
+        );
+        set(&mut settings.renderer.backend, renderer.backend);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn apply(arguments: &[&str]) -> Result<LaunchSettings, SettingsError> {
+        let mut settings = LaunchSettings::default();
+        settings.apply_args(args(arguments))?;
+        Ok(settings)
+    }
+
+    // Differs from the defaults in every field
+    fn custom_settings() -> LaunchSettings {
+        LaunchSettings {
+            window_title: "Custom".to_string(),
+            width: 640,
+            height: 480,
+            min_size: Some((320, 240)),
+            resizable: false,
+            window_mode: WindowMode::Borderless,
+            canvas_id: "game".to_string(),
+            fit_canvas_to_parent: true,
+            renderer: RendererSettings {
+                present_mode: PresentMode::Mailbox,
+                msaa_samples: 4,
+                power_preference: PowerPreference::HighPerformance,
+                backend: Some(Backend::Vulkan),
+            },
+        }
+    }
+
+    #[test]
+    fn flags_set_their_fields() {
+        let settings = apply(&[
+            "--title",
+            "Game",
+            "--width",
+            "800",
+            "--height=600",
+            "--min-size",
+            "400x300",
+            "--fixed-size",
+            "--fullscreen",
+            "--present-mode",
+            "fifo-relaxed",
+            "--msaa",
+            "8",
+            "--power",
+            "low",
+            "--backend=gl",
+            "--canvas",
+            "view",
+            "--fit-canvas",
+        ])
+        .unwrap();
+        assert_eq!(settings.window_title, "Game");
+        assert_eq!((settings.width, settings.height), (800, 600));
+        assert_eq!(settings.min_size, Some((400, 300)));
+        assert!(!settings.resizable);
+        assert_eq!(settings.window_mode, WindowMode::Fullscreen);
+        assert_eq!(settings.canvas_id, "view");
+        assert!(settings.fit_canvas_to_parent);
+        assert_eq!(
+            settings.renderer,
+            RendererSettings {
+                present_mode: PresentMode::FifoRelaxed,
+                msaa_samples: 8,
+                power_preference: PowerPreference::LowPower,
+                backend: Some(Backend::Gl),
+            }
+        );
+    }
+
+    #[test]
+    fn later_flags_win() {
+        let settings = apply(&["--no-vsync", "--borderless", "--vsync", "--windowed"]).unwrap();
+        assert_eq!(settings.renderer.present_mode, PresentMode::AutoVsync);
+        assert_eq!(settings.window_mode, WindowMode::Windowed);
+    }
+
+    #[test]
+    fn unknown_arguments_are_ignored() {
+        let settings = apply(&["game.ron", "--seed", "--width", "1024", "-v"]).unwrap();
+        assert_eq!(settings.width, 1024);
+    }
+
+    #[test]
+    fn flags_without_values_are_rejected() {
+        assert!(matches!(
+            apply(&["--width"]),
+            Err(SettingsError::MissingValue { flag }) if flag == "--width"
+        ));
+        assert!(matches!(
+            apply(&["--config"]),
+            Err(SettingsError::MissingValue { flag }) if flag == "--config"
+        ));
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        for arguments in [
+            ["--width", "wide"],
+            ["--min-size", "400"],
+            ["--min-size", "400xtall"],
+            ["--msaa", "3"],
+            ["--present-mode", "sometimes"],
+            ["--power", "max"],
+            ["--backend", "glide"],
+        ] {
+            assert!(
+                matches!(
+                    apply(&arguments),
+                    Err(SettingsError::InvalidValue { flag, value })
+                        if flag == arguments[0] && value == arguments[1]
+                ),
+                "accepted {arguments:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn config_files_keep_fields_they_leave_out() {
+        let mut settings = custom_settings();
+        settings
+            .apply_ron_str("(width: 1920, min_size: None, renderer: (msaa_samples: 2))")
+            .unwrap();
+        assert_eq!(settings.width, 1920);
+        assert_eq!(settings.min_size, None);
+        assert_eq!(settings.renderer.msaa_samples, 2);
+        // Everything else is still what the application chose
+        assert_eq!(settings.window_title, "Custom");
+        assert_eq!(settings.height, 480);
+        assert_eq!(settings.window_mode, WindowMode::Borderless);
+        assert_eq!(settings.renderer.present_mode, PresentMode::Mailbox);
+        assert_eq!(settings.renderer.backend, Some(Backend::Vulkan));
+    }
+
+    #[test]
+    fn saved_settings_apply_every_field() {
+        let path = std::env::temp_dir().join(format!("settings-{}.ron", uuid::Uuid::new_v4()));
+        custom_settings().save(&path).unwrap();
+        let loaded = LaunchSettings::load(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            format!("{:?}", loaded.unwrap()),
+            format!("{:?}", custom_settings())
+        );
+    }
+
+    #[test]
+    fn command_line_overrides_config_file_overrides_application() {
+        let path = std::env::temp_dir().join(format!("settings-{}.ron", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "(width: 1920, height: 1080, canvas_id: \"file\")").unwrap();
+        let mut settings = custom_settings();
+        // The file is applied first even though it comes after the flags
+        let result = settings.apply_args(args(&[
+            "--height",
+            "900",
+            "--config",
+            path.to_str().unwrap(),
+        ]));
+        std::fs::remove_file(&path).unwrap();
+        result.unwrap();
+
+        assert_eq!(settings.window_title, "Custom");
+        assert_eq!(settings.width, 1920);
+        assert_eq!(settings.height, 900);
+        assert_eq!(settings.canvas_id, "file");
+    }
+
+    #[test]
+    fn missing_config_files_are_reported() {
+        let path = std::env::temp_dir().join(format!("settings-{}.ron", uuid::Uuid::new_v4()));
+        assert!(matches!(
+            apply(&["--config", path.to_str().unwrap()]),
+            Err(SettingsError::Persistence(_))
+        ));
+    }
+}
@@ -0,0 +1,413 @@
+//! Plans the shadow maps of a frame: which lights get them, where they go in the shadow
+//! atlas and the light space projections they are rendered with.
+//!
+//! Directional lights get cascades fit to the camera's view frustum, spot lights a single
+//! perspective map covering their outer cone. Every map is a square tile of one shared
+//! depth atlas, handed out to directional lights first, then spot lights in world order.
+
+/// Tiles along each side of the shadow atlas
+pub const SHADOW_ATLAS_TILES: u32 = 4;
+pub const SHADOW_SLOTS: usize = (SHADOW_ATLAS_TILES * SHADOW_ATLAS_TILES) as usize;
+pub const MAX_CASCADES: usize = 4;
+// Casters this far past a cascade towards the light still shadow it
+const CASCADE_CASTER_DEPTH: f32 = 100.0;
+const SPOT_SHADOW_NEAR: f32 = 0.05;
+// Wider cones would need more than one map to keep texels a useful size
+const MAX_SPOT_SHADOW_FOV: f32 = 170.0 * std::f32::consts::PI / 180.0;
+
+/// A light space projection rendered into one atlas tile
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ShadowView {
+    pub view_projection: nalgebra_glm::Mat4,
+    /// World size of a shadow map texel. For spot lights this is per unit of distance from
+    /// the light, as their texels grow with it.
+    pub texel_size: f32,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DirectionalShadow {
+    /// Slot of the first cascade, the others follow it
+    pub first_slot: u32,
+    pub cascade_count: u32,
+    /// View depth where each cascade ends
+    pub splits: [f32; MAX_CASCADES],
+    pub settings: crate::world::Shadow,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SpotShadow {
+    pub slot: u32,
+    pub settings: crate::world::Shadow,
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct ShadowPlan {
+    /// Indexed by slot
+    pub views: Vec<ShadowView>,
+    /// Parallel to the lights' directional lights
+    pub directional: Vec<Option<DirectionalShadow>>,
+    /// Parallel to the lights' omni and spot lights
+    pub point: Vec<Option<SpotShadow>>,
+    /// Whether any light wanting shadows was left without a slot
+    pub overflowed: bool,
+}
+
+/// Assigns atlas slots to every shadow casting light and fits their projections.
+///
+/// `tile_resolution` is the size of one atlas tile in texels, cascades are snapped to it so
+/// their shadows don't shimmer as the camera moves.
+pub fn plan_shadows(
+    lights: &crate::lighting::Lights,
+    camera_view: &nalgebra_glm::Mat4,
+    camera_projection: &nalgebra_glm::Mat4,
+    tile_resolution: u32,
+) -> ShadowPlan {
+    let mut plan = ShadowPlan::default();
+    let frustum = Frustum::new(camera_view, camera_projection);
+
+    for light in &lights.directional {
+        let Some(settings) = light.shadow else {
+            plan.directional.push(None);
+            continue;
+        };
+        let cascade_count = (light.cascades.count as usize).clamp(1, MAX_CASCADES);
+        if plan.views.len() + cascade_count > SHADOW_SLOTS {
+            plan.overflowed = true;
+            plan.directional.push(None);
+            continue;
+        }
+        let near = frustum.near_depth;
+        let far = frustum.far_depth.min(light.cascades.max_distance).max(near);
+        let split_depths = cascade_splits(near, far, cascade_count, light.cascades.split_blend);
+        let mut splits = [f32::MAX; MAX_CASCADES];
+        splits[..cascade_count].copy_from_slice(&split_depths);
+
+        let first_slot = plan.views.len() as u32;
+        let mut start = near;
+        for end in split_depths {
+            let corners = frustum.corners(start, end);
+            plan.views
+                .push(fit_cascade(&corners, &light.direction, tile_resolution));
+            start = end;
+        }
+        plan.directional.push(Some(DirectionalShadow {
+            first_slot,
+            cascade_count: cascade_count as u32,
+            splits,
+            settings,
+        }));
+    }
+
+    for light in &lights.point {
+        let Some(settings) = light.shadow else {
+            plan.point.push(None);
+            continue;
+        };
+        if plan.views.len() >= SHADOW_SLOTS {
+            plan.overflowed = true;
+            plan.point.push(None);
+            continue;
+        }
+        plan.point.push(Some(SpotShadow {
+            slot: plan.views.len() as u32,
+            settings,
+        }));
+        plan.views.push(spot_view(light, tile_resolution));
+    }
+    plan
+}
+
+/// View depths where each of `count` cascades between `near` and `far` ends.
+///
+/// Blends between even spacing at a `blend` of 0 and logarithmic spacing at 1, which keeps
+/// the ratio of texel size to view depth the same in every cascade.
+pub fn cascade_splits(near: f32, far: f32, count: usize, blend: f32) -> Vec<f32> {
+    // The logarithmic scheme needs a near plane in front of the camera
+    let log_near = near.max(0.01);
+    let blend = blend.clamp(0.0, 1.0);
+    (1..=count)
+        .map(|cascade| {
+            let t = cascade as f32 / count as f32;
+            let uniform = near + (far - near) * t;
+            let logarithmic = log_near * (far.max(log_near) / log_near).powf(t);
+            uniform + (logarithmic - uniform) * blend
+        })
+        .collect()
+}
+
+// An orthographic projection along the light covering a bounding sphere of the corners, so
+// its size doesn't change as the camera turns
+fn fit_cascade(
+    corners: &[nalgebra_glm::Vec3; 8],
+    direction: &nalgebra_glm::Vec3,
+    tile_resolution: u32,
+) -> ShadowView {
+    let center = corners.iter().sum::<nalgebra_glm::Vec3>() / corners.len() as f32;
+    let radius = corners
+        .iter()
+        .map(|corner| nalgebra_glm::distance(corner, &center))
+        .fold(0.0, f32::max);
+    // Rounding up keeps float noise from changing the texel size every frame
+    let radius = ((radius * 16.0).ceil() / 16.0).max(1.0 / 16.0);
+    let texel_size = 2.0 * radius / tile_resolution as f32;
+
+    let light_view = nalgebra_glm::look_at_rh(
+        &nalgebra_glm::Vec3::zeros(),
+        direction,
+        &up_vector(direction),
+    );
+    let center = (light_view * nalgebra_glm::vec4(center.x, center.y, center.z, 1.0)).xyz();
+    // Moving the projection in whole texels keeps each texel over the same part of the world
+    let snap = |value: f32| (value / texel_size).floor() * texel_size;
+    let (x, y) = (snap(center.x), snap(center.y));
+    let projection = nalgebra_glm::ortho_rh_zo(
+        x - radius,
+        x + radius,
+        y - radius,
+        y + radius,
+        -center.z - radius - CASCADE_CASTER_DEPTH,
+        -center.z + radius,
+    );
+    ShadowView {
+        view_projection: projection * light_view,
+        texel_size,
+    }
+}
+
+fn spot_view(light: &crate::lighting::PointLight, tile_resolution: u32) -> ShadowView {
+    let fov = (2.0 * light.cone_angle).min(MAX_SPOT_SHADOW_FOV);
+    let near = SPOT_SHADOW_NEAR.min(light.range * 0.5);
+    let view = nalgebra_glm::look_at_rh(
+        &light.position,
+        &(light.position + light.direction),
+        &up_vector(&light.direction),
+    );
+    let projection = nalgebra_glm::perspective_rh_zo(1.0, fov, near, light.range.max(near * 2.0));
+    ShadowView {
+        view_projection: projection * view,
+        texel_size: 2.0 * (fov / 2.0).tan() / tile_resolution as f32,
+    }
+}
+
+// Any vector not parallel to the direction works, Y unless the light points straight up or down
+fn up_vector(direction: &nalgebra_glm::Vec3) -> nalgebra_glm::Vec3 {
+    match direction.y.abs() > 0.99 {
+        true => nalgebra_glm::Vec3::z(),
+        false => nalgebra_glm::Vec3::y(),
+    }
+}
+
+// World space rays through the corners of the camera's view
+struct Frustum {
+    // Two points along each ray
+    rays: [(nalgebra_glm::Vec3, nalgebra_glm::Vec3); 4],
+    camera_to_world: nalgebra_glm::Mat4,
+    near_depth: f32,
+    // Infinite for projections without a far plane
+    far_depth: f32,
+}
+
+impl Frustum {
+    fn new(view: &nalgebra_glm::Mat4, projection: &nalgebra_glm::Mat4) -> Self {
+        let inverse = projection
+            .try_inverse()
+            .unwrap_or_else(nalgebra_glm::Mat4::identity);
+        let unproject = |x: f32, y: f32, z: f32| {
+            let point = inverse * nalgebra_glm::vec4(x, y, z, 1.0);
+            point.xyz() / point.w
+        };
+        let rays = [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)]
+            .map(|(x, y)| (unproject(x, y, 0.0), unproject(x, y, 0.5)));
+        let far = -unproject(0.0, 0.0, 1.0).z;
+        Self {
+            rays,
+            camera_to_world: view
+                .try_inverse()
+                .unwrap_or_else(nalgebra_glm::Mat4::identity),
+            near_depth: -unproject(0.0, 0.0, 0.0).z,
+            far_depth: match far.is_finite() && far > 0.0 {
+                true => far,
+                false => f32::INFINITY,
+            },
+        }
+    }
+
+    // World space corners of the part of the view between two depths
+    fn corners(&self, start: f32, end: f32) -> [nalgebra_glm::Vec3; 8] {
+        let mut corners = [nalgebra_glm::Vec3::zeros(); 8];
+        for (index, depth) in [start, end].into_iter().enumerate() {
+            for (ray, (a, b)) in self.rays.iter().enumerate() {
+                let point = a + (b - a) * ((-depth - a.z) / (b.z - a.z));
+                corners[index * 4 + ray] = (self.camera_to_world
+                    * nalgebra_glm::vec4(point.x, point.y, point.z, 1.0))
+                .xyz();
+            }
+        }
+        corners
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lighting::{DirectionalLight, Lights, PointLight};
+
+    const TILE_RESOLUTION: u32 = 1024;
+
+    fn sun(cascade_count: u32, shadow: bool) -> DirectionalLight {
+        DirectionalLight {
+            direction: nalgebra_glm::normalize(&nalgebra_glm::vec3(-1.0, -2.0, -1.0)),
+            radiance: nalgebra_glm::vec3(1.0, 1.0, 1.0),
+            shadow: shadow.then(crate::world::Shadow::default),
+            cascades: crate::world::ShadowCascades {
+                count: cascade_count,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn spot(shadow: bool) -> PointLight {
+        PointLight {
+            position: nalgebra_glm::vec3(0.0, 5.0, 0.0),
+            direction: -nalgebra_glm::Vec3::y(),
+            radiance: nalgebra_glm::vec3(1.0, 1.0, 1.0),
+            range: 20.0,
+            cone_scale: 4.0,
+            cone_offset: -3.0,
+            cone_angle: 0.6,
+            shadow: shadow.then(crate::world::Shadow::default),
+        }
+    }
+
+    fn camera_at(eye: nalgebra_glm::Vec3, far: f32) -> (nalgebra_glm::Mat4, nalgebra_glm::Mat4) {
+        let view = nalgebra_glm::look_at_rh(
+            &eye,
+            &(eye + nalgebra_glm::vec3(0.3, -0.2, -1.0)),
+            &nalgebra_glm::Vec3::y(),
+        );
+        let projection = nalgebra_glm::perspective_rh_zo(16.0 / 9.0, 1.0, 0.1, far);
+        (view, projection)
+    }
+
+    fn plan(lights: &Lights, far: f32) -> ShadowPlan {
+        let (view, projection) = camera_at(nalgebra_glm::vec3(0.0, 2.0, 5.0), far);
+        plan_shadows(lights, &view, &projection, TILE_RESOLUTION)
+    }
+
+    #[test]
+    fn directional_lights_take_slots_before_spot_lights() {
+        let lights = Lights {
+            directional: vec![sun(2, true), sun(4, false), sun(1, true)],
+            point: vec![spot(true), spot(false), spot(true)],
+        };
+        let plan = plan(&lights, 100.0);
+        let slots = plan
+            .directional
+            .iter()
+            .map(|shadow| shadow.map(|shadow| (shadow.first_slot, shadow.cascade_count)))
+            .collect::<Vec<_>>();
+        assert_eq!(slots, vec![Some((0, 2)), None, Some((2, 1))]);
+        let slots = plan
+            .point
+            .iter()
+            .map(|shadow| shadow.map(|shadow| shadow.slot))
+            .collect::<Vec<_>>();
+        assert_eq!(slots, vec![Some(3), None, Some(4)]);
+        assert_eq!(plan.views.len(), 5);
+        assert!(!plan.overflowed);
+    }
+
+    #[test]
+    fn lights_past_the_last_slot_overflow() {
+        let mut lights = Lights {
+            directional: vec![sun(4, true); 3],
+            point: vec![spot(true); 4],
+        };
+        let full = plan(&lights, 100.0);
+        assert_eq!(full.views.len(), SHADOW_SLOTS);
+        assert!(!full.overflowed);
+
+        lights.point.push(spot(true));
+        let overflowed = plan(&lights, 100.0);
+        assert_eq!(overflowed.views.len(), SHADOW_SLOTS);
+        assert_eq!(overflowed.point[4], None);
+        assert!(overflowed.overflowed);
+
+        // A directional light only gets its cascades if they all fit, later lights may still
+        lights
+            .directional
+            .extend([sun(3, true), sun(2, true), sun(1, true)]);
+        lights.point.clear();
+        let overflowed = plan(&lights, 100.0);
+        let first_slots = overflowed.directional[3..]
+            .iter()
+            .map(|shadow| shadow.map(|shadow| shadow.first_slot))
+            .collect::<Vec<_>>();
+        assert_eq!(first_slots, vec![Some(12), None, Some(15)]);
+        assert_eq!(overflowed.views.len(), SHADOW_SLOTS);
+        assert!(overflowed.overflowed);
+    }
+
+    #[test]
+    fn splits_increase_up_to_the_far_depth() {
+        for blend in [0.0, 1.0] {
+            let splits = cascade_splits(0.1, 50.0, 4, blend);
+            assert_eq!(splits.len(), 4);
+            assert!(splits[0] > 0.1);
+            assert!(
+                splits.windows(2).all(|pair| pair[0] < pair[1]),
+                "{splits:?}"
+            );
+            assert!((splits[3] - 50.0).abs() < 1e-3, "{splits:?}");
+        }
+        let even = cascade_splits(0.0, 40.0, 4, 0.0);
+        assert_eq!(even, vec![10.0, 20.0, 30.0, 40.0]);
+        // Logarithmic splits keep the same ratio between consecutive depths
+        let logarithmic = cascade_splits(1.0, 10000.0, 4, 1.0);
+        for (split, expected) in logarithmic.iter().zip([10.0, 100.0, 1000.0, 10000.0]) {
+            assert!((split / expected - 1.0).abs() < 1e-4, "{logarithmic:?}");
+        }
+    }
+
+    #[test]
+    fn cascades_end_at_the_nearer_of_far_plane_and_max_distance() {
+        let lights = Lights {
+            directional: vec![sun(3, true)],
+            point: Vec::new(),
+        };
+        // The default cascades stop at 50
+        for (far, end) in [(100.0, 50.0), (30.0, 30.0)] {
+            let shadow = plan(&lights, far).directional[0].unwrap();
+            assert!((shadow.splits[2] - end).abs() < 1e-3, "{:?}", shadow.splits);
+            assert_eq!(shadow.splits[3], f32::MAX);
+        }
+    }
+
+    #[test]
+    fn cascades_are_snapped_to_whole_texels() {
+        let lights = Lights {
+            directional: vec![sun(4, true)],
+            point: Vec::new(),
+        };
+        for eye in [
+            nalgebra_glm::vec3(0.0, 2.0, 5.0),
+            nalgebra_glm::vec3(0.37, 2.0, 5.11),
+            nalgebra_glm::vec3(-13.2, 4.5, 0.8),
+        ] {
+            let (view, projection) = camera_at(eye, 100.0);
+            let plan = plan_shadows(&lights, &view, &projection, TILE_RESOLUTION);
+            for cascade in &plan.views {
+                // The light's view is centered on the world origin, which has to land on a
+                // texel corner for texels to stay put as the camera moves
+                let origin = cascade.view_projection * nalgebra_glm::vec4(0.0, 0.0, 0.0, 1.0);
+                for axis in 0..2 {
+                    let texels = origin[axis] * TILE_RESOLUTION as f32 / 2.0;
+                    assert!(
+                        (texels - texels.round()).abs() < 1e-2,
+                        "origin is {texels} texels from the center"
+                    );
+                }
+            }
+        }
+    }
+}
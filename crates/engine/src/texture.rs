@@ -0,0 +1,529 @@
+//! Cpu side texture processing: decoding image files into `world::Image`s and building mip
+//! chains for them. Nothing here touches the gpu, the renderer uploads the results.
+
+/// File formats `decode_image` understands
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+    Ktx2,
+}
+
+impl ImageFormat {
+    const PNG_SIGNATURE: &'static [u8] = &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+    const JPEG_SIGNATURE: &'static [u8] = &[0xff, 0xd8, 0xff];
+    const KTX2_SIGNATURE: &'static [u8] = &[
+        0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, b'\r', b'\n', 0x1a, b'\n',
+    ];
+
+    /// Identifies a format from the signature at the start of a file
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(Self::PNG_SIGNATURE) {
+            Some(Self::Png)
+        } else if bytes.starts_with(Self::JPEG_SIGNATURE) {
+            Some(Self::Jpeg)
+        } else if bytes.starts_with(Self::KTX2_SIGNATURE) {
+            Some(Self::Ktx2)
+        } else {
+            None
+        }
+    }
+
+    pub fn from_extension(path: impl AsRef<std::path::Path>) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => Some(Self::Png),
+            "jpg" | "jpeg" => Some(Self::Jpeg),
+            "ktx2" => Some(Self::Ktx2),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TextureError {
+    Io(std::io::Error),
+    Image(image::ImageError),
+    Ktx2(ktx2::ParseError),
+    Zstd(String),
+    UnknownFormat,
+    UnsupportedKtx2(String),
+}
+
+impl std::fmt::Display for TextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "Failed to read image: {error}"),
+            Self::Image(error) => write!(f, "Failed to decode image: {error}"),
+            Self::Ktx2(error) => write!(f, "Failed to parse KTX2 container: {error}"),
+            Self::Zstd(error) => write!(f, "Failed to decompress KTX2 level: {error}"),
+            Self::UnknownFormat => write!(f, "Unrecognized image format"),
+            Self::UnsupportedKtx2(reason) => write!(f, "Unsupported KTX2 texture: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+impl From<std::io::Error> for TextureError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<image::ImageError> for TextureError {
+    fn from(error: image::ImageError) -> Self {
+        Self::Image(error)
+    }
+}
+
+impl From<ktx2::ParseError> for TextureError {
+    fn from(error: ktx2::ParseError) -> Self {
+        Self::Ktx2(error)
+    }
+}
+
+/// Reads and decodes an image file, see `decode_image`
+pub fn load_image(path: impl AsRef<std::path::Path>) -> Result<crate::world::Image, TextureError> {
+    let bytes = std::fs::read(path.as_ref())?;
+    let format = ImageFormat::detect(&bytes)
+        .or_else(|| ImageFormat::from_extension(path))
+        .ok_or(TextureError::UnknownFormat)?;
+    decode_image_with_format(&bytes, format)
+}
+
+/// Decodes a PNG, JPEG or KTX2 file into RGBA8 pixels, detecting the format from its signature.
+///
+/// Pixels are returned as stored. KTX2 formats record whether they are sRGB or linear in the
+/// image's color space, for PNG and JPEG it is decided by how the texture is used. Only
+/// uncompressed 8 bit KTX2 formats are supported and only the base level of the first layer
+/// and face is kept, mips are regenerated by `generate_mips`.
+pub fn decode_image(bytes: &[u8]) -> Result<crate::world::Image, TextureError> {
+    let format = ImageFormat::detect(bytes).ok_or(TextureError::UnknownFormat)?;
+    decode_image_with_format(bytes, format)
+}
+
+pub fn decode_image_with_format(
+    bytes: &[u8],
+    format: ImageFormat,
+) -> Result<crate::world::Image, TextureError> {
+    match format {
+        ImageFormat::Png | ImageFormat::Jpeg => {
+            let format = match format {
+                ImageFormat::Png => image::ImageFormat::Png,
+                _ => image::ImageFormat::Jpeg,
+            };
+            let decoded = image::load_from_memory_with_format(bytes, format)?.into_rgba8();
+            Ok(crate::world::Image {
+                width: decoded.width(),
+                height: decoded.height(),
+                pixels: decoded.into_raw(),
+                color_space: None,
+            })
+        }
+        ImageFormat::Ktx2 => decode_ktx2(bytes),
+    }
+}
+
+fn decode_ktx2(bytes: &[u8]) -> Result<crate::world::Image, TextureError> {
+    let reader = ktx2::Reader::new(bytes)?;
+    let header = reader.header();
+    let format = header
+        .format
+        .ok_or_else(|| TextureError::UnsupportedKtx2("Basis Universal encoding".to_string()))?;
+    let layout = Ktx2Layout::new(format)
+        .ok_or_else(|| TextureError::UnsupportedKtx2(format!("format {format:?}")))?;
+    if header.pixel_depth > 1 {
+        return Err(TextureError::UnsupportedKtx2("3D texture".to_string()));
+    }
+
+    let level = reader
+        .levels()
+        .next()
+        .ok_or(TextureError::Ktx2(ktx2::ParseError::UnexpectedEnd))?;
+    let level = match header.supercompression_scheme {
+        None => std::borrow::Cow::Borrowed(level),
+        Some(ktx2::SupercompressionScheme::Zstandard) => {
+            let mut decompressed = Vec::new();
+            let mut decoder = ruzstd::StreamingDecoder::new(level)
+                .map_err(|error| TextureError::Zstd(error.to_string()))?;
+            std::io::Read::read_to_end(&mut decoder, &mut decompressed)?;
+            std::borrow::Cow::Owned(decompressed)
+        }
+        Some(scheme) => {
+            return Err(TextureError::UnsupportedKtx2(format!(
+                "supercompression {scheme:?}"
+            )))
+        }
+    };
+
+    // 1D textures have a height of zero
+    let (width, height) = (header.pixel_width, header.pixel_height.max(1));
+    // Sizes come straight from the file, so they may not even be addressable. Decoded texels
+    // take four bytes, never fewer than in the file, so checking those covers both.
+    let texel_count = (width as usize)
+        .checked_mul(height as usize)
+        .filter(|texel_count| texel_count.checked_mul(4).is_some())
+        .ok_or_else(|| TextureError::UnsupportedKtx2(format!("{width}x{height} is too large")))?;
+    let level = level
+        .get(..texel_count * layout.channels)
+        .ok_or(TextureError::Ktx2(ktx2::ParseError::UnexpectedEnd))?;
+    let pixels = level
+        .chunks_exact(layout.channels)
+        .flat_map(|texel| layout.to_rgba(texel))
+        .collect();
+    Ok(crate::world::Image {
+        width,
+        height,
+        pixels,
+        color_space: Some(layout.color_space),
+    })
+}
+
+// How the channels of an uncompressed 8 bit KTX2 format map to RGBA
+struct Ktx2Layout {
+    channels: usize,
+    bgr: bool,
+    color_space: ColorSpace,
+}
+
+impl Ktx2Layout {
+    fn new(format: ktx2::Format) -> Option<Self> {
+        use ColorSpace::{Linear, Srgb};
+        let (channels, bgr, color_space) = match format {
+            ktx2::Format::R8_UNORM => (1, false, Linear),
+            ktx2::Format::R8_SRGB => (1, false, Srgb),
+            ktx2::Format::R8G8_UNORM => (2, false, Linear),
+            ktx2::Format::R8G8_SRGB => (2, false, Srgb),
+            ktx2::Format::R8G8B8_UNORM => (3, false, Linear),
+            ktx2::Format::R8G8B8_SRGB => (3, false, Srgb),
+            ktx2::Format::B8G8R8_UNORM => (3, true, Linear),
+            ktx2::Format::B8G8R8_SRGB => (3, true, Srgb),
+            ktx2::Format::R8G8B8A8_UNORM => (4, false, Linear),
+            ktx2::Format::R8G8B8A8_SRGB => (4, false, Srgb),
+            ktx2::Format::B8G8R8A8_UNORM => (4, true, Linear),
+            ktx2::Format::B8G8R8A8_SRGB => (4, true, Srgb),
+            _ => return None,
+        };
+        Some(Self {
+            channels,
+            bgr,
+            color_space,
+        })
+    }
+
+    fn to_rgba(&self, texel: &[u8]) -> [u8; 4] {
+        let mut rgba = [0, 0, 0, 255];
+        rgba[..self.channels].copy_from_slice(texel);
+        if self.bgr {
+            rgba.swap(0, 2);
+        }
+        // Single channel images are treated as grayscale, matching the glTF importer
+        if self.channels == 1 {
+            rgba[1] = rgba[0];
+            rgba[2] = rgba[0];
+        }
+        rgba
+    }
+}
+
+/// How a texture's color channels are encoded, alpha is always linear
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum ColorSpace {
+    Linear,
+    Srgb,
+}
+
+/// Number of levels in a full mip chain down to 1x1
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    u32::BITS - width.max(height).max(1).leading_zeros()
+}
+
+/// Builds the full mip chain for an image, starting with a copy of the image itself.
+///
+/// Each level halves the previous one with a box filter, rounding odd sizes down. sRGB
+/// channels are averaged in linear space so downsampled levels don't darken.
+pub fn generate_mips(
+    image: &crate::world::Image,
+    color_space: ColorSpace,
+) -> Vec<crate::world::Image> {
+    let mut levels = vec![image.clone()];
+    while let Some(previous) = levels
+        .last()
+        .filter(|level| level.width > 1 || level.height > 1)
+    {
+        levels.push(downsample(previous, color_space));
+    }
+    levels
+}
+
+fn downsample(image: &crate::world::Image, color_space: ColorSpace) -> crate::world::Image {
+    let width = (image.width / 2).max(1);
+    let height = (image.height / 2).max(1);
+    let texel = |x: u32, y: u32| {
+        let x = x.min(image.width - 1);
+        let y = y.min(image.height - 1);
+        let offset = (y as usize * image.width as usize + x as usize) * 4;
+        &image.pixels[offset..offset + 4]
+    };
+
+    let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+    for y in 0..height {
+        for x in 0..width {
+            let footprint = [
+                texel(2 * x, 2 * y),
+                texel(2 * x + 1, 2 * y),
+                texel(2 * x, 2 * y + 1),
+                texel(2 * x + 1, 2 * y + 1),
+            ];
+            for channel in 0..4 {
+                let srgb = color_space == ColorSpace::Srgb && channel < 3;
+                let sum: f32 = footprint
+                    .iter()
+                    .map(|texel| match srgb {
+                        true => srgb_to_linear(texel[channel]),
+                        false => texel[channel] as f32 / 255.0,
+                    })
+                    .sum();
+                let average = sum / footprint.len() as f32;
+                pixels.push(match srgb {
+                    true => linear_to_srgb(average),
+                    false => (average * 255.0).round() as u8,
+                });
+            }
+        }
+    }
+    crate::world::Image {
+        width,
+        height,
+        pixels,
+        color_space: image.color_space,
+    }
+}
+
+pub fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let encoded = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A single level, uncompressed KTX2 file. The data format descriptor is left empty as
+    // the decoder only reads the format from the header.
+    fn ktx2_file(format: u32, width: u32, height: u32, level: &[u8]) -> Vec<u8> {
+        const HEADER_SIZE: u64 = 80;
+        const LEVEL_INDEX_SIZE: u64 = 24;
+        let mut bytes = ImageFormat::KTX2_SIGNATURE.to_vec();
+        for value in [format, 1, width, height, 0, 0, 1, 1, 0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        // Data format descriptor and key/value data offsets and lengths
+        bytes.extend_from_slice(&[0; 16]);
+        // Supercompression global data offset and length
+        bytes.extend_from_slice(&[0; 16]);
+        let level_offset = HEADER_SIZE + LEVEL_INDEX_SIZE;
+        for value in [level_offset, level.len() as u64, level.len() as u64] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.extend_from_slice(level);
+        bytes
+    }
+
+    const R8G8B8A8_UNORM: u32 = 37;
+    const B8G8R8_SRGB: u32 = 36;
+
+    #[test]
+    fn ktx2_texels_are_expanded_to_rgba() {
+        let file = ktx2_file(B8G8R8_SRGB, 2, 1, &[1, 2, 3, 4, 5, 6]);
+        let image = decode_image(&file).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.pixels, [3, 2, 1, 255, 6, 5, 4, 255]);
+    }
+
+    #[test]
+    fn ktx2_color_spaces_are_kept() {
+        let srgb = decode_image(&ktx2_file(B8G8R8_SRGB, 1, 1, &[0; 3])).unwrap();
+        assert_eq!(srgb.color_space, Some(ColorSpace::Srgb));
+        let linear = decode_image(&ktx2_file(R8G8B8A8_UNORM, 1, 1, &[0; 4])).unwrap();
+        assert_eq!(linear.color_space, Some(ColorSpace::Linear));
+        // Mips are encoded like the image they are made from
+        let mips = generate_mips(&srgb, ColorSpace::Srgb);
+        assert_eq!(mips[0].color_space, Some(ColorSpace::Srgb));
+        let mips = generate_mips(&solid_image(2, 2, [0; 4]), ColorSpace::Linear);
+        assert_eq!(mips[1].color_space, None);
+    }
+
+    #[test]
+    fn ktx2_levels_shorter_than_their_size_are_rejected() {
+        let file = ktx2_file(R8G8B8A8_UNORM, 2, 2, &[0; 12]);
+        assert!(matches!(
+            decode_image(&file),
+            Err(TextureError::Ktx2(ktx2::ParseError::UnexpectedEnd))
+        ));
+    }
+
+    #[test]
+    fn ktx2_sizes_that_overflow_are_rejected() {
+        let file = ktx2_file(R8G8B8A8_UNORM, u32::MAX, u32::MAX, &[0; 16]);
+        assert!(matches!(
+            decode_image(&file),
+            Err(TextureError::UnsupportedKtx2(_))
+        ));
+    }
+
+    fn solid_image(width: u32, height: u32, rgba: [u8; 4]) -> crate::world::Image {
+        crate::world::Image {
+            width,
+            height,
+            pixels: rgba.repeat((width * height) as usize),
+            color_space: None,
+        }
+    }
+
+    #[test]
+    fn formats_are_detected_from_their_signature() {
+        let png = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', 0, 0];
+        assert_eq!(ImageFormat::detect(&png), Some(ImageFormat::Png));
+        assert_eq!(
+            ImageFormat::detect(&[0xff, 0xd8, 0xff, 0xe0]),
+            Some(ImageFormat::Jpeg)
+        );
+        assert_eq!(
+            ImageFormat::detect(&ktx2_file(R8G8B8A8_UNORM, 1, 1, &[0; 4])),
+            Some(ImageFormat::Ktx2)
+        );
+        // Truncated signatures and other files aren't mistaken for either
+        assert_eq!(ImageFormat::detect(&png[..4]), None);
+        assert_eq!(ImageFormat::detect(b"GIF89a"), None);
+        assert_eq!(ImageFormat::detect(&[]), None);
+    }
+
+    #[test]
+    fn formats_are_detected_from_their_extension() {
+        assert_eq!(
+            ImageFormat::from_extension("albedo.PNG"),
+            Some(ImageFormat::Png)
+        );
+        assert_eq!(
+            ImageFormat::from_extension("textures/photo.jpeg"),
+            Some(ImageFormat::Jpeg)
+        );
+        assert_eq!(
+            ImageFormat::from_extension("a.jpg"),
+            Some(ImageFormat::Jpeg)
+        );
+        assert_eq!(
+            ImageFormat::from_extension("a.ktx2"),
+            Some(ImageFormat::Ktx2)
+        );
+        assert_eq!(ImageFormat::from_extension("a.bmp"), None);
+        assert_eq!(ImageFormat::from_extension("png"), None);
+    }
+
+    #[test]
+    fn mip_chains_end_at_one_texel() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(2, 2), 2);
+        assert_eq!(mip_level_count(256, 256), 9);
+        assert_eq!(mip_level_count(5, 3), 3);
+        assert_eq!(mip_level_count(1, 1000), 10);
+        assert_eq!(mip_level_count(1024, 1), 11);
+        assert_eq!(mip_level_count(0, 0), 1);
+    }
+
+    #[test]
+    fn mip_sizes_round_down_and_stop_at_one() {
+        let sizes = |width, height| {
+            generate_mips(&solid_image(width, height, [0; 4]), ColorSpace::Linear)
+                .iter()
+                .map(|level| {
+                    assert_eq!(
+                        level.pixels.len(),
+                        (level.width * level.height * 4) as usize
+                    );
+                    (level.width, level.height)
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sizes(4, 4), [(4, 4), (2, 2), (1, 1)]);
+        assert_eq!(sizes(5, 3), [(5, 3), (2, 1), (1, 1)]);
+        assert_eq!(sizes(8, 2), [(8, 2), (4, 1), (2, 1), (1, 1)]);
+        assert_eq!(sizes(1, 7), [(1, 7), (1, 3), (1, 1)]);
+        assert_eq!(sizes(1, 1), [(1, 1)]);
+        for (width, height) in [(5, 3), (8, 2), (1, 7), (300, 17)] {
+            assert_eq!(
+                sizes(width, height).len() as u32,
+                mip_level_count(width, height)
+            );
+        }
+    }
+
+    #[test]
+    fn mips_keep_the_first_level_unchanged() {
+        let mut image = solid_image(3, 2, [10, 20, 30, 40]);
+        image.pixels[0] = 200;
+        let mips = generate_mips(&image, ColorSpace::Srgb);
+        assert_eq!(mips[0].pixels, image.pixels);
+    }
+
+    #[test]
+    fn srgb_channels_are_averaged_in_linear_space() {
+        // Black and white columns, with half transparent white
+        let image = crate::world::Image {
+            width: 2,
+            height: 2,
+            pixels: [[0, 0, 0, 255], [255, 255, 255, 0]].concat().repeat(2),
+            color_space: None,
+        };
+
+        let srgb = &generate_mips(&image, ColorSpace::Srgb)[1];
+        // Half of the light is 188 in sRGB, averaging the encoded values would give 128
+        assert_eq!(srgb.pixels[..3], [188, 188, 188]);
+        // Alpha is always linear
+        assert_eq!(srgb.pixels[3], 128);
+
+        let linear = &generate_mips(&image, ColorSpace::Linear)[1];
+        assert_eq!(linear.pixels, [128, 128, 128, 128]);
+    }
+
+    #[test]
+    fn odd_sizes_drop_the_last_column() {
+        // Halving 3x1 rounds down to 1x1, which covers the first two columns only. The
+        // missing second row is clamped to the first.
+        let image = crate::world::Image {
+            width: 3,
+            height: 1,
+            pixels: [[0, 0, 0, 0], [100, 100, 100, 100], [255, 255, 255, 255]].concat(),
+            color_space: None,
+        };
+        let mips = generate_mips(&image, ColorSpace::Linear);
+        assert_eq!(mips[1].pixels, [50, 50, 50, 50]);
+    }
+
+    #[test]
+    fn srgb_conversions_round_trip() {
+        for value in 0..=255 {
+            assert_eq!(linear_to_srgb(srgb_to_linear(value)), value);
+        }
+        assert_eq!(srgb_to_linear(0), 0.0);
+        assert_eq!(srgb_to_linear(255), 1.0);
+        assert_eq!(linear_to_srgb(-1.0), 0);
+        assert_eq!(linear_to_srgb(2.0), 255);
+    }
+}
@@ -0,0 +1,284 @@
+/// Frame and simulation clocks, advanced by the engine once per frame.
+///
+/// Simulation time runs at `time_scale` relative to real time and stops while paused.
+/// It is consumed in steps of `fixed_timestep` by `State::fixed_update`; whatever is left
+/// over is exposed as `alpha` so rendering can blend the previous and current simulation
+/// states, e.g. with `Transform3D::interpolate`.
+///
+/// Changing `fixed_timestep` takes effect from the next frame, fixed updates already
+/// counted for this one keep the length they were counted with.
+#[derive(Debug, Clone)]
+pub struct Time {
+    pub fixed_timestep: crate::Duration,
+    /// Fixed steps allowed per frame before the remaining backlog is dropped
+    pub max_fixed_steps: u32,
+    /// Clamped to [0, `MAX_TIME_SCALE`], infinite and NaN scales stop simulation time
+    pub time_scale: f32,
+    pub paused: bool,
+    delta: crate::Duration,
+    real_delta: crate::Duration,
+    elapsed: crate::Duration,
+    real_elapsed: crate::Duration,
+    accumulator: crate::Duration,
+    pending_fixed_steps: u32,
+    // The fixed timestep the pending steps were counted with
+    fixed_step: crate::Duration,
+    fixed_elapsed: crate::Duration,
+    fixed_tick: u64,
+    frame: u64,
+}
+
+/// Largest `Time::time_scale` the clocks run at
+pub const MAX_TIME_SCALE: f32 = 1000.0;
+
+impl Default for Time {
+    fn default() -> Self {
+        Self {
+            fixed_timestep: crate::Duration::from_nanos(1_000_000_000 / 60),
+            max_fixed_steps: 8,
+            time_scale: 1.0,
+            paused: false,
+            delta: crate::Duration::ZERO,
+            real_delta: crate::Duration::ZERO,
+            elapsed: crate::Duration::ZERO,
+            real_elapsed: crate::Duration::ZERO,
+            accumulator: crate::Duration::ZERO,
+            pending_fixed_steps: 0,
+            fixed_step: crate::Duration::from_nanos(1_000_000_000 / 60),
+            fixed_elapsed: crate::Duration::ZERO,
+            fixed_tick: 0,
+            frame: 0,
+        }
+    }
+}
+
+impl Time {
+    /// Sets the fixed update rate in ticks per second
+    pub fn set_fixed_rate(&mut self, hz: f64) {
+        self.fixed_timestep = crate::Duration::from_secs_f64(1.0 / hz.max(f64::EPSILON));
+    }
+
+    pub fn fixed_rate(&self) -> f64 {
+        1.0 / self.fixed_timestep.as_secs_f64()
+    }
+
+    /// Scaled time since the previous frame, zero while paused
+    pub fn delta(&self) -> crate::Duration {
+        self.delta
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    /// Wall clock time since the previous frame, unaffected by scaling and pausing
+    pub fn real_delta(&self) -> crate::Duration {
+        self.real_delta
+    }
+
+    /// Total scaled time, advancing only while unpaused
+    pub fn elapsed(&self) -> crate::Duration {
+        self.elapsed
+    }
+
+    pub fn real_elapsed(&self) -> crate::Duration {
+        self.real_elapsed
+    }
+
+    /// Simulation time consumed by fixed updates so far
+    pub fn fixed_elapsed(&self) -> crate::Duration {
+        self.fixed_elapsed
+    }
+
+    /// Length of the fixed updates run this frame
+    pub fn fixed_delta_seconds(&self) -> f32 {
+        self.fixed_step.as_secs_f32()
+    }
+
+    /// Number of fixed updates run since startup
+    pub fn fixed_tick(&self) -> u64 {
+        self.fixed_tick
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// How far the simulation has progressed towards the next fixed update, in [0, 1)
+    pub fn alpha(&self) -> f32 {
+        if self.fixed_step.is_zero() {
+            return 0.0;
+        }
+        (self.accumulator.as_secs_f64() / self.fixed_step.as_secs_f64()) as f32
+    }
+
+    /// Advances the clocks by one frame and works out how many fixed updates to run
+    pub fn update(&mut self, real_delta: crate::Duration) {
+        self.frame += 1;
+        self.real_delta = real_delta;
+        self.real_elapsed += real_delta;
+
+        self.delta = if self.paused {
+            crate::Duration::ZERO
+        } else {
+            // Scaling past the clamp could overflow the duration, and f32 math would round
+            // away nanoseconds even at a scale of 1
+            let time_scale = match self.time_scale.is_finite() {
+                true => self.time_scale.clamp(0.0, MAX_TIME_SCALE),
+                false => 0.0,
+            };
+            real_delta.mul_f64(time_scale as f64)
+        };
+        self.elapsed += self.delta;
+
+        self.fixed_step = self.fixed_timestep;
+        if self.fixed_timestep.is_zero() {
+            self.pending_fixed_steps = 0;
+            return;
+        }
+        self.accumulator += self.delta;
+        let steps = (self.accumulator.as_nanos() / self.fixed_timestep.as_nanos()) as u64;
+        if steps > self.max_fixed_steps as u64 {
+            log::warn!(
+                "Simulation is {} fixed steps behind, skipping ahead",
+                steps - self.max_fixed_steps as u64
+            );
+            self.pending_fixed_steps = self.max_fixed_steps;
+            self.accumulator = crate::Duration::from_nanos(
+                (self.accumulator.as_nanos() % self.fixed_timestep.as_nanos()) as u64,
+            ) + self.fixed_timestep * self.max_fixed_steps;
+        } else {
+            self.pending_fixed_steps = steps as u32;
+        }
+    }
+
+    /// Consumes one pending fixed update, returning false once the frame has none left
+    pub fn next_fixed_step(&mut self) -> bool {
+        if self.pending_fixed_steps == 0 {
+            return false;
+        }
+        self.pending_fixed_steps -= 1;
+        self.accumulator -= self.fixed_step;
+        self.fixed_elapsed += self.fixed_step;
+        self.fixed_tick += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(millis: u64) -> crate::Duration {
+        crate::Duration::from_millis(millis)
+    }
+
+    fn ten_millisecond_steps() -> Time {
+        Time {
+            fixed_timestep: millis(10),
+            ..Default::default()
+        }
+    }
+
+    fn run_fixed_steps(time: &mut Time) -> u32 {
+        let mut steps = 0;
+        while time.next_fixed_step() {
+            steps += 1;
+        }
+        steps
+    }
+
+    #[test]
+    fn uneven_frames_run_whole_steps() {
+        let mut time = ten_millisecond_steps();
+        let steps = [4, 4, 4, 15, 3, 30]
+            .map(|delta| {
+                time.update(millis(delta));
+                run_fixed_steps(&mut time)
+            })
+            .to_vec();
+        assert_eq!(steps, vec![0, 0, 1, 1, 1, 3]);
+        assert_eq!(time.fixed_tick(), 6);
+        assert_eq!(time.fixed_elapsed(), millis(60));
+        assert_eq!(time.elapsed(), millis(60));
+    }
+
+    #[test]
+    fn long_frames_are_clamped_to_max_fixed_steps() {
+        let mut time = Time {
+            max_fixed_steps: 3,
+            ..ten_millisecond_steps()
+        };
+        time.update(millis(55));
+        assert_eq!(run_fixed_steps(&mut time), 3);
+        // The dropped steps are gone but the remainder is kept
+        assert_eq!(time.fixed_elapsed(), millis(30));
+        assert!((time.alpha() - 0.5).abs() < 1e-6);
+        time.update(millis(5));
+        assert_eq!(run_fixed_steps(&mut time), 1);
+    }
+
+    #[test]
+    fn paused_time_only_advances_real_time() {
+        let mut time = ten_millisecond_steps();
+        time.paused = true;
+        time.update(millis(25));
+        assert_eq!(run_fixed_steps(&mut time), 0);
+        assert_eq!(time.delta(), crate::Duration::ZERO);
+        assert_eq!(time.elapsed(), crate::Duration::ZERO);
+        assert_eq!(time.real_elapsed(), millis(25));
+        assert_eq!(time.frame(), 1);
+    }
+
+    #[test]
+    fn time_scale_speeds_up_the_simulation() {
+        let mut time = Time {
+            time_scale: 2.0,
+            ..ten_millisecond_steps()
+        };
+        time.update(millis(15));
+        assert_eq!(time.delta(), millis(30));
+        assert_eq!(time.real_delta(), millis(15));
+        assert_eq!(run_fixed_steps(&mut time), 3);
+    }
+
+    #[test]
+    fn unusable_time_scales_do_not_panic() {
+        let mut time = ten_millisecond_steps();
+        for time_scale in [f32::INFINITY, f32::NAN, -1.0] {
+            time.time_scale = time_scale;
+            time.update(millis(10));
+            assert_eq!(time.delta(), crate::Duration::ZERO);
+        }
+        time.time_scale = f32::MAX;
+        time.update(millis(10));
+        assert_eq!(time.delta(), millis(10).mul_f64(MAX_TIME_SCALE as f64));
+    }
+
+    #[test]
+    fn alpha_stays_below_one() {
+        let mut time = ten_millisecond_steps();
+        for delta in [1, 3, 7, 9, 10, 11, 13, 19, 20, 29, 1, 1] {
+            time.update(millis(delta));
+            run_fixed_steps(&mut time);
+            assert!((0.0..1.0).contains(&time.alpha()), "{}", time.alpha());
+        }
+    }
+
+    #[test]
+    fn changing_the_rate_mid_frame_applies_from_the_next_frame() {
+        let mut time = ten_millisecond_steps();
+        time.update(millis(35));
+        assert!(time.next_fixed_step());
+        time.set_fixed_rate(1000.0);
+        // The rest of this frame's steps keep the length they were counted with
+        assert_eq!(run_fixed_steps(&mut time), 2);
+        assert_eq!(time.fixed_elapsed(), millis(30));
+        assert_eq!(time.fixed_delta_seconds(), 0.01);
+        assert!((0.0..1.0).contains(&time.alpha()));
+
+        time.update(millis(2));
+        assert_eq!(time.fixed_delta_seconds(), 0.001);
+        assert_eq!(run_fixed_steps(&mut time), 7);
+    }
+}
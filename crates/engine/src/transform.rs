@@ -0,0 +1,273 @@
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransformError {
+    // The scene graph contains a cycle reachable through this node
+    Cycle { node: petgraph::graph::NodeIndex },
+    // A node can only inherit its transform from a single parent
+    MultipleParents { node: petgraph::graph::NodeIndex },
+}
+
+impl std::fmt::Display for TransformError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cycle { node } => write!(
+                f,
+                "Scene graph contains a cycle through node {}, world transforms cannot be computed",
+                node.index()
+            ),
+            Self::MultipleParents { node } => write!(
+                f,
+                "Node {} has more than one parent, world transforms cannot be computed",
+                node.index()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TransformError {}
+
+/// Cached world-space matrices for every node in a scene.
+///
+/// Local transforms are accumulated along parent -> child edges. After the first full build,
+/// only the subtrees of nodes marked dirty are recomputed. Any structural change to the scene
+/// triggers a full rebuild on the next update.
+#[derive(Default, Debug, Clone)]
+pub struct SceneTransforms {
+    global_transforms: std::collections::HashMap<petgraph::graph::NodeIndex, nalgebra_glm::Mat4>,
+    dirty: std::collections::HashSet<petgraph::graph::NodeIndex>,
+    built: bool,
+    revision: u64,
+}
+
+impl SceneTransforms {
+    pub fn global_transform(&self, node: petgraph::graph::NodeIndex) -> Option<nalgebra_glm::Mat4> {
+        self.global_transforms.get(&node).copied()
+    }
+
+    pub fn mark_dirty(&mut self, node: petgraph::graph::NodeIndex) {
+        self.dirty.insert(node);
+    }
+
+    /// Forces a full rebuild on the next update
+    pub fn invalidate(&mut self) {
+        self.built = false;
+    }
+
+    pub fn update(&mut self, scene: &crate::world::Scene) -> Result<(), TransformError> {
+        let structure_changed = !self.built || self.revision != scene.revision();
+        if structure_changed {
+            return self.rebuild(scene);
+        }
+
+        let dirty = std::mem::take(&mut self.dirty);
+        for node in dirty.into_iter() {
+            if scene.node_weight(node).is_none() {
+                continue;
+            }
+            let parent_transform = match parent(scene, node)? {
+                Some(parent) => self.global_transforms[&parent],
+                None => nalgebra_glm::Mat4::identity(),
+            };
+            self.propagate(scene, node, parent_transform)?;
+        }
+
+        Ok(())
+    }
+
+    fn rebuild(&mut self, scene: &crate::world::Scene) -> Result<(), TransformError> {
+        self.global_transforms.clear();
+        self.dirty.clear();
+        self.built = true;
+        self.revision = scene.revision();
+
+        let result = petgraph::algo::toposort(scene.graph(), None)
+            .map_err(|cycle| TransformError::Cycle {
+                node: cycle.node_id(),
+            })
+            .and_then(|sorted| {
+                sorted.into_iter().try_for_each(|node| {
+                    let parent_transform = match parent(scene, node)? {
+                        Some(parent) => self.global_transforms[&parent],
+                        None => nalgebra_glm::Mat4::identity(),
+                    };
+                    self.global_transforms
+                        .insert(node, parent_transform * scene[node].local_matrix());
+                    Ok(())
+                })
+            });
+
+        if result.is_err() {
+            self.global_transforms.clear();
+            self.built = false;
+        }
+
+        result
+    }
+
+    fn propagate(
+        &mut self,
+        scene: &crate::world::Scene,
+        node: petgraph::graph::NodeIndex,
+        parent_transform: nalgebra_glm::Mat4,
+    ) -> Result<(), TransformError> {
+        let mut visited = std::collections::HashSet::new();
+        let mut stack = vec![(node, parent_transform)];
+        while let Some((node, parent_transform)) = stack.pop() {
+            if !visited.insert(node) {
+                self.built = false;
+                return Err(TransformError::Cycle { node });
+            }
+            let global_transform = parent_transform * scene[node].local_matrix();
+            self.global_transforms.insert(node, global_transform);
+            scene
+                .neighbors_directed(node, petgraph::Direction::Outgoing)
+                .for_each(|child| stack.push((child, global_transform)));
+        }
+        Ok(())
+    }
+}
+
+fn parent(
+    scene: &crate::world::Scene,
+    node: petgraph::graph::NodeIndex,
+) -> Result<Option<petgraph::graph::NodeIndex>, TransformError> {
+    let mut parents = scene.neighbors_directed(node, petgraph::Direction::Incoming);
+    let parent = parents.next();
+    if parents.next().is_some() {
+        return Err(TransformError::MultipleParents { node });
+    }
+    Ok(parent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node_at(x: f32) -> crate::world::Node {
+        crate::world::Node::Node3D {
+            transform: crate::world::Transform3D {
+                translation: nalgebra_glm::vec3(x, 0.0, 0.0),
+                ..Default::default()
+            },
+            node: crate::world::Node3D::Empty,
+        }
+    }
+
+    fn translation(transforms: &SceneTransforms, node: petgraph::graph::NodeIndex) -> f32 {
+        transforms.global_transform(node).unwrap()[(0, 3)]
+    }
+
+    fn set_x(scene: &mut crate::world::Scene, node: petgraph::graph::NodeIndex, x: f32) {
+        if let crate::world::Node::Node3D { transform, .. } = &mut scene[node] {
+            transform.translation.x = x;
+        }
+    }
+
+    #[test]
+    fn children_inherit_parent_transforms() {
+        let mut scene = crate::world::Scene::default();
+        let root = scene.add_node(node_at(1.0));
+        let child = scene.add_node(node_at(2.0));
+        let grandchild = scene.add_node(node_at(4.0));
+        scene.add_edge(root, child);
+        scene.add_edge(child, grandchild);
+
+        let mut transforms = SceneTransforms::default();
+        transforms.update(&scene).unwrap();
+        assert_eq!(translation(&transforms, root), 1.0);
+        assert_eq!(translation(&transforms, child), 3.0);
+        assert_eq!(translation(&transforms, grandchild), 7.0);
+    }
+
+    #[test]
+    fn dirty_nodes_only_recompute_their_subtree() {
+        let mut scene = crate::world::Scene::default();
+        let root = scene.add_node(node_at(1.0));
+        let child = scene.add_node(node_at(2.0));
+        let other_root = scene.add_node(node_at(10.0));
+        let other_child = scene.add_node(node_at(20.0));
+        scene.add_edge(root, child);
+        scene.add_edge(other_root, other_child);
+
+        let mut transforms = SceneTransforms::default();
+        transforms.update(&scene).unwrap();
+
+        // The other subtree is edited without being marked dirty, so a recompute of it
+        // would show up as a changed matrix
+        set_x(&mut scene, root, 5.0);
+        set_x(&mut scene, other_root, 100.0);
+        transforms.mark_dirty(root);
+        transforms.update(&scene).unwrap();
+
+        assert_eq!(translation(&transforms, root), 5.0);
+        assert_eq!(translation(&transforms, child), 7.0);
+        assert_eq!(translation(&transforms, other_root), 10.0);
+        assert_eq!(translation(&transforms, other_child), 30.0);
+    }
+
+    #[test]
+    fn dirty_child_keeps_its_parent_transform() {
+        let mut scene = crate::world::Scene::default();
+        let root = scene.add_node(node_at(1.0));
+        let child = scene.add_node(node_at(2.0));
+        scene.add_edge(root, child);
+
+        let mut transforms = SceneTransforms::default();
+        transforms.update(&scene).unwrap();
+        set_x(&mut scene, child, 3.0);
+        transforms.mark_dirty(child);
+        transforms.update(&scene).unwrap();
+
+        assert_eq!(translation(&transforms, root), 1.0);
+        assert_eq!(translation(&transforms, child), 4.0);
+    }
+
+    #[test]
+    fn structural_changes_rebuild_everything() {
+        let mut scene = crate::world::Scene::default();
+        let root = scene.add_node(node_at(1.0));
+        let child = scene.add_node(node_at(2.0));
+
+        let mut transforms = SceneTransforms::default();
+        transforms.update(&scene).unwrap();
+        assert_eq!(translation(&transforms, child), 2.0);
+
+        scene.add_edge(root, child);
+        transforms.update(&scene).unwrap();
+        assert_eq!(translation(&transforms, child), 3.0);
+    }
+
+    #[test]
+    fn cycles_are_reported() {
+        let mut scene = crate::world::Scene::default();
+        let a = scene.add_node(node_at(1.0));
+        let b = scene.add_node(node_at(2.0));
+        scene.add_edge(a, b);
+        scene.add_edge(b, a);
+
+        let mut transforms = SceneTransforms::default();
+        let error = transforms.update(&scene).unwrap_err();
+        assert!(matches!(error, TransformError::Cycle { node } if node == a || node == b));
+        assert_eq!(transforms.global_transform(a), None);
+
+        // Breaking the cycle recovers on the next update
+        scene.remove_edge(b, a);
+        transforms.update(&scene).unwrap();
+        assert_eq!(translation(&transforms, b), 3.0);
+    }
+
+    #[test]
+    fn multiple_parents_are_reported() {
+        let mut scene = crate::world::Scene::default();
+        let a = scene.add_node(node_at(1.0));
+        let b = scene.add_node(node_at(2.0));
+        let child = scene.add_node(node_at(3.0));
+        scene.add_edge(a, child);
+        scene.add_edge(b, child);
+
+        let mut transforms = SceneTransforms::default();
+        assert_eq!(
+            transforms.update(&scene),
+            Err(TransformError::MultipleParents { node: child })
+        );
+    }
+}
@@ -1,10 +1,726 @@
 #[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct World {
     pub scenes: Vec<Scene>,
+    #[serde(serialize_with = "crate::persistence::serialize_sorted")]
     pub meshes: MeshRegistry,
+    #[serde(serialize_with = "crate::persistence::serialize_sorted")]
+    pub materials: MaterialRegistry,
+    #[serde(serialize_with = "crate::persistence::serialize_sorted")]
+    pub textures: TextureRegistry,
+    #[serde(skip)]
+    pub transforms: Vec<crate::transform::SceneTransforms>,
+    // Runtime override for the rendered camera, see `World::set_active_camera`
+    #[serde(skip)]
+    pub active_camera: Option<NodeId>,
+    // Lets the renderer tell when a cached mesh or texture has to be uploaded again
+    #[serde(skip)]
+    pub revisions: ResourceRevisions,
 }
 
-pub type Scene = petgraph::Graph<Node, ()>;
+impl World {
+    /// Recomputes cached world transforms for every scene, returning the first error encountered
+    pub fn update_transforms(&mut self) -> Result<(), crate::transform::TransformError> {
+        if self.transforms.len() != self.scenes.len() {
+            self.transforms = vec![crate::transform::SceneTransforms::default(); self.scenes.len()];
+        }
+        let mut result = Ok(());
+        for (scene, transforms) in self.scenes.iter().zip(self.transforms.iter_mut()) {
+            if let Err(error) = transforms.update(scene) {
+                if result.is_ok() {
+                    result = Err(error);
+                }
+            }
+        }
+        result
+    }
+
+    /// Finds the scene and current index of a node by its stable id
+    pub fn find_node(&self, id: NodeId) -> Option<(usize, petgraph::graph::NodeIndex)> {
+        self.scenes
+            .iter()
+            .enumerate()
+            .find_map(|(scene_index, scene)| Some((scene_index, scene.node_index(id)?)))
+    }
+
+    pub fn global_transform(
+        &self,
+        scene_index: usize,
+        node: petgraph::graph::NodeIndex,
+    ) -> Option<nalgebra_glm::Mat4> {
+        self.transforms.get(scene_index)?.global_transform(node)
+    }
+
+    /// The camera used to render the world: the camera chosen with `set_active_camera` if it
+    /// still exists, otherwise the first camera registered to a main viewport, falling back to
+    /// the first camera found in any scene
+    pub fn active_camera(&self) -> Option<(usize, petgraph::graph::NodeIndex)> {
+        if let Some((scene_index, node_index)) =
+            self.active_camera.and_then(|id| self.find_node(id))
+        {
+            if self.camera(scene_index, node_index).is_some() {
+                return Some((scene_index, node_index));
+            }
+        }
+
+        let cameras = self
+            .scenes
+            .iter()
+            .enumerate()
+            .flat_map(|(scene_index, scene)| {
+                scene
+                    .node_indices()
+                    .filter(|node_index| {
+                        matches!(
+                            scene[*node_index],
+                            Node::Node3D {
+                                node: Node3D::Camera3D { .. },
+                                ..
+                            }
+                        )
+                    })
+                    .map(move |node_index| (scene_index, node_index))
+            })
+            .collect::<Vec<_>>();
+
+        cameras
+            .iter()
+            .find(|(scene_index, node_index)| {
+                is_registered_to_main_viewport(&self.scenes[*scene_index], *node_index)
+            })
+            .or_else(|| cameras.first())
+            .copied()
+    }
+
+    pub fn camera(
+        &self,
+        scene_index: usize,
+        node: petgraph::graph::NodeIndex,
+    ) -> Option<&Camera3D> {
+        match self.scenes.get(scene_index)?.node_weight(node)? {
+            Node::Node3D {
+                node: Node3D::Camera3D { camera },
+                ..
+            } => Some(camera),
+            _ => None,
+        }
+    }
+
+    /// Replaces the local transform of a Node3D and marks its subtree for recomputation
+    pub fn set_local_transform(
+        &mut self,
+        scene_index: usize,
+        node: petgraph::graph::NodeIndex,
+        transform: Transform3D,
+    ) {
+        let Some(Node::Node3D {
+            transform: local_transform,
+            ..
+        }) = self
+            .scenes
+            .get_mut(scene_index)
+            .and_then(|scene| scene.node_weight_mut(node))
+        else {
+            return;
+        };
+        *local_transform = transform;
+        if let Some(transforms) = self.transforms.get_mut(scene_index) {
+            transforms.mark_dirty(node);
+        }
+    }
+
+    /// Adds a node to a scene, optionally as the child of an existing node in that scene
+    pub fn spawn_node(
+        &mut self,
+        scene_index: usize,
+        id: NodeId,
+        node: Node,
+        parent: Option<NodeId>,
+    ) -> Result<petgraph::graph::NodeIndex, SceneError> {
+        if scene_index >= self.scenes.len() {
+            return Err(SceneError::SceneNotFound { scene_index });
+        }
+        if self.find_node(id).is_some() {
+            return Err(SceneError::DuplicateNode { id });
+        }
+        let parent_index = match parent {
+            Some(parent) => match self.find_node(parent) {
+                Some((parent_scene, parent_index)) if parent_scene == scene_index => {
+                    Some(parent_index)
+                }
+                Some(_) => return Err(SceneError::DifferentScenes { node: id, parent }),
+                None => return Err(SceneError::NodeNotFound { id: parent }),
+            },
+            None => None,
+        };
+
+        let scene = &mut self.scenes[scene_index];
+        let Some(index) = scene.add_node_with_id(id, node) else {
+            return Err(SceneError::DuplicateNode { id });
+        };
+        if let Some(parent_index) = parent_index {
+            scene.add_edge(parent_index, index);
+        }
+        Ok(index)
+    }
+
+    /// Removes a node along with every node beneath it.
+    ///
+    /// Fails without removing anything if a node beneath it also has a parent elsewhere,
+    /// as removing that node would take it from under the other parent too.
+    pub fn despawn_node(&mut self, id: NodeId) -> Result<(), SceneError> {
+        let (scene_index, node_index) =
+            self.find_node(id).ok_or(SceneError::NodeNotFound { id })?;
+        let scene = &mut self.scenes[scene_index];
+        let mut subtree = std::collections::HashSet::new();
+        let mut dfs = petgraph::visit::Dfs::new(scene.graph(), node_index);
+        while let Some(node) = dfs.next(scene.graph()) {
+            subtree.insert(node);
+        }
+        let shared = subtree
+            .iter()
+            .copied()
+            .filter(|&node| {
+                node != node_index
+                    && scene
+                        .neighbors_directed(node, petgraph::Direction::Incoming)
+                        .any(|parent| !subtree.contains(&parent))
+            })
+            .find_map(|node| scene.node_id(node));
+        if let Some(descendant) = shared {
+            return Err(SceneError::SharedDescendant {
+                node: id,
+                descendant,
+            });
+        }
+        subtree.into_iter().for_each(|node| {
+            scene.remove_node(node);
+        });
+        if self
+            .active_camera
+            .is_some_and(|camera| self.find_node(camera).is_none())
+        {
+            self.active_camera = None;
+        }
+        Ok(())
+    }
+
+    /// Moves a node under a new parent in the same scene, or to the scene root for `None`
+    pub fn reparent_node(&mut self, id: NodeId, parent: Option<NodeId>) -> Result<(), SceneError> {
+        let (scene_index, node_index) =
+            self.find_node(id).ok_or(SceneError::NodeNotFound { id })?;
+        let parent_index = match parent {
+            Some(parent) => {
+                let (parent_scene, parent_index) = self
+                    .find_node(parent)
+                    .ok_or(SceneError::NodeNotFound { id: parent })?;
+                if parent_scene != scene_index {
+                    return Err(SceneError::DifferentScenes { node: id, parent });
+                }
+                if petgraph::algo::has_path_connecting(
+                    self.scenes[scene_index].graph(),
+                    node_index,
+                    parent_index,
+                    None,
+                ) {
+                    return Err(SceneError::Cycle { node: id, parent });
+                }
+                Some(parent_index)
+            }
+            None => None,
+        };
+
+        let scene = &mut self.scenes[scene_index];
+        let parents = scene
+            .neighbors_directed(node_index, petgraph::Direction::Incoming)
+            .collect::<Vec<_>>();
+        parents.into_iter().for_each(|old_parent| {
+            scene.remove_edge(old_parent, node_index);
+        });
+        if let Some(parent_index) = parent_index {
+            scene.add_edge(parent_index, node_index);
+        }
+        Ok(())
+    }
+
+    /// Replaces the local transform of a Node3D looked up by its stable id
+    pub fn set_node_transform(
+        &mut self,
+        id: NodeId,
+        transform: Transform3D,
+    ) -> Result<(), SceneError> {
+        let (scene_index, node_index) =
+            self.find_node(id).ok_or(SceneError::NodeNotFound { id })?;
+        if !matches!(self.scenes[scene_index][node_index], Node::Node3D { .. }) {
+            return Err(SceneError::NotANode3D { id });
+        }
+        self.set_local_transform(scene_index, node_index, transform);
+        Ok(())
+    }
+
+    /// Renders through the given camera, or restores the default camera selection for `None`
+    pub fn set_active_camera(&mut self, id: Option<NodeId>) -> Result<(), SceneError> {
+        if let Some(id) = id {
+            let (scene_index, node_index) =
+                self.find_node(id).ok_or(SceneError::NodeNotFound { id })?;
+            if self.camera(scene_index, node_index).is_none() {
+                return Err(SceneError::NotACamera { id });
+            }
+        }
+        self.active_camera = id;
+        Ok(())
+    }
+
+    /// Adds or replaces a mesh, so a renderer that already uploaded the old one uploads it again
+    pub fn insert_mesh(&mut self, id: impl Into<MeshId>, mesh: Mesh) -> Option<Mesh> {
+        let id = id.into();
+        self.mark_mesh_changed(&id);
+        self.meshes.insert(id, mesh)
+    }
+
+    /// Edits a mesh in place, see `World::insert_mesh`
+    pub fn mesh_mut(&mut self, id: &str) -> Option<&mut Mesh> {
+        self.mark_mesh_changed(id);
+        self.meshes.get_mut(id)
+    }
+
+    /// Must be called after editing or replacing a mesh directly through `World::meshes`
+    pub fn mark_mesh_changed(&mut self, id: &str) {
+        self.revisions
+            .meshes
+            .insert(id.to_string(), next_revision());
+    }
+
+    /// Adds or replaces a texture, see `World::insert_mesh`
+    pub fn insert_texture(
+        &mut self,
+        id: impl Into<TextureId>,
+        texture: Texture,
+    ) -> Option<Texture> {
+        let id = id.into();
+        self.mark_texture_changed(&id);
+        self.textures.insert(id, texture)
+    }
+
+    /// Edits a texture in place, see `World::insert_mesh`
+    pub fn texture_mut(&mut self, id: &str) -> Option<&mut Texture> {
+        self.mark_texture_changed(id);
+        self.textures.get_mut(id)
+    }
+
+    /// Must be called after editing or replacing a texture directly through `World::textures`
+    pub fn mark_texture_changed(&mut self, id: &str) {
+        self.revisions
+            .textures
+            .insert(id.to_string(), next_revision());
+    }
+
+    /// Removes a scene, shifting the indices of every scene after it down by one
+    pub fn unload_scene(&mut self, scene_index: usize) -> Result<Scene, SceneError> {
+        if scene_index >= self.scenes.len() {
+            return Err(SceneError::SceneNotFound { scene_index });
+        }
+        if scene_index < self.transforms.len() {
+            self.transforms.remove(scene_index);
+        }
+        Ok(self.scenes.remove(scene_index))
+    }
+
+    /// Appends the scenes of another world and returns their indices.
+    ///
+    /// Incoming nodes whose ids are already in use, such as when the same file is loaded twice,
+    /// are given fresh ids. Incoming meshes, materials and textures whose ids are already in
+    /// use are renamed with a numeric suffix, and references to them are updated to match.
+    pub fn merge(&mut self, mut other: World) -> std::ops::Range<usize> {
+        let texture_ids = merged_ids(&self.textures, &other.textures);
+        for material in other.materials.values_mut() {
+            for reference in material.texture_references_mut() {
+                rename(&mut reference.texture, &texture_ids);
+            }
+        }
+        let material_ids = merged_ids(&self.materials, &other.materials);
+        for mesh in other.meshes.values_mut() {
+            match mesh {
+                Mesh::ArrayMesh(array_mesh) => array_mesh
+                    .surfaces
+                    .iter_mut()
+                    .filter_map(|surface| surface.material.as_mut())
+                    .for_each(|material| rename(material, &material_ids)),
+                Mesh::PrimitiveMesh(primitive_mesh) => {
+                    if let Some(material) = primitive_mesh.material.as_mut() {
+                        rename(material, &material_ids);
+                    }
+                }
+                _ => {}
+            }
+        }
+        let mesh_ids = merged_ids(&self.meshes, &other.meshes);
+
+        let start = self.scenes.len();
+        for mut scene in other.scenes {
+            for node in scene.graph.node_weights_mut() {
+                if let Node::VisualInstance3D(VisualInstance3D::Geometry(
+                    Geometry::MeshInstance3D(MeshInstance3D {
+                        mesh_reference: Some(mesh),
+                    }),
+                )) = node
+                {
+                    rename(mesh, &mesh_ids);
+                }
+            }
+            let collisions = scene
+                .node_indices()
+                .filter(|index| {
+                    scene
+                        .node_id(*index)
+                        .is_some_and(|id| self.find_node(id).is_some())
+                })
+                .collect::<Vec<_>>();
+            collisions.into_iter().for_each(|index| {
+                let mut id = NodeId::new_v4();
+                while self.find_node(id).is_some() || scene.node_index(id).is_some() {
+                    id = NodeId::new_v4();
+                }
+                scene.set_node_id(index, id);
+            });
+            self.scenes.push(scene);
+        }
+        for (mut id, mesh) in other.meshes {
+            rename(&mut id, &mesh_ids);
+            self.insert_mesh(id, mesh);
+        }
+        for (mut id, material) in other.materials {
+            rename(&mut id, &material_ids);
+            self.materials.insert(id, material);
+        }
+        for (mut id, texture) in other.textures {
+            rename(&mut id, &texture_ids);
+            self.insert_texture(id, texture);
+        }
+        start..self.scenes.len()
+    }
+}
+
+// New ids for the incoming registry entries whose ids are already taken, keyed by the old id.
+// Suffixes skip ids used by either registry so a renamed entry can't collide again.
+fn merged_ids<T>(
+    existing: &std::collections::HashMap<String, T>,
+    incoming: &std::collections::HashMap<String, T>,
+) -> std::collections::HashMap<String, String> {
+    let mut renames = std::collections::HashMap::new();
+    let mut taken = existing
+        .keys()
+        .chain(incoming.keys())
+        .cloned()
+        .collect::<std::collections::HashSet<_>>();
+    let mut colliding = incoming
+        .keys()
+        .filter(|id| existing.contains_key(*id))
+        .collect::<Vec<_>>();
+    // Sorted so the same merge always produces the same ids
+    colliding.sort();
+    for id in colliding {
+        let renamed = (1..)
+            .map(|suffix| format!("{id}_{suffix}"))
+            .find(|renamed| !taken.contains(renamed))
+            .unwrap();
+        taken.insert(renamed.clone());
+        renames.insert(id.clone(), renamed);
+    }
+    renames
+}
+
+fn rename(id: &mut String, renames: &std::collections::HashMap<String, String>) {
+    if let Some(renamed) = renames.get(id) {
+        id.clone_from(renamed);
+    }
+}
+
+/// Revisions of a world's registry entries, bumped whenever one is changed through the world.
+///
+/// Revisions are unique across every world in the process, so an entry of a newly created
+/// or loaded world never shares a revision with an entry of the world it replaced.
+#[derive(Debug, Clone)]
+pub struct ResourceRevisions {
+    // Revision of every entry that wasn't changed since the world was created
+    base: u64,
+    meshes: std::collections::HashMap<MeshId, u64>,
+    textures: std::collections::HashMap<TextureId, u64>,
+}
+
+impl Default for ResourceRevisions {
+    fn default() -> Self {
+        Self {
+            base: next_revision(),
+            meshes: std::collections::HashMap::new(),
+            textures: std::collections::HashMap::new(),
+        }
+    }
+}
+
+impl ResourceRevisions {
+    pub fn mesh(&self, id: &str) -> u64 {
+        self.meshes.get(id).copied().unwrap_or(self.base)
+    }
+
+    pub fn texture(&self, id: &str) -> u64 {
+        self.textures.get(id).copied().unwrap_or(self.base)
+    }
+}
+
+fn next_revision() -> u64 {
+    static NEXT_REVISION: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+    NEXT_REVISION.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SceneError {
+    SceneNotFound { scene_index: usize },
+    NodeNotFound { id: NodeId },
+    DuplicateNode { id: NodeId },
+    // Parent and child must live in the same scene graph
+    DifferentScenes { node: NodeId, parent: NodeId },
+    // The new parent is the node itself or one of its descendants
+    Cycle { node: NodeId, parent: NodeId },
+    NotANode3D { id: NodeId },
+    NotACamera { id: NodeId },
+    // A node beneath the despawned one is also a child of a node that stays
+    SharedDescendant { node: NodeId, descendant: NodeId },
+}
+
+impl std::fmt::Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SceneNotFound { scene_index } => write!(f, "Scene {scene_index} does not exist"),
+            Self::NodeNotFound { id } => write!(f, "Node {id} does not exist"),
+            Self::DuplicateNode { id } => write!(f, "Node {id} already exists"),
+            Self::DifferentScenes { node, parent } => write!(
+                f,
+                "Node {node} cannot be parented to {parent} because they are in different scenes"
+            ),
+            Self::Cycle { node, parent } => write!(
+                f,
+                "Node {node} cannot be parented to {parent} because it would create a cycle"
+            ),
+            Self::NotANode3D { id } => write!(f, "Node {id} is not a Node3D"),
+            Self::NotACamera { id } => write!(f, "Node {id} is not a camera"),
+            Self::SharedDescendant { node, descendant } => write!(
+                f,
+                "Node {node} cannot be despawned because {descendant} beneath it has another parent"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+pub type SceneGraph = petgraph::stable_graph::StableGraph<Node, ()>;
+
+/// Stable identifier for a node that survives graph edits, save files and replication
+pub type NodeId = uuid::Uuid;
+
+/// A scene graph where every node carries a stable id.
+///
+/// Node indices remain valid across removals of other nodes, but a removed node's slot
+/// may be reused, so anything held long term should refer to nodes by `NodeId`.
+/// Structural edits must go through the `Scene` methods so the id lookup stays in sync.
+#[derive(Default, Debug, Clone, serde::Deserialize)]
+#[serde(from = "SceneData<SceneGraph>")]
+pub struct Scene {
+    graph: SceneGraph,
+    ids: std::collections::HashMap<petgraph::graph::NodeIndex, NodeId>,
+    indices: std::collections::HashMap<NodeId, petgraph::graph::NodeIndex>,
+    revision: u64,
+}
+
+impl Scene {
+    pub fn graph(&self) -> &SceneGraph {
+        &self.graph
+    }
+
+    /// Incremented on every structural change, so caches can detect stale node indices
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn add_node(&mut self, node: Node) -> petgraph::graph::NodeIndex {
+        let mut id = NodeId::new_v4();
+        while self.indices.contains_key(&id) {
+            id = NodeId::new_v4();
+        }
+        self.insert_node(id, node)
+    }
+
+    /// Adds a node with a known id, such as one received over the network.
+    /// Returns `None` if the id is already in use.
+    pub fn add_node_with_id(
+        &mut self,
+        id: NodeId,
+        node: Node,
+    ) -> Option<petgraph::graph::NodeIndex> {
+        if self.indices.contains_key(&id) {
+            return None;
+        }
+        Some(self.insert_node(id, node))
+    }
+
+    pub fn remove_node(&mut self, index: petgraph::graph::NodeIndex) -> Option<Node> {
+        let node = self.graph.remove_node(index)?;
+        if let Some(id) = self.ids.remove(&index) {
+            self.indices.remove(&id);
+        }
+        self.revision += 1;
+        Some(node)
+    }
+
+    pub fn add_edge(
+        &mut self,
+        parent: petgraph::graph::NodeIndex,
+        child: petgraph::graph::NodeIndex,
+    ) -> petgraph::graph::EdgeIndex {
+        self.revision += 1;
+        self.graph.add_edge(parent, child, ())
+    }
+
+    pub fn remove_edge(
+        &mut self,
+        parent: petgraph::graph::NodeIndex,
+        child: petgraph::graph::NodeIndex,
+    ) -> bool {
+        let Some(edge) = self.graph.find_edge(parent, child) else {
+            return false;
+        };
+        self.graph.remove_edge(edge);
+        self.revision += 1;
+        true
+    }
+
+    // Ids are expected to be unique, callers must check the new id isn't already taken
+    fn set_node_id(&mut self, index: petgraph::graph::NodeIndex, id: NodeId) {
+        if let Some(old_id) = self.ids.insert(index, id) {
+            self.indices.remove(&old_id);
+        }
+        self.indices.insert(id, index);
+    }
+
+    pub fn node_id(&self, index: petgraph::graph::NodeIndex) -> Option<NodeId> {
+        self.ids.get(&index).copied()
+    }
+
+    pub fn node_index(&self, id: NodeId) -> Option<petgraph::graph::NodeIndex> {
+        self.indices.get(&id).copied()
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<&Node> {
+        self.graph.node_weight(self.node_index(id)?)
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        let index = self.node_index(id)?;
+        self.graph.node_weight_mut(index)
+    }
+
+    pub fn node_weight_mut(&mut self, index: petgraph::graph::NodeIndex) -> Option<&mut Node> {
+        self.graph.node_weight_mut(index)
+    }
+
+    fn insert_node(&mut self, id: NodeId, node: Node) -> petgraph::graph::NodeIndex {
+        let index = self.graph.add_node(node);
+        self.ids.insert(index, id);
+        self.indices.insert(id, index);
+        self.revision += 1;
+        index
+    }
+}
+
+impl std::ops::Deref for Scene {
+    type Target = SceneGraph;
+
+    fn deref(&self) -> &Self::Target {
+        &self.graph
+    }
+}
+
+impl std::ops::Index<petgraph::graph::NodeIndex> for Scene {
+    type Output = Node;
+
+    fn index(&self, index: petgraph::graph::NodeIndex) -> &Self::Output {
+        &self.graph[index]
+    }
+}
+
+impl std::ops::IndexMut<petgraph::graph::NodeIndex> for Scene {
+    fn index_mut(&mut self, index: petgraph::graph::NodeIndex) -> &mut Self::Output {
+        &mut self.graph[index]
+    }
+}
+
+// On disk the id lookup is stored as (node index, id) pairs sorted by index,
+// and rebuilt in both directions when loading
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "Scene")]
+struct SceneData<G> {
+    graph: G,
+    ids: Vec<(u32, NodeId)>,
+}
+
+impl serde::Serialize for Scene {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut ids = self
+            .ids
+            .iter()
+            .map(|(index, id)| (index.index() as u32, *id))
+            .collect::<Vec<_>>();
+        ids.sort_by_key(|(index, _)| *index);
+        SceneData {
+            graph: &self.graph,
+            ids,
+        }
+        .serialize(serializer)
+    }
+}
+
+// Every node of a bare graph is given a fresh id
+impl From<SceneGraph> for Scene {
+    fn from(graph: SceneGraph) -> Self {
+        Self::from(SceneData {
+            graph,
+            ids: Vec::new(),
+        })
+    }
+}
+
+impl From<SceneData<SceneGraph>> for Scene {
+    fn from(data: SceneData<SceneGraph>) -> Self {
+        let mut scene = Self {
+            graph: data.graph,
+            ..Default::default()
+        };
+        for (index, id) in data.ids {
+            let index = petgraph::graph::NodeIndex::new(index as usize);
+            // Only the first pair for a node or an id counts, so a hand edited file repeating
+            // one can't leave two nodes answering to the same id
+            if scene.graph.contains_node(index)
+                && !scene.ids.contains_key(&index)
+                && !scene.indices.contains_key(&id)
+            {
+                scene.ids.insert(index, id);
+                scene.indices.insert(id, index);
+            }
+        }
+        // Nodes saved without an id, or with one already taken, are given a fresh one
+        let missing = scene
+            .graph
+            .node_indices()
+            .filter(|index| !scene.ids.contains_key(index))
+            .collect::<Vec<_>>();
+        for index in missing {
+            let id = NodeId::new_v4();
+            scene.ids.insert(index, id);
+            scene.indices.insert(id, index);
+        }
+        scene
+    }
+}
 
 // For data references in Node components, store the data offset into resource buffers
 #[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -19,6 +735,33 @@ pub enum Node {
     VisualInstance3D(VisualInstance3D),
 }
 
+// Walks up the parent chain looking for a main viewport, guarding against cycles
+fn is_registered_to_main_viewport(scene: &Scene, node: petgraph::graph::NodeIndex) -> bool {
+    let mut current = node;
+    for _ in 0..scene.node_count() {
+        let Some(parent) = scene
+            .neighbors_directed(current, petgraph::Direction::Incoming)
+            .next()
+        else {
+            return false;
+        };
+        if matches!(scene[parent], Node::Viewport(Viewport::Main { .. })) {
+            return true;
+        }
+        current = parent;
+    }
+    false
+}
+
+impl Node {
+    pub fn local_matrix(&self) -> nalgebra_glm::Mat4 {
+        match self {
+            Self::Node3D { transform, .. } => transform.matrix(),
+            _ => nalgebra_glm::Mat4::identity(),
+        }
+    }
+}
+
 #[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub enum Viewport {
     #[default]
@@ -47,6 +790,15 @@ pub enum Node3D {
     Camera3D {
         camera: Camera3D,
     },
+    DirectionalLight3D {
+        light: DirectionalLight3D,
+    },
+    OmniLight3D {
+        light: OmniLight3D,
+    },
+    SpotLight3D {
+        light: SpotLight3D,
+    },
 }
 
 #[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -60,10 +812,10 @@ pub enum VisualInstance3D {
 pub enum Geometry {
     #[default]
     Empty,
-    Label3D,                        // TODO: 3D text rendering
-    SpriteBase3D(SpriteBase3D),     // TODO: 2D sprites rendered in 3D world
-    MeshInstance3D(MeshInstance3D), // TODO: 3D meshes rendering
-    MultiMeshInstance3D,            // TODO: instanced 3D rendering
+    Label3D,                    // TODO: 3D text rendering
+    SpriteBase3D(SpriteBase3D), // TODO: 2D sprites rendered in 3D world
+    MeshInstance3D(MeshInstance3D),
+    MultiMeshInstance3D, // TODO: instanced 3D rendering
 }
 
 #[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -98,12 +850,439 @@ pub enum Mesh {
     /// Intended for a small amount of geometry that is expected to change frequently.
     // TODO: implement implement immediate mode meshes
     Immediate,
-    // TODO: implement array meshes
     // Used to construct a mesh from a set of vertices and indices
-    ArrayMesh,
+    ArrayMesh(ArrayMesh),
     PrimitiveMesh(PrimitiveMesh),
 }
 
+impl Mesh {
+    /// The surfaces to draw for this mesh, generating geometry for primitive meshes
+    pub fn surfaces(&self) -> Vec<Surface> {
+        match self {
+            Self::ArrayMesh(array_mesh) => array_mesh.surfaces.clone(),
+            Self::PrimitiveMesh(primitive_mesh) => vec![Surface {
+                material: primitive_mesh.material.clone(),
+                ..Surface::from(primitive_mesh.shape.geometry())
+            }],
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ArrayMesh {
+    pub surfaces: Vec<Surface>,
+}
+
+pub type MaterialId = String;
+pub type MaterialRegistry = std::collections::HashMap<MaterialId, Material>;
+
+// Material parameters follow the glTF 2.0 metallic-roughness model
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Material {
+    pub base_color_factor: [f32; 4],
+    pub base_color_texture: Option<TextureReference>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub metallic_roughness_texture: Option<TextureReference>,
+    pub normal_texture: Option<TextureReference>,
+    pub normal_scale: f32,
+    pub occlusion_texture: Option<TextureReference>,
+    pub occlusion_strength: f32,
+    pub emissive_factor: [f32; 3],
+    pub emissive_texture: Option<TextureReference>,
+    pub alpha_mode: AlphaMode,
+    pub double_sided: bool,
+    // Unlit materials ignore lighting and output the base color directly
+    pub unlit: bool,
+}
+
+impl Material {
+    /// Every texture the material samples
+    pub fn texture_references_mut(&mut self) -> impl Iterator<Item = &mut TextureReference> {
+        [
+            &mut self.base_color_texture,
+            &mut self.metallic_roughness_texture,
+            &mut self.normal_texture,
+            &mut self.occlusion_texture,
+            &mut self.emissive_texture,
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            base_color_factor: [1.0, 1.0, 1.0, 1.0],
+            base_color_texture: None,
+            metallic_factor: 1.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive_factor: [0.0, 0.0, 0.0],
+            emissive_texture: None,
+            alpha_mode: AlphaMode::default(),
+            double_sided: false,
+            unlit: false,
+        }
+    }
+}
+
+#[derive(Default, Debug, Copy, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum AlphaMode {
+    #[default]
+    Opaque,
+    Mask {
+        cutoff: f32,
+    },
+    Blend,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TextureReference {
+    pub texture: TextureId,
+    // Index of the UV set used to sample the texture
+    pub uv_set: u32,
+}
+
+pub type TextureId = String;
+pub type TextureRegistry = std::collections::HashMap<TextureId, Texture>;
+
+#[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Texture {
+    pub image: Image,
+    pub sampler: Sampler,
+}
+
+// Decoded pixels stored as tightly packed RGBA8 rows
+#[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+    /// How the source file says the color channels are encoded. Images that don't say are
+    /// sampled as sRGB or linear depending on how a material uses them.
+    #[serde(default)]
+    pub color_space: Option<crate::texture::ColorSpace>,
+}
+
+impl Image {
+    /// Loads a PNG, JPEG or KTX2 file, see `texture::decode_image`
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, crate::texture::TextureError> {
+        crate::texture::load_image(path)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, crate::texture::TextureError> {
+        crate::texture::decode_image(bytes)
+    }
+
+    pub fn load_png(path: impl AsRef<std::path::Path>) -> Result<Self, image::ImageError> {
+        let decoded = image::open(path)?.into_rgba8();
+        Ok(Self {
+            width: decoded.width(),
+            height: decoded.height(),
+            pixels: decoded.into_raw(),
+            color_space: None,
+        })
+    }
+
+    pub fn save_png(&self, path: impl AsRef<std::path::Path>) -> Result<(), image::ImageError> {
+        image::save_buffer_with_format(
+            path,
+            &self.pixels,
+            self.width,
+            self.height,
+            image::ExtendedColorType::Rgba8,
+            image::ImageFormat::Png,
+        )
+    }
+}
+
+#[derive(
+    Default, Debug, Copy, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub struct Sampler {
+    pub mag_filter: Filter,
+    pub min_filter: Filter,
+    pub mipmap_filter: Filter,
+    pub wrap_u: WrapMode,
+    pub wrap_v: WrapMode,
+}
+
+#[derive(
+    Default, Debug, Copy, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum Filter {
+    Nearest,
+    #[default]
+    Linear,
+}
+
+#[derive(
+    Default, Debug, Copy, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum WrapMode {
+    ClampToEdge,
+    MirroredRepeat,
+    #[default]
+    Repeat,
+}
+
+// A separate set of vertex arrays drawn with a single material
+#[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Surface {
+    pub attributes: VertexAttributes,
+    // Non-indexed surfaces draw their vertices in order
+    pub indices: Option<Indices>,
+    pub topology: PrimitiveTopology,
+    pub material: Option<MaterialId>,
+}
+
+impl Surface {
+    pub fn validate(&self) -> Result<(), MeshError> {
+        self.attributes.validate()?;
+        let vertex_count = self.attributes.vertex_count();
+        if let Some(index) = self
+            .indices
+            .as_ref()
+            .and_then(|indices| indices.iter().find(|index| *index as usize >= vertex_count))
+        {
+            return Err(MeshError::IndexOutOfBounds {
+                index,
+                vertex_count,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl From<crate::primitive::PrimitiveGeometry> for Surface {
+    fn from(geometry: crate::primitive::PrimitiveGeometry) -> Self {
+        Self {
+            attributes: VertexAttributes {
+                positions: geometry.positions,
+                normals: Some(geometry.normals),
+                uv0: Some(geometry.uvs),
+                ..Default::default()
+            },
+            indices: Some(Indices::U32(geometry.indices)),
+            topology: geometry.topology,
+            material: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+impl Indices {
+    pub fn len(&self) -> usize {
+        match self {
+            Self::U16(indices) => indices.len(),
+            Self::U32(indices) => indices.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = u32> + '_> {
+        match self {
+            Self::U16(indices) => Box::new(indices.iter().map(|index| *index as u32)),
+            Self::U32(indices) => Box::new(indices.iter().copied()),
+        }
+    }
+}
+
+// Each stream other than positions is optional and must hold one element per vertex
+#[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct VertexAttributes {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Option<Vec<[f32; 3]>>,
+    pub tangents: Option<Vec<[f32; 4]>>,
+    pub uv0: Option<Vec<[f32; 2]>>,
+    pub uv1: Option<Vec<[f32; 2]>>,
+    pub colors: Option<Vec<[f32; 4]>>,
+    pub joints: Option<Vec<[u16; 4]>>,
+    pub weights: Option<Vec<[f32; 4]>>,
+}
+
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub enum VertexAttribute {
+    Position,
+    Normal,
+    Tangent,
+    Uv0,
+    Uv1,
+    Color,
+    Joints,
+    Weights,
+}
+
+impl VertexAttribute {
+    pub const ALL: [Self; 8] = [
+        Self::Position,
+        Self::Normal,
+        Self::Tangent,
+        Self::Uv0,
+        Self::Uv1,
+        Self::Color,
+        Self::Joints,
+        Self::Weights,
+    ];
+
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Position | Self::Normal => 12,
+            Self::Tangent | Self::Color | Self::Weights => 16,
+            Self::Uv0 | Self::Uv1 | Self::Joints => 8,
+        }
+    }
+}
+
+impl VertexAttributes {
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// The attributes present in this surface, in interleaving order
+    pub fn layout(&self) -> Vec<VertexAttribute> {
+        VertexAttribute::ALL
+            .into_iter()
+            .filter(|attribute| self.stream(*attribute).is_some())
+            .collect()
+    }
+
+    pub fn stride(&self) -> usize {
+        self.layout().iter().map(VertexAttribute::size).sum()
+    }
+
+    pub fn validate(&self) -> Result<(), MeshError> {
+        let expected = self.vertex_count();
+        for attribute in self.layout() {
+            let found = self.stream(attribute).map(|stream| stream.len).unwrap_or(0);
+            if found != expected {
+                return Err(MeshError::AttributeLengthMismatch {
+                    attribute,
+                    expected,
+                    found,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Packs every present stream into a single interleaved vertex buffer following `layout`
+    pub fn interleave(&self) -> Vec<u8> {
+        let layout = self.layout();
+        let streams = layout
+            .iter()
+            .filter_map(|attribute| self.stream(*attribute))
+            .collect::<Vec<_>>();
+        let mut bytes = Vec::with_capacity(self.vertex_count() * self.stride());
+        for vertex in 0..self.vertex_count() {
+            for stream in streams.iter() {
+                let element_size = stream.bytes.len() / stream.len.max(1);
+                bytes.extend_from_slice(
+                    &stream.bytes[vertex * element_size..(vertex + 1) * element_size],
+                );
+            }
+        }
+        bytes
+    }
+
+    /// Raw little-endian bytes of a single attribute stream
+    pub fn stream_bytes(&self, attribute: VertexAttribute) -> Option<&[u8]> {
+        self.stream(attribute).map(|stream| stream.bytes)
+    }
+
+    /// Replaces an attribute stream with elements copied from raw little-endian bytes
+    pub fn set_stream_bytes(&mut self, attribute: VertexAttribute, bytes: &[u8]) {
+        match attribute {
+            VertexAttribute::Position => self.positions = bytemuck::pod_collect_to_vec(bytes),
+            VertexAttribute::Normal => self.normals = Some(bytemuck::pod_collect_to_vec(bytes)),
+            VertexAttribute::Tangent => self.tangents = Some(bytemuck::pod_collect_to_vec(bytes)),
+            VertexAttribute::Uv0 => self.uv0 = Some(bytemuck::pod_collect_to_vec(bytes)),
+            VertexAttribute::Uv1 => self.uv1 = Some(bytemuck::pod_collect_to_vec(bytes)),
+            VertexAttribute::Color => self.colors = Some(bytemuck::pod_collect_to_vec(bytes)),
+            VertexAttribute::Joints => self.joints = Some(bytemuck::pod_collect_to_vec(bytes)),
+            VertexAttribute::Weights => self.weights = Some(bytemuck::pod_collect_to_vec(bytes)),
+        }
+    }
+
+    fn stream(&self, attribute: VertexAttribute) -> Option<AttributeStream<'_>> {
+        fn stream<T: bytemuck::Pod>(elements: &[T]) -> AttributeStream<'_> {
+            AttributeStream {
+                bytes: bytemuck::cast_slice(elements),
+                len: elements.len(),
+            }
+        }
+        match attribute {
+            VertexAttribute::Position => Some(stream(&self.positions)),
+            VertexAttribute::Normal => self.normals.as_deref().map(stream),
+            VertexAttribute::Tangent => self.tangents.as_deref().map(stream),
+            VertexAttribute::Uv0 => self.uv0.as_deref().map(stream),
+            VertexAttribute::Uv1 => self.uv1.as_deref().map(stream),
+            VertexAttribute::Color => self.colors.as_deref().map(stream),
+            VertexAttribute::Joints => self.joints.as_deref().map(stream),
+            VertexAttribute::Weights => self.weights.as_deref().map(stream),
+        }
+    }
+}
+
+struct AttributeStream<'a> {
+    bytes: &'a [u8],
+    len: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MeshError {
+    AttributeLengthMismatch {
+        attribute: VertexAttribute,
+        expected: usize,
+        found: usize,
+    },
+    IndexOutOfBounds {
+        index: u32,
+        vertex_count: usize,
+    },
+}
+
+impl std::fmt::Display for MeshError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AttributeLengthMismatch {
+                attribute,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Vertex attribute {attribute:?} has {found} elements but the surface has {expected} vertices"
+            ),
+            Self::IndexOutOfBounds {
+                index,
+                vertex_count,
+            } => write!(
+                f,
+                "Index {index} is out of bounds for a surface with {vertex_count} vertices"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MeshError {}
+
 #[derive(Copy, Clone, Debug, serde::Serialize, serde::Deserialize, bytemuck::Zeroable)]
 pub struct Transform3D {
     pub translation: nalgebra_glm::Vec3,
@@ -185,6 +1364,15 @@ impl Transform3D {
     pub fn matrix(&self) -> nalgebra_glm::Mat4 {
         nalgebra_glm::Mat4::from(*self)
     }
+
+    /// Blends towards `other`, used with `Time::alpha` to smooth fixed-rate simulation states
+    pub fn interpolate(&self, other: &Self, alpha: f32) -> Self {
+        Self {
+            translation: nalgebra_glm::lerp(&self.translation, &other.translation, alpha),
+            rotation: nalgebra_glm::quat_slerp(&self.rotation, &other.rotation, alpha),
+            scale: nalgebra_glm::lerp(&self.scale, &other.scale, alpha),
+        }
+    }
 }
 
 #[derive(Default, Debug, serde::Serialize, serde::Deserialize, Clone)]
@@ -201,6 +1389,119 @@ impl Camera3D {
     }
 }
 
+/// Light arriving from a single direction everywhere, shining along the node's -Z axis
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct DirectionalLight3D {
+    /// Linear RGB
+    pub color: [f32; 3],
+    pub intensity: f32,
+    /// Casts shadows through cascaded shadow maps fit to the active camera when set
+    #[serde(default)]
+    pub shadow: Option<Shadow>,
+    #[serde(default)]
+    pub cascades: ShadowCascades,
+}
+
+impl Default for DirectionalLight3D {
+    fn default() -> Self {
+        Self {
+            color: [1.0; 3],
+            intensity: 1.0,
+            shadow: None,
+            cascades: ShadowCascades::default(),
+        }
+    }
+}
+
+/// How a light's shadow map is sampled. Biases are in shadow map texels, so they scale with
+/// the world size a texel covers and rarely need tuning per scene.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq)]
+pub struct Shadow {
+    /// Moves the receiving point towards the light, against acne on surfaces facing it
+    pub depth_bias: f32,
+    /// Moves the receiving point along its normal, against acne at grazing angles
+    pub normal_bias: f32,
+}
+
+impl Default for Shadow {
+    fn default() -> Self {
+        Self {
+            depth_bias: 1.0,
+            normal_bias: 1.5,
+        }
+    }
+}
+
+/// Splits the camera's view depth into shadow maps of increasing size, so nearby shadows
+/// get more texels than distant ones
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone, Copy, PartialEq)]
+pub struct ShadowCascades {
+    /// Between 1 and 4
+    pub count: u32,
+    /// View depth past which nothing receives shadows, the camera's far plane if closer
+    pub max_distance: f32,
+    /// Blends split depths from evenly spaced at 0 to logarithmically spaced at 1
+    pub split_blend: f32,
+}
+
+impl Default for ShadowCascades {
+    fn default() -> Self {
+        Self {
+            count: 4,
+            max_distance: 50.0,
+            split_blend: 0.75,
+        }
+    }
+}
+
+/// Light radiating in all directions from the node's position, falling off with the inverse
+/// square of the distance and reaching zero at `range`
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct OmniLight3D {
+    /// Linear RGB
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub range: f32,
+}
+
+impl Default for OmniLight3D {
+    fn default() -> Self {
+        Self {
+            color: [1.0; 3],
+            intensity: 1.0,
+            range: 10.0,
+        }
+    }
+}
+
+/// An omni light restricted to a cone around the node's -Z axis. The cone angles are half
+/// angles, the light fades out between the inner and the outer one.
+#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
+pub struct SpotLight3D {
+    /// Linear RGB
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub range: f32,
+    pub inner_cone_angle_rad: f32,
+    pub outer_cone_angle_rad: f32,
+    /// Casts shadows through a shadow map covering the outer cone when set
+    #[serde(default)]
+    pub shadow: Option<Shadow>,
+}
+
+impl Default for SpotLight3D {
+    fn default() -> Self {
+        Self {
+            color: [1.0; 3],
+            intensity: 1.0,
+            range: 10.0,
+            inner_cone_angle_rad: 0.0,
+            outer_cone_angle_rad: std::f32::consts::FRAC_PI_4,
+            shadow: None,
+        }
+    }
+}
+
 #[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
 pub enum Projection {
     Perspective(PerspectiveCamera),
@@ -240,7 +1541,7 @@ impl PerspectiveCamera {
         };
 
         if let Some(z_far) = self.z_far {
-            nalgebra_glm::perspective_zo(aspect_ratio, self.y_fov_rad, self.z_near, z_far)
+            nalgebra_glm::perspective_rh_zo(aspect_ratio, self.y_fov_rad, self.z_near, z_far)
         } else {
             nalgebra_glm::infinite_perspective_rh_zo(aspect_ratio, self.y_fov_rad, self.z_near)
         }
@@ -257,25 +1558,13 @@ pub struct OrthographicCamera {
 
 impl OrthographicCamera {
     pub fn matrix(&self) -> nalgebra_glm::Mat4 {
-        let z_sum = self.z_near + self.z_far;
-        let z_diff = self.z_near - self.z_far;
-        nalgebra_glm::Mat4::new(
-            1.0 / self.x_mag,
-            0.0,
-            0.0,
-            0.0,
-            0.0,
-            1.0 / self.y_mag,
-            0.0,
-            0.0,
-            0.0,
-            0.0,
-            2.0 / z_diff,
-            0.0,
-            0.0,
-            0.0,
-            z_sum / z_diff,
-            1.0,
+        nalgebra_glm::ortho_rh_zo(
+            -self.x_mag,
+            self.x_mag,
+            -self.y_mag,
+            self.y_mag,
+            self.z_near,
+            self.z_far,
         )
     }
 }
@@ -368,16 +1657,359 @@ impl Default for Orientation {
 #[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct PrimitiveMesh {
     pub shape: PrimitiveShape,
+    #[serde(default)]
+    pub material: Option<MaterialId>,
 }
 
 #[derive(Default, Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub enum PrimitiveShape {
     #[default]
     Triangle,
-    Box,
-    Capsule,
-    Cylinder,
-    Plane,
+    Box(BoxShape),
+    Capsule(CapsuleShape),
+    Cylinder(CylinderShape),
+    Plane(PlaneShape),
     Point,
-    Sphere,
+    Sphere(SphereShape),
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct BoxShape {
+    pub size: nalgebra_glm::Vec3,
+    // Number of extra cuts across each face, 0 produces a single quad per face
+    pub subdivisions: u32,
+}
+
+impl Default for BoxShape {
+    fn default() -> Self {
+        Self {
+            size: nalgebra_glm::vec3(1.0, 1.0, 1.0),
+            subdivisions: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CapsuleShape {
+    pub radius: f32,
+    // Total height, including both hemispheres
+    pub height: f32,
+    pub segments: u32,
+    // Rings per hemisphere
+    pub rings: u32,
+}
+
+impl Default for CapsuleShape {
+    fn default() -> Self {
+        Self {
+            radius: 0.5,
+            height: 2.0,
+            segments: 64,
+            rings: 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct CylinderShape {
+    pub top_radius: f32,
+    pub bottom_radius: f32,
+    pub height: f32,
+    pub segments: u32,
+    pub rings: u32,
+    pub cap_top: bool,
+    pub cap_bottom: bool,
+}
+
+impl Default for CylinderShape {
+    fn default() -> Self {
+        Self {
+            top_radius: 0.5,
+            bottom_radius: 0.5,
+            height: 2.0,
+            segments: 64,
+            rings: 4,
+            cap_top: true,
+            cap_bottom: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PlaneShape {
+    pub size: nalgebra_glm::Vec2,
+    // Number of extra cuts along each axis, 0 produces a single quad
+    pub subdivisions: u32,
+}
+
+impl Default for PlaneShape {
+    fn default() -> Self {
+        Self {
+            size: nalgebra_glm::vec2(2.0, 2.0),
+            subdivisions: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SphereShape {
+    pub radius: f32,
+    pub segments: u32,
+    pub rings: u32,
+}
+
+impl Default for SphereShape {
+    fn default() -> Self {
+        Self {
+            radius: 0.5,
+            segments: 64,
+            rings: 32,
+        }
+    }
+}
+
+#[derive(
+    Default, Debug, Copy, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum PrimitiveTopology {
+    PointList,
+    LineList,
+    LineStrip,
+    #[default]
+    TriangleList,
+    TriangleStrip,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A scene instancing one mesh, whose material samples one texture, all named `name`
+    fn textured_world(name: &str, width: u32) -> World {
+        let mut world = World::default();
+        let mut scene = Scene::default();
+        scene.add_node(Node::VisualInstance3D(VisualInstance3D::Geometry(
+            Geometry::MeshInstance3D(MeshInstance3D {
+                mesh_reference: Some(name.to_string()),
+            }),
+        )));
+        world.scenes.push(scene);
+        world.meshes.insert(
+            name.to_string(),
+            Mesh::PrimitiveMesh(PrimitiveMesh {
+                shape: PrimitiveShape::Box(BoxShape::default()),
+                material: Some(name.to_string()),
+            }),
+        );
+        world.materials.insert(
+            name.to_string(),
+            Material {
+                base_color_texture: Some(TextureReference {
+                    texture: name.to_string(),
+                    uv_set: 0,
+                }),
+                emissive_texture: Some(TextureReference {
+                    texture: name.to_string(),
+                    uv_set: 1,
+                }),
+                ..Default::default()
+            },
+        );
+        world.textures.insert(
+            name.to_string(),
+            Texture {
+                image: Image {
+                    width,
+                    height: 1,
+                    pixels: vec![255; width as usize * 4],
+                    color_space: None,
+                },
+                sampler: Sampler::default(),
+            },
+        );
+        world
+    }
+
+    fn mesh_reference(scene: &Scene) -> Option<&str> {
+        scene.node_weights().find_map(|node| match node {
+            Node::VisualInstance3D(VisualInstance3D::Geometry(Geometry::MeshInstance3D(
+                instance,
+            ))) => instance.mesh_reference.as_deref(),
+            _ => None,
+        })
+    }
+
+    #[test]
+    fn merge_renames_colliding_resources() {
+        let mut world = textured_world("crate", 1);
+        // Already taken, so the first rename has to skip it
+        world
+            .meshes
+            .insert("crate_1".to_string(), Mesh::Placeholder);
+        let scenes = world.merge(textured_world("crate", 2));
+        assert_eq!(scenes, 1..2);
+
+        // Existing resources are untouched
+        assert_eq!(mesh_reference(&world.scenes[0]), Some("crate"));
+        assert_eq!(world.textures["crate"].image.width, 1);
+        assert!(matches!(world.meshes["crate_1"], Mesh::Placeholder));
+
+        assert_eq!(mesh_reference(&world.scenes[1]), Some("crate_2"));
+        let Mesh::PrimitiveMesh(mesh) = &world.meshes["crate_2"] else {
+            panic!("merged mesh is missing");
+        };
+        assert_eq!(mesh.material.as_deref(), Some("crate_1"));
+        let material = &world.materials["crate_1"];
+        assert_eq!(
+            material.base_color_texture.as_ref().unwrap().texture,
+            "crate_1"
+        );
+        assert_eq!(
+            material.emissive_texture.as_ref().unwrap().texture,
+            "crate_1"
+        );
+        assert_eq!(world.textures["crate_1"].image.width, 2);
+        assert_eq!(world.meshes.len(), 3);
+        assert_eq!(world.materials.len(), 2);
+        assert_eq!(world.textures.len(), 2);
+    }
+
+    #[test]
+    fn merge_keeps_unique_resource_ids() {
+        let mut world = textured_world("crate", 1);
+        world.merge(textured_world("barrel", 2));
+        assert_eq!(mesh_reference(&world.scenes[1]), Some("barrel"));
+        assert_eq!(
+            world.materials["barrel"]
+                .base_color_texture
+                .as_ref()
+                .unwrap()
+                .texture,
+            "barrel"
+        );
+        assert_eq!(world.textures["barrel"].image.width, 2);
+    }
+
+    // Spawns nodes under the given parents, returning their ids in order
+    fn spawn_tree(world: &mut World, parents: &[Option<usize>]) -> Vec<NodeId> {
+        world.scenes.push(Scene::default());
+        let mut ids = Vec::new();
+        for parent in parents {
+            let id = NodeId::new_v4();
+            world
+                .spawn_node(0, id, Node::Empty, parent.map(|parent| ids[parent]))
+                .unwrap();
+            ids.push(id);
+        }
+        ids
+    }
+
+    #[test]
+    fn despawning_removes_the_subtree() {
+        let mut world = World::default();
+        let ids = spawn_tree(&mut world, &[None, Some(0), Some(1), Some(0), None]);
+        world.despawn_node(ids[1]).unwrap();
+        let remaining = [0, 3, 4].map(|node| world.find_node(ids[node]).is_some());
+        assert_eq!(remaining, [true; 3]);
+        assert!(world.find_node(ids[1]).is_none());
+        assert!(world.find_node(ids[2]).is_none());
+        assert_eq!(world.scenes[0].node_count(), 3);
+    }
+
+    #[test]
+    fn despawning_a_shared_descendant_is_rejected() {
+        let mut world = World::default();
+        let ids = spawn_tree(&mut world, &[None, Some(0), None, Some(1)]);
+        // The grandchild is also a child of the second root
+        let scene = &mut world.scenes[0];
+        let (root, grandchild) = (
+            scene.node_index(ids[2]).unwrap(),
+            scene.node_index(ids[3]).unwrap(),
+        );
+        scene.add_edge(root, grandchild);
+
+        assert_eq!(
+            world.despawn_node(ids[0]),
+            Err(SceneError::SharedDescendant {
+                node: ids[0],
+                descendant: ids[3],
+            })
+        );
+        assert_eq!(world.scenes[0].node_count(), 4);
+        // Nor can the other parent take it along
+        assert!(world.despawn_node(ids[2]).is_err());
+        // Once it is only in one subtree it goes with that one
+        world.scenes[0].remove_edge(root, grandchild);
+        world.despawn_node(ids[0]).unwrap();
+        assert_eq!(world.scenes[0].node_count(), 1);
+    }
+
+    #[test]
+    fn loaded_scenes_give_repeated_ids_fresh_ones() {
+        let mut graph = SceneGraph::default();
+        let nodes = [(); 3].map(|_| graph.add_node(Node::Empty));
+        let (first, second, third) = (NodeId::new_v4(), NodeId::new_v4(), NodeId::new_v4());
+        let scene = Scene::from(SceneData {
+            graph,
+            ids: vec![(0, first), (1, first), (2, second), (2, third)],
+        });
+
+        assert_eq!(scene.node_id(nodes[0]), Some(first));
+        assert_eq!(scene.node_id(nodes[2]), Some(second));
+        let fresh = scene.node_id(nodes[1]).unwrap();
+        assert!(![first, second, third].contains(&fresh));
+        assert_eq!(scene.node_index(first), Some(nodes[0]));
+        assert_eq!(scene.node_index(fresh), Some(nodes[1]));
+        assert_eq!(scene.node_index(third), None);
+        assert_eq!(scene.ids.len(), 3);
+        assert_eq!(scene.indices.len(), 3);
+    }
+
+    #[test]
+    fn merge_gives_colliding_nodes_fresh_ids() {
+        let mut world = textured_world("crate", 1);
+        let copy = world.clone();
+        let node = world.scenes[0].node_indices().next().unwrap();
+        let id = world.scenes[0].node_id(node).unwrap();
+        world.merge(copy);
+        assert_eq!(world.find_node(id), Some((0, node)));
+        assert_eq!(world.scenes[1].node_count(), 1);
+        assert!(world.scenes[1].node_index(id).is_none());
+    }
+
+    #[test]
+    fn mesh_edits_bump_revisions() {
+        let mut world = textured_world("crate", 1);
+        let loaded = world.revisions.mesh("crate");
+        // Another world loading the same meshes must not look up to date
+        assert_ne!(textured_world("crate", 1).revisions.mesh("crate"), loaded);
+
+        world.mesh_mut("crate");
+        let edited = world.revisions.mesh("crate");
+        assert_ne!(edited, loaded);
+        world.insert_mesh("crate", Mesh::Placeholder);
+        assert_ne!(world.revisions.mesh("crate"), edited);
+        let replaced = world.revisions.mesh("crate");
+        world.mark_mesh_changed("crate");
+        assert_ne!(world.revisions.mesh("crate"), replaced);
+
+        // Merged meshes can reuse the id of one that was removed before the renderer saw it
+        let removed = world.revisions.mesh("crate");
+        world.meshes.remove("crate");
+        world.merge(textured_world("crate", 1));
+        assert_ne!(world.revisions.mesh("crate"), removed);
+    }
+
+    #[test]
+    fn texture_edits_bump_revisions() {
+        let mut world = textured_world("crate", 1);
+        let loaded = world.revisions.texture("crate");
+        world.texture_mut("crate").unwrap().image.width = 2;
+        let edited = world.revisions.texture("crate");
+        assert_ne!(edited, loaded);
+        world.insert_texture("crate", Texture::default());
+        assert_ne!(world.revisions.texture("crate"), edited);
+        // Meshes and textures are tracked separately
+        assert_eq!(world.revisions.mesh("crate"), loaded);
+    }
 }
@@ -0,0 +1,50 @@
+// Renders every reference scene offscreen and compares it against `crates/engine/golden`.
+//
+//     cargo test -p engine --test golden                  check all scenes
+//     GOLDEN_BLESS=1 cargo test -p engine --test golden   regenerate the references
+//     GOLDEN_FILTER=box cargo test -p engine --test golden  only scenes whose name contains "box"
+//
+// The references were rendered by Mesa's llvmpipe through the GL backend, run with
+// `WGPU_BACKEND=gl` so other software adapters aren't picked. Machines without any software
+// adapter skip the check, unless `CI` or `GOLDEN_REQUIRE` is set.
+#[test]
+fn reference_scenes_match() {
+    let _ = env_logger::builder().is_test(true).try_init();
+
+    let harness = engine::golden::GoldenHarness {
+        bless: std::env::var_os("GOLDEN_BLESS").is_some(),
+        ..Default::default()
+    };
+    if !harness.adapter_available() {
+        // A job meant to check the references must not pass without rendering any
+        let required = ["CI", "GOLDEN_REQUIRE"]
+            .iter()
+            .any(|variable| std::env::var_os(variable).is_some());
+        assert!(
+            !required,
+            "no software adapter is available to render the references"
+        );
+        eprintln!("skipping golden image checks, no software adapter is available");
+        return;
+    }
+    let filter = std::env::var("GOLDEN_FILTER").unwrap_or_default();
+
+    let mut failures = Vec::new();
+    for (name, mut world) in engine::golden::reference_scenes() {
+        if !name.contains(&filter) {
+            continue;
+        }
+        match harness.check(&name, &mut world) {
+            Ok(()) if harness.bless => println!("blessed {name}"),
+            Ok(()) => println!("ok      {name}"),
+            Err(error) => failures.push(format!("{name}: {error}")),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} golden image check(s) failed:\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}